@@ -1,25 +1,27 @@
+use rand::Rng;
+
 use super::hash_table::HashTable;
 use crate::Entry;
 
 #[derive(Debug)]
-pub struct Dict {
-    primary: Box<HashTable>,
-    secondary: Box<HashTable>,
+pub struct Dict<V = String> {
+    primary: Box<HashTable<V>>,
+    secondary: Box<HashTable<V>>,
     migrate_pos: isize,
 }
 
-impl Default for Dict {
+impl<V> Default for Dict<V> {
     fn default() -> Self {
         let primary = Box::new(HashTable::default());
         Self {
-            primary: primary,
+            primary,
             secondary: Box::new(HashTable::EMPTY_TABLE),
             migrate_pos: -1,
         }
     }
 }
 
-impl Dict {
+impl<V> Dict<V> {
     /// Constant to figure out how many items could be stored in a bucket at max
     pub const MAX_ENTRIES_PER_BUCKET: usize = 2;
 
@@ -27,17 +29,26 @@ impl Dict {
     /// during one migration. 
     pub const MAX_REHASH_OPS: usize = 2;
 
+    /// Number of random buckets `random_entry` tries before walking the dict
+    pub const MAX_RANDOM_TRIES: usize = 64;
+
     pub fn size(&self) -> usize {
         self.primary.items + self.secondary.items
     }
 
-    pub fn insert(&mut self, key: &str, value: &str) -> Option<String> {
+    pub fn insert(&mut self, key: &str, value: V) -> Option<V> {
         assert_ne!(self.primary.bucket_count(), 0, "inserting into empty dict");
 
-        let old = self.primary.insert(Entry {
-            key: key.into(),
-            value: value.into(),
-        });
+        // a key that wasn't migrated yet would otherwise be kept twice,
+        // and the stale copy would win once it gets migrated
+        let stale = self.secondary.remove(key).map(Entry::into_value);
+        let old = self
+            .primary
+            .insert(Entry {
+                key: key.into(),
+                value,
+            })
+            .or(stale);
         // trigger the rehash only if the load factor is exceeded
         // AND we are not finished with the previous migration
         if self.primary.bucket_count() * Self::MAX_ENTRIES_PER_BUCKET < self.primary.items
@@ -49,25 +60,157 @@ impl Dict {
         old
     }
 
-    pub fn get(&mut self, key: &str) -> Option<&Entry> {
+    pub fn get(&mut self, key: &str) -> Option<&Entry<V>> {
         self.migrate();
         self.primary.get(key).or_else(|| self.secondary.get(key))
     }
 
-    pub fn get_mut(&mut self, key: &str) -> Option<&mut Entry> {
+    /// Looks up `key` without advancing the migration, for callers
+    /// that only hold a shared reference
+    pub fn peek(&self, key: &str) -> Option<&Entry<V>> {
+        self.primary.get(key).or_else(|| self.secondary.get(key))
+    }
+
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut Entry<V>> {
         self.migrate();
         self.primary
             .get_mut(key)
             .or_else(|| self.secondary.get_mut(key))
     }
 
-    pub fn remove(&mut self, key: &str) -> Option<Entry> {
+    pub fn remove(&mut self, key: &str) -> Option<Entry<V>> {
         self.migrate();
         self.primary
             .remove(key)
             .or_else(|| self.secondary.remove(key))
     }
 
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.primary.contains_key(key) || self.secondary.contains_key(key)
+    }
+
+    /// Iterates over every entry, including the ones
+    /// still waiting to be migrated out of the secondary table
+    pub fn iter(&self) -> impl Iterator<Item = &Entry<V>> {
+        self.primary.iter().chain(self.secondary.iter())
+    }
+
+    /// Returns the number of buckets in the primary and secondary tables
+    pub fn bucket_counts(&self) -> (usize, usize) {
+        (self.primary.bucket_count(), self.secondary.bucket_count())
+    }
+
+    /// Load factor of the primary table
+    pub fn load_factor(&self) -> usize {
+        self.primary.load_factor()
+    }
+
+    /// Whether a migration from the secondary table is in progress
+    pub fn is_rehashing(&self) -> bool {
+        self.migrate_pos != -1
+    }
+
+    /// Returns up to `count` entries, collected by walking the buckets
+    /// of both tables from a random position.
+    ///
+    /// This is cheap, but entries sharing a neighbourhood are picked together,
+    /// so it is only meant for approximate algorithms like eviction.
+    pub fn sample<R: Rng + ?Sized>(&self, count: usize, rng: &mut R) -> Vec<&Entry<V>> {
+        let total = self.primary.bucket_count() + self.secondary.bucket_count();
+        if self.size() == 0 || count == 0 {
+            return Vec::new();
+        }
+
+        let mut out = Vec::with_capacity(count);
+        let mut idx = rng.random_range(0..total);
+        // don't scan the whole table for sparse dicts,
+        // unless we haven't found anything yet
+        let max_steps = count * 10;
+
+        for step in 0..total {
+            if step >= max_steps && !out.is_empty() {
+                break;
+            }
+            let bucket = match idx.checked_sub(self.primary.bucket_count()) {
+                None => &self.primary.buckets[idx],
+                Some(i) => &self.secondary.buckets[i],
+            };
+            for entry in bucket {
+                out.push(entry);
+                if out.len() == count {
+                    return out;
+                }
+            }
+            idx = (idx + 1) % total;
+        }
+        out
+    }
+
+    /// Returns an entry picked uniformly at random, from either table.
+    ///
+    /// Picks a random bucket and a random position up to the longest chain,
+    /// retrying if the bucket is shorter than that, so every entry is just as
+    /// likely to be picked. Sparse tables fall back to a linear walk after
+    /// `MAX_RANDOM_TRIES` misses.
+    pub fn random_entry<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<&Entry<V>> {
+        let size = self.size();
+        if size == 0 {
+            return None;
+        }
+
+        let primary = self.primary.bucket_count();
+        let total = primary + self.secondary.bucket_count();
+        let chain = self.primary.max_chain.max(self.secondary.max_chain);
+        for _ in 0..Self::MAX_RANDOM_TRIES {
+            let idx = rng.random_range(0..total);
+            let bucket = match idx.checked_sub(primary) {
+                None => &self.primary.buckets[idx],
+                Some(i) => &self.secondary.buckets[i],
+            };
+            if let Some(entry) = bucket.iter().nth(rng.random_range(0..chain)) {
+                return Some(entry);
+            }
+        }
+        self.iter().nth(rng.random_range(0..size))
+    }
+
+    /// Visits the entries of the buckets at `cursor`, returning the cursor
+    /// to continue from, or 0 once the whole dict was scanned.
+    ///
+    /// The cursor is advanced with its bits reversed, so every entry that is
+    /// present for the whole scan gets visited at least once, even if the
+    /// dict grows or a migration starts or finishes in between calls.
+    /// Entries may be visited more than once.
+    pub fn scan(&self, mut cursor: usize, mut f: impl FnMut(&Entry<V>)) -> usize {
+        if self.size() == 0 {
+            return 0;
+        }
+
+        if self.secondary.bucket_count() == 0 {
+            let mask = self.primary.mask;
+            self.primary.buckets[cursor & mask].iter().for_each(&mut f);
+            return next_cursor(cursor, mask);
+        }
+
+        // visit the bucket in the smaller table, then every bucket
+        // of the larger one that it expands into
+        let (small, large) = if self.primary.bucket_count() <= self.secondary.bucket_count() {
+            (&self.primary, &self.secondary)
+        } else {
+            (&self.secondary, &self.primary)
+        };
+        let (m0, m1) = (small.mask, large.mask);
+
+        small.buckets[cursor & m0].iter().for_each(&mut f);
+        loop {
+            large.buckets[cursor & m1].iter().for_each(&mut f);
+            cursor = next_cursor(cursor, m1);
+            if cursor & (m0 ^ m1) == 0 {
+                return cursor;
+            }
+        }
+    }
+
     // [private]
 
     fn trigger_migration(&mut self) {
@@ -120,7 +263,7 @@ impl Dict {
 
         if self.secondary.is_empty() || (self.migrate_pos as usize) == self.secondary.bucket_count()
         {
-            self.secondary = Box::new(HashTable::EMPTY_TABLE);
+            *self.secondary = HashTable::EMPTY_TABLE;
             self.migrate_pos = -1;
 
             // println!("migration finished, empty out secondary table")
@@ -134,34 +277,44 @@ impl Dict {
     }
 }
 
+/// Increments the bits of `cursor` covered by `mask` in reverse order
+fn next_cursor(mut cursor: usize, mask: usize) -> usize {
+    cursor |= !mask;
+    cursor = cursor.reverse_bits();
+    cursor = cursor.wrapping_add(1);
+    cursor.reverse_bits()
+}
+
 #[cfg(test)]
 mod test {
+    use std::collections::HashSet;
+
     use crate::Dict;
 
     #[test]
     fn insert() {
-        let mut d = Dict::default();
-        let old = d.insert("hi", "baby");
+        let mut d = Dict::<String>::default();
+        let old = d.insert("hi", "baby".to_string());
         assert!(old.is_none());
         assert_eq!(d.size(), 1);
 
-        let old = d.insert("hi", "something else");
+        let old = d.insert("hi", "something else".to_string());
         assert_eq!(old.unwrap(), "baby");
         assert_eq!(d.size(), 1);
 
-        let old = d.insert("hello", "yellow");
+        let old = d.insert("hello", "yellow".to_string());
         assert!(old.is_none());
         assert_eq!(d.size(), 2);
     }
 
     #[test]
     fn it_works_big_time() {
-        let mut d = Dict::default();
+        let mut d = Dict::<String>::default();
 
         let strs: Vec<String> = (0..18).map(|i| format!("{i}")).collect();
 
         for x in strs {
-            d.insert(&x, &x);
+            d.insert(&x, x.clone());
         }
         dbg!(&d.primary.load_factor(), &d);
 
@@ -178,4 +331,107 @@ mod test {
 
         dbg!(&d.primary.load_factor(), &d);
     }
+
+    #[test]
+    fn overwrite_while_migrating() {
+        let mut d = Dict::<usize>::default();
+        let mut i = 0;
+        while !d.is_rehashing() {
+            d.insert(&format!("{i}"), i);
+            i += 1;
+        }
+        assert!(!d.secondary.is_empty());
+
+        // every key still in the secondary table gets a new value
+        let stale: Vec<String> = d.secondary.iter().map(|e| e.key.clone()).collect();
+        for key in &stale {
+            let n: usize = key.parse().unwrap();
+            assert_eq!(d.insert(key, n + 100), Some(n));
+        }
+        assert_eq!(d.size(), i);
+
+        while d.is_rehashing() {
+            d.get("");
+        }
+        assert_eq!(d.size(), i);
+        for key in &stale {
+            let n: usize = key.parse().unwrap();
+            assert_eq!(d.get(key).map(|e| *e.value()), Some(n + 100));
+        }
+    }
+
+    #[test]
+    fn remove() {
+        let mut d = Dict::<usize>::default();
+        for i in 0..20 {
+            d.insert(&format!("{i}"), i);
+        }
+
+        for i in 0..20 {
+            assert_eq!(d.remove(&format!("{i}")).map(|e| e.into_value()), Some(i));
+        }
+        assert!(d.remove("0").is_none());
+        assert_eq!(d.size(), 0);
+    }
+
+    #[test]
+    fn scan_survives_resizing() {
+        let mut d = Dict::<usize>::default();
+        for i in 0..10 {
+            d.insert(&format!("{i}"), i);
+        }
+
+        let mut seen = HashSet::new();
+        let mut cursor = 0;
+        let mut calls = 0;
+        loop {
+            cursor = d.scan(cursor, |e| {
+                seen.insert(*e.value());
+            });
+            // grow the dict mid scan, so the cursor crosses migrations
+            for i in 0..10 {
+                d.insert(&format!("new:{calls}:{i}"), 1000);
+            }
+            calls += 1;
+            if cursor == 0 {
+                break;
+            }
+        }
+
+        assert!((0..10).all(|i| seen.contains(&i)));
+    }
+
+    #[test]
+    fn random_entry_is_uniform() {
+        let mut rng = rand::rng();
+        let mut d = Dict::<usize>::default();
+        assert!(d.random_entry(&mut rng).is_none());
+
+        // stop in the middle of a migration
+        for i in 0..17 {
+            d.insert(&format!("{i}"), i);
+        }
+        assert!(d.is_rehashing());
+
+        let mut hits = [0; 17];
+        for _ in 0..17_000 {
+            hits[*d.random_entry(&mut rng).unwrap().value()] += 1;
+        }
+        assert!(hits.iter().all(|&h| (700..1300).contains(&h)), "{hits:?}");
+    }
+
+    #[test]
+    fn sample() {
+        let mut rng = rand::rng();
+        let mut d = Dict::<usize>::default();
+        assert!(d.sample(5, &mut rng).is_empty());
+
+        for i in 0..100 {
+            d.insert(&format!("{i}"), i);
+        }
+
+        let sampled = d.sample(5, &mut rng);
+        assert_eq!(sampled.len(), 5);
+        assert!(sampled.iter().all(|e| e.value().to_string() == e.key()));
+    }
 }
@@ -0,0 +1,246 @@
+use rand::Rng;
+
+use crate::Dict;
+
+/// A map of fields to byte string values, used for hashes.
+///
+/// Small maps are kept as a flat list of pairs, searched linearly,
+/// which is both smaller and faster than hashing for a handful of fields.
+/// Once the map gets more than `MAX_COMPACT_LEN` fields, or a field or value
+/// longer than `MAX_COMPACT_VALUE`, it is converted to a `Dict` for good.
+#[derive(Debug)]
+pub struct FieldMap {
+    encoding: Encoding,
+    /// Bytes used by the fields and values, kept up to date on every change
+    bytes: usize,
+}
+
+#[derive(Debug)]
+enum Encoding {
+    Compact(Vec<(String, Vec<u8>)>),
+    Dict(Dict<Vec<u8>>),
+}
+
+impl Default for FieldMap {
+    fn default() -> Self {
+        Self {
+            encoding: Encoding::Compact(Vec::new()),
+            bytes: 0,
+        }
+    }
+}
+
+impl FieldMap {
+    /// Number of fields a compact map can hold
+    pub const MAX_COMPACT_LEN: usize = 128;
+
+    /// Length of the longest field or value a compact map can hold
+    pub const MAX_COMPACT_VALUE: usize = 64;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        match &self.encoding {
+            Encoding::Compact(pairs) => pairs.len(),
+            Encoding::Dict(dict) => dict.size(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether the map still uses the compact encoding
+    pub fn is_compact(&self) -> bool {
+        matches!(self.encoding, Encoding::Compact(_))
+    }
+
+    /// Approximate number of heap bytes used by the map
+    pub fn mem_usage(&self) -> usize {
+        let overhead = match &self.encoding {
+            Encoding::Compact(pairs) => pairs.capacity() * size_of::<(String, Vec<u8>)>(),
+            Encoding::Dict(dict) => {
+                let (primary, secondary) = dict.bucket_counts();
+                (primary + secondary) * size_of::<usize>() * 2
+                    + dict.size() * size_of::<(String, Vec<u8>)>()
+            }
+        };
+        overhead + self.bytes
+    }
+
+    pub fn get(&self, field: &str) -> Option<&[u8]> {
+        match &self.encoding {
+            Encoding::Compact(pairs) => pairs
+                .iter()
+                .find(|(f, _)| f == field)
+                .map(|(_, v)| v.as_slice()),
+            Encoding::Dict(dict) => dict.peek(field).map(|e| e.value().as_slice()),
+        }
+    }
+
+    pub fn contains_key(&self, field: &str) -> bool {
+        self.get(field).is_some()
+    }
+
+    /// Sets `field` to `value`, returning the previous value
+    pub fn insert(&mut self, field: &str, value: Vec<u8>) -> Option<Vec<u8>> {
+        if field.len() > Self::MAX_COMPACT_VALUE || value.len() > Self::MAX_COMPACT_VALUE {
+            self.convert();
+        }
+
+        self.bytes += field.len() + value.len();
+        let old = match &mut self.encoding {
+            Encoding::Compact(pairs) => match pairs.iter_mut().find(|(f, _)| f == field) {
+                Some((_, v)) => Some(std::mem::replace(v, value)),
+                None => {
+                    pairs.push((field.to_string(), value));
+                    None
+                }
+            },
+            Encoding::Dict(dict) => dict.insert(field, value),
+        };
+
+        match &old {
+            Some(old) => self.bytes -= field.len() + old.len(),
+            None if self.len() > Self::MAX_COMPACT_LEN => self.convert(),
+            None => {}
+        }
+        old
+    }
+
+    pub fn remove(&mut self, field: &str) -> Option<Vec<u8>> {
+        let value = match &mut self.encoding {
+            Encoding::Compact(pairs) => {
+                let idx = pairs.iter().position(|(f, _)| f == field)?;
+                pairs.swap_remove(idx).1
+            }
+            Encoding::Dict(dict) => dict.remove(field)?.into_value(),
+        };
+        self.bytes -= field.len() + value.len();
+        Some(value)
+    }
+
+    pub fn iter(&self) -> Box<dyn Iterator<Item = (&str, &[u8])> + '_> {
+        match &self.encoding {
+            Encoding::Compact(pairs) => {
+                Box::new(pairs.iter().map(|(f, v)| (f.as_str(), v.as_slice())))
+            }
+            Encoding::Dict(dict) => Box::new(dict.iter().map(|e| (e.key(), e.value().as_slice()))),
+        }
+    }
+
+    /// Returns a field and its value picked uniformly at random
+    pub fn random<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<(&str, &[u8])> {
+        match &self.encoding {
+            Encoding::Compact(pairs) if pairs.is_empty() => None,
+            Encoding::Compact(pairs) => {
+                let (field, value) = &pairs[rng.random_range(0..pairs.len())];
+                Some((field, value))
+            }
+            Encoding::Dict(dict) => dict
+                .random_entry(rng)
+                .map(|e| (e.key(), e.value().as_slice())),
+        }
+    }
+
+    /// Visits the fields at `cursor`, returning the cursor to continue from,
+    /// 0 once done. Compact maps are visited in one go, see `Dict::scan`
+    pub fn scan(&self, cursor: usize, mut f: impl FnMut(&str, &[u8])) -> usize {
+        match &self.encoding {
+            Encoding::Compact(pairs) => {
+                pairs.iter().for_each(|(field, v)| f(field, v));
+                0
+            }
+            Encoding::Dict(dict) => dict.scan(cursor, |e| f(e.key(), e.value())),
+        }
+    }
+
+    fn convert(&mut self) {
+        if let Encoding::Compact(pairs) = &mut self.encoding {
+            let mut dict = Dict::default();
+            for (field, value) in pairs.drain(..) {
+                dict.insert(&field, value);
+            }
+            self.encoding = Encoding::Dict(dict);
+        }
+    }
+}
+
+impl Clone for FieldMap {
+    fn clone(&self) -> Self {
+        let encoding = match &self.encoding {
+            Encoding::Compact(pairs) => Encoding::Compact(pairs.clone()),
+            Encoding::Dict(dict) => {
+                let mut copy = Dict::default();
+                for e in dict.iter() {
+                    copy.insert(e.key(), e.value().clone());
+                }
+                Encoding::Dict(copy)
+            }
+        };
+        Self {
+            encoding,
+            bytes: self.bytes,
+        }
+    }
+}
+
+impl PartialEq for FieldMap {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().all(|(f, v)| other.get(f) == Some(v))
+    }
+}
+
+impl Eq for FieldMap {}
+
+#[cfg(test)]
+mod test {
+    use super::FieldMap;
+
+    #[test]
+    fn converts_when_growing() {
+        let mut m = FieldMap::new();
+        for i in 0..FieldMap::MAX_COMPACT_LEN {
+            assert_eq!(m.insert(&format!("f{i}"), b"v".to_vec()), None);
+        }
+        assert!(m.is_compact());
+        assert_eq!(m.insert("f0", b"w".to_vec()), Some(b"v".to_vec()));
+
+        m.insert("one more", b"v".to_vec());
+        assert!(!m.is_compact());
+        assert_eq!(m.len(), FieldMap::MAX_COMPACT_LEN + 1);
+        assert_eq!(m.get("f0"), Some(&b"w"[..]));
+
+        let mut long = FieldMap::new();
+        long.insert("f", vec![b'x'; FieldMap::MAX_COMPACT_VALUE + 1]);
+        assert!(!long.is_compact());
+    }
+
+    #[test]
+    fn random_fields() {
+        let mut rng = rand::rng();
+        let mut m = FieldMap::new();
+        assert_eq!(m.random(&mut rng), None);
+
+        for i in 0..=FieldMap::MAX_COMPACT_LEN {
+            m.insert(&format!("f{i}"), i.to_string().into_bytes());
+            let (field, value) = m.random(&mut rng).unwrap();
+            assert_eq!(m.get(field), Some(value));
+        }
+        assert!(!m.is_compact());
+    }
+
+    #[test]
+    fn tracks_bytes() {
+        let mut m = FieldMap::new();
+        m.insert("ab", b"cd".to_vec());
+        m.insert("ab", b"cde".to_vec());
+        assert_eq!(m.bytes, 5);
+        assert_eq!(m.remove("ab"), Some(b"cde".to_vec()));
+        assert_eq!(m.remove("ab"), None);
+        assert_eq!(m.bytes, 0);
+        assert!(m.is_empty());
+    }
+}
@@ -6,31 +6,35 @@ use std::{
 use super::Entry;
 
 #[derive(Debug)]
-pub(crate) struct HashTable {
-    pub(crate) buckets: Vec<LinkedList<Entry>>,
+pub(crate) struct HashTable<V> {
+    pub(crate) buckets: Vec<LinkedList<Entry<V>>>,
     pub(crate) items: usize,
     pub(crate) mask: usize,
+    /// Upper bound on the length of any bucket, it never shrinks
+    pub(crate) max_chain: usize,
 }
 
 #[derive(Debug)]
-pub struct Iter<'a> {
-    ht: &'a HashTable,
-    cursor: Cursor<'a, Entry>,
+pub struct Iter<'a, V> {
+    ht: &'a HashTable<V>,
+    /// `None` for tables without any buckets
+    cursor: Option<Cursor<'a, Entry<V>>>,
     bucket_idx: usize,
 }
 
-impl Default for HashTable {
+impl<V> Default for HashTable<V> {
     fn default() -> Self {
         Self::new_with_buckets(Self::DEFAULT_BUCKET_SIZE)
     }
 }
 
-impl HashTable {
+impl<V> HashTable<V> {
     pub const DEFAULT_BUCKET_SIZE: usize = 4;
-    pub const EMPTY_TABLE: HashTable = HashTable {
+    pub const EMPTY_TABLE: HashTable<V> = HashTable {
         buckets: Vec::new(),
         mask: 0,
         items: 0,
+        max_chain: 0,
     };
 
     /// Creates a new `HashTable` with `cap` many buckets
@@ -46,6 +50,7 @@ impl HashTable {
             buckets,
             items: 0,
             mask,
+            max_chain: 0,
         }
     }
 
@@ -68,15 +73,14 @@ impl HashTable {
     /// Inserts an item into the hash table.
     /// This does not resize the table, so if
     /// the tables size is 0, then this function return early with `None`
-    pub fn insert(&mut self, node: Entry) -> Option<String> {
-        let hash = Self::hash(&node.key.as_str());
-        let i = hash as usize & self.mask;
+    pub fn insert(&mut self, node: Entry<V>) -> Option<V> {
+        let i = self.idx(&node.key);
 
-        let slot: Option<&mut Entry> = self
+        let slot: Option<&mut Entry<V>> = self
             .buckets
             .get_mut(i)?
             .iter_mut()
-            .find(|n| n.key.as_str() == &node.key);
+            .find(|n| n.key == node.key);
 
         match slot {
             Some(n) => {
@@ -86,37 +90,32 @@ impl HashTable {
             None => {
                 self.buckets[i].push_back(node);
                 self.items += 1;
+                self.max_chain = self.max_chain.max(self.buckets[i].len());
                 None
             }
         }
     }
 
-    pub fn get(&self, key: &str) -> Option<&Entry> {
-        let hash = Self::hash(key);
-        let i = hash as usize & self.mask;
+    pub fn get(&self, key: &str) -> Option<&Entry<V>> {
+        let i = self.idx(key);
         self.buckets.get(i)?.iter().find(|n| n.key == key)
     }
 
-    pub fn get_mut(&mut self, key: &str) -> Option<&mut Entry> {
-        let hash = Self::hash(key);
-        let i = hash as usize & self.mask;
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut Entry<V>> {
+        let i = self.idx(key);
         self.buckets.get_mut(i)?.iter_mut().find(|n| n.key == key)
     }
 
-    pub fn remove(&mut self, key: &str) -> Option<Entry> {
-        let hash = Self::hash(key);
-        let i = hash as usize & self.mask;
+    pub fn remove(&mut self, key: &str) -> Option<Entry<V>> {
+        let i = self.idx(key);
         let mut cursor_mut = self.buckets.get_mut(i)?.cursor_front_mut();
         loop {
-            match cursor_mut.current() {
-                Some(node) => {
-                    if node.key == key {
-                        return cursor_mut.remove_current();
-                    }
-                    cursor_mut.move_next();
-                }
-                None => return None,
+            let node = cursor_mut.current()?;
+            if node.key == key {
+                self.items -= 1;
+                return cursor_mut.remove_current();
             }
+            cursor_mut.move_next();
         }
     }
 
@@ -126,18 +125,10 @@ impl HashTable {
 
     // [adapters]
 
-    pub fn iter(&self) -> Iter<'_> {
-        let cursor = if self.is_empty() {
-            /// FIXME: stupid variable that nobody wants here!
-            static EMPTY_LIST: LinkedList<Entry> = LinkedList::new();
-            EMPTY_LIST.cursor_front()
-        } else {
-            self.buckets[0].cursor_front()
-        };
-
+    pub fn iter(&self) -> Iter<'_, V> {
         Iter {
-            ht: &self,
-            cursor,
+            ht: self,
+            cursor: self.buckets.first().map(LinkedList::cursor_front),
             bucket_idx: 0,
         }
     }
@@ -155,18 +146,16 @@ impl HashTable {
     }
 }
 
-impl<'a> Iterator for Iter<'a> {
-    type Item = &'a Entry;
+impl<'a, V> Iterator for Iter<'a, V> {
+    type Item = &'a Entry<V>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.ht.bucket_count() == 0 {
-            return None;
-        }
+        let cursor = self.cursor.as_mut()?;
 
         loop {
-            match self.cursor.current() {
+            match cursor.current() {
                 Some(node) => {
-                    self.cursor.move_next();
+                    cursor.move_next();
                     return Some(node);
                 }
                 None => {
@@ -174,7 +163,7 @@ impl<'a> Iterator for Iter<'a> {
                         return None;
                     }
                     self.bucket_idx += 1;
-                    self.cursor = self.ht.buckets[self.bucket_idx].cursor_front();
+                    *cursor = self.ht.buckets[self.bucket_idx].cursor_front();
                 }
             }
         }
@@ -195,7 +184,7 @@ mod test {
 
     #[test]
     fn insert() {
-        let mut t = HashTable::default();
+        let mut t = HashTable::<String>::default();
         dbg!(&t);
 
         let old = t.insert(node!("foo", "bar"));
@@ -215,7 +204,7 @@ mod test {
     }
     #[test]
     fn get() {
-        let mut t = HashTable::default();
+        let mut t = HashTable::<String>::default();
 
         t.insert(node!("peti", "is a baby"));
         t.insert(node!("sina", "is a tiny baby"));
@@ -228,7 +217,7 @@ mod test {
 
     #[test]
     fn dbg() {
-        let mut t = HashTable::new_with_buckets(12);
+        let mut t = HashTable::<String>::new_with_buckets(12);
 
         let pairs: Vec<(String, String)> = (0..25)
             .map(|i| {
@@ -247,7 +236,7 @@ mod test {
     }
     #[test]
     fn dbg_long_chains() {
-        let mut t = HashTable::new_with_buckets(2);
+        let mut t = HashTable::<String>::new_with_buckets(2);
 
         let pairs: Vec<(String, String)> = (0..25)
             .map(|i| {
@@ -267,7 +256,7 @@ mod test {
 
     #[test]
     fn iter() {
-        let mut h = HashTable::new_with_buckets(0);
+        let mut h = HashTable::<String>::new_with_buckets(0);
 
         for i in 0..32 {
             h.insert(node!(format!("{}", i).as_str(), ""));
@@ -282,7 +271,7 @@ mod test {
 
     #[test]
     fn rust_doc_example() {
-        let mut book_reviews = HashTable::new_with_buckets(0);
+        let mut book_reviews = HashTable::<String>::new_with_buckets(0);
 
         // Review some books.
         book_reviews.insert(node!("Adventures of Huckleberry Finn", "My favorite book."));
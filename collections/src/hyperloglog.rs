@@ -0,0 +1,297 @@
+/// A HyperLogLog cardinality estimator with `2^PRECISION` registers,
+/// for a standard error of about 0.81%.
+///
+/// Every element is hashed, the low `PRECISION` bits pick a register and
+/// the register keeps the longest run of trailing zeros, plus one, seen in
+/// the rest of the hash. A new estimator starts out sparse, storing only the
+/// registers that are set, sorted by index. Once it has more than
+/// `SPARSE_MAX_LEN` of them it switches to the dense encoding, every register
+/// packed into 6 bits, 12kB in total.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HyperLogLog {
+    encoding: Encoding,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Encoding {
+    Sparse(Vec<(u16, u8)>),
+    Dense(Vec<u8>),
+}
+
+impl Default for HyperLogLog {
+    fn default() -> Self {
+        Self {
+            encoding: Encoding::Sparse(Vec::new()),
+        }
+    }
+}
+
+impl HyperLogLog {
+    pub const PRECISION: u32 = 14;
+    pub const REGISTERS: usize = 1 << Self::PRECISION;
+    /// Number of set registers after which the sparse encoding takes
+    /// more memory than it is worth
+    pub const SPARSE_MAX_LEN: usize = 750;
+
+    const BITS_PER_REGISTER: usize = 6;
+    const DENSE_BYTES: usize = Self::REGISTERS * Self::BITS_PER_REGISTER / 8;
+    /// Hash bits left to count zeros in, once the register index is taken
+    const Q: u32 = 64 - Self::PRECISION;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether the estimator still uses the sparse encoding
+    pub fn is_sparse(&self) -> bool {
+        matches!(self.encoding, Encoding::Sparse(_))
+    }
+
+    /// Approximate number of heap bytes used by the estimator
+    pub fn mem_usage(&self) -> usize {
+        match &self.encoding {
+            Encoding::Sparse(registers) => registers.capacity() * size_of::<(u16, u8)>(),
+            Encoding::Dense(registers) => registers.capacity(),
+        }
+    }
+
+    /// Adds `element`, returning whether any register changed
+    pub fn add(&mut self, element: &[u8]) -> bool {
+        let hash = murmur_hash64a(element, 0xadc83b19);
+        let index = (hash & (Self::REGISTERS as u64 - 1)) as u16;
+        // the extra bit bounds the count when the rest of the hash is all zeros
+        let rest = hash >> Self::PRECISION | 1 << Self::Q;
+        let count = rest.trailing_zeros() as u8 + 1;
+        self.set_max(index, count)
+    }
+
+    /// Estimates the number of distinct elements added
+    pub fn count(&self) -> u64 {
+        let mut histogram = [0u32; Self::Q as usize + 2];
+        match &self.encoding {
+            Encoding::Sparse(registers) => {
+                histogram[0] = (Self::REGISTERS - registers.len()) as u32;
+                for &(_, count) in registers {
+                    histogram[count as usize] += 1;
+                }
+            }
+            Encoding::Dense(registers) => {
+                for i in 0..Self::REGISTERS {
+                    histogram[dense_get(registers, i) as usize] += 1;
+                }
+            }
+        }
+        estimate(&histogram)
+    }
+
+    /// Merges `other` into `self`, so it counts the elements of both
+    pub fn merge(&mut self, other: &HyperLogLog) {
+        match &other.encoding {
+            Encoding::Sparse(registers) => {
+                for &(index, count) in registers {
+                    self.set_max(index, count);
+                }
+            }
+            Encoding::Dense(registers) => {
+                self.make_dense();
+                for i in 0..Self::REGISTERS {
+                    self.set_max(i as u16, dense_get(registers, i));
+                }
+            }
+        }
+    }
+
+    fn set_max(&mut self, index: u16, count: u8) -> bool {
+        match &mut self.encoding {
+            Encoding::Sparse(registers) => {
+                match registers.binary_search_by_key(&index, |&(i, _)| i) {
+                    Ok(pos) if registers[pos].1 >= count => return false,
+                    Ok(pos) => registers[pos].1 = count,
+                    Err(pos) => registers.insert(pos, (index, count)),
+                }
+                if registers.len() > Self::SPARSE_MAX_LEN {
+                    self.make_dense();
+                }
+                true
+            }
+            Encoding::Dense(registers) => {
+                if dense_get(registers, index as usize) >= count {
+                    return false;
+                }
+                dense_set(registers, index as usize, count);
+                true
+            }
+        }
+    }
+
+    fn make_dense(&mut self) {
+        if let Encoding::Sparse(sparse) = &self.encoding {
+            let mut registers = vec![0; Self::DENSE_BYTES];
+            for &(index, count) in sparse {
+                dense_set(&mut registers, index as usize, count);
+            }
+            self.encoding = Encoding::Dense(registers);
+        }
+    }
+}
+
+/// Reads the 6 bit register at `index`, registers may straddle two bytes
+fn dense_get(registers: &[u8], index: usize) -> u8 {
+    let bit = index * HyperLogLog::BITS_PER_REGISTER;
+    let (byte, shift) = (bit / 8, bit % 8);
+    let low = registers[byte] as u16 >> shift;
+    let high = registers
+        .get(byte + 1)
+        .map_or(0, |&b| (b as u16) << (8 - shift));
+    ((low | high) & 0x3f) as u8
+}
+
+fn dense_set(registers: &mut [u8], index: usize, value: u8) {
+    let bit = index * HyperLogLog::BITS_PER_REGISTER;
+    let (byte, shift) = (bit / 8, bit % 8);
+    let value = (value as u16 & 0x3f) << shift;
+    let mask = 0x3fu16 << shift;
+
+    registers[byte] = (registers[byte] & !(mask as u8)) | value as u8;
+    if shift > 2 {
+        let high = &mut registers[byte + 1];
+        *high = (*high & !((mask >> 8) as u8)) | (value >> 8) as u8;
+    }
+}
+
+/// Ertl's improved raw estimator, from "New cardinality estimation algorithms
+/// for HyperLogLog sketches", which needs no bias correction at any range.
+/// `histogram[k]` is the number of registers holding `k`
+fn estimate(histogram: &[u32]) -> u64 {
+    let m = HyperLogLog::REGISTERS as f64;
+    let q = HyperLogLog::Q as usize;
+
+    let mut z = m * tau((m - histogram[q + 1] as f64) / m);
+    for &registers in histogram[1..=q].iter().rev() {
+        z += registers as f64;
+        z *= 0.5;
+    }
+    z += m * sigma(histogram[0] as f64 / m);
+
+    let alpha = 0.5 / std::f64::consts::LN_2;
+    (alpha * m * m / z).round() as u64
+}
+
+fn sigma(mut x: f64) -> f64 {
+    if x == 1.0 {
+        return f64::INFINITY;
+    }
+    let (mut y, mut z) = (1.0, x);
+    loop {
+        x *= x;
+        let prev = z;
+        z += x * y;
+        y += y;
+        if prev == z {
+            return z;
+        }
+    }
+}
+
+fn tau(mut x: f64) -> f64 {
+    if x == 0.0 || x == 1.0 {
+        return 0.0;
+    }
+    let (mut y, mut z) = (1.0, 1.0 - x);
+    loop {
+        x = x.sqrt();
+        let prev = z;
+        y *= 0.5;
+        z -= (1.0 - x).powi(2) * y;
+        if prev == z {
+            return z / 3.0;
+        }
+    }
+}
+
+/// MurmurHash64A by Austin Appleby, stable across platforms and releases
+/// unlike the hashers of the standard library
+fn murmur_hash64a(data: &[u8], seed: u64) -> u64 {
+    const M: u64 = 0xc6a4a7935bd1e995;
+    const R: u32 = 47;
+
+    let mut h = seed ^ (data.len() as u64).wrapping_mul(M);
+    let mut chunks = data.chunks_exact(8);
+    for chunk in &mut chunks {
+        let mut k = u64::from_le_bytes(chunk.try_into().unwrap());
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+        h ^= k;
+        h = h.wrapping_mul(M);
+    }
+
+    let tail = chunks.remainder();
+    if !tail.is_empty() {
+        for (i, &b) in tail.iter().enumerate() {
+            h ^= (b as u64) << (8 * i);
+        }
+        h = h.wrapping_mul(M);
+    }
+
+    h ^= h >> R;
+    h = h.wrapping_mul(M);
+    h ^= h >> R;
+    h
+}
+
+#[cfg(test)]
+mod test {
+    use super::{HyperLogLog, dense_get, dense_set};
+
+    fn error(hll: &HyperLogLog, actual: u64) -> f64 {
+        (hll.count() as f64 - actual as f64).abs() / actual as f64
+    }
+
+    #[test]
+    fn registers_pack_into_six_bits() {
+        let mut registers = vec![0; HyperLogLog::DENSE_BYTES];
+        for i in 0..HyperLogLog::REGISTERS {
+            dense_set(&mut registers, i, (i % 64) as u8);
+        }
+        assert!((0..HyperLogLog::REGISTERS).all(|i| dense_get(&registers, i) == (i % 64) as u8));
+    }
+
+    #[test]
+    fn estimates_within_error() {
+        let mut hll = HyperLogLog::new();
+        assert_eq!(hll.count(), 0);
+
+        for i in 0..100 {
+            hll.add(format!("element:{i}").as_bytes());
+        }
+        assert!(hll.is_sparse());
+        assert!(!hll.add(b"element:0"));
+        assert!(error(&hll, 100) < 0.02);
+
+        for i in 100..100_000 {
+            hll.add(format!("element:{i}").as_bytes());
+        }
+        assert!(!hll.is_sparse());
+        assert!(error(&hll, 100_000) < 0.03, "{}", hll.count());
+    }
+
+    #[test]
+    fn merge() {
+        let (mut a, mut b) = (HyperLogLog::new(), HyperLogLog::new());
+        for i in 0..5000 {
+            a.add(format!("{i}").as_bytes());
+            b.add(format!("{}", i + 2500).as_bytes());
+        }
+        let mut sparse = HyperLogLog::new();
+        sparse.add(b"one more");
+
+        a.merge(&b);
+        a.merge(&sparse);
+        assert!(error(&a, 7501) < 0.03, "{}", a.count());
+
+        sparse.merge(&b);
+        assert!(!sparse.is_sparse());
+        assert!(error(&sparse, 5001) < 0.03, "{}", sparse.count());
+    }
+}
@@ -1,22 +1,40 @@
 #![feature(linked_list_cursors)]
 mod dict;
+mod field_map;
 mod hash_table;
+mod hyperloglog;
+mod list;
+mod set;
+mod sorted_set;
+mod stream;
 
 pub use dict::Dict;
+pub use field_map::FieldMap;
+pub use hyperloglog::HyperLogLog;
+pub use list::QuickList;
+pub use set::Set;
+pub use sorted_set::SortedSet;
+pub use stream::{Consumer, ConsumerGroup, Fields, PendingEntry, Stream, StreamId};
 
 #[derive(Debug, Hash, PartialEq, Eq)]
-pub struct Entry {
+pub struct Entry<V = String> {
     key: String,
-    value: String,
+    value: V,
 }
 
-impl Entry {
+impl<V> Entry<V> {
     pub fn key(&self) -> &str {
         &self.key
     }
-    pub fn value(&self) -> &str {
+    pub fn value(&self) -> &V {
         &self.value
     }
+    pub fn value_mut(&mut self) -> &mut V {
+        &mut self.value
+    }
+    pub fn into_value(self) -> V {
+        self.value
+    }
 }
 
 use thiserror::Error;
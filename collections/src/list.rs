@@ -0,0 +1,367 @@
+use std::collections::VecDeque;
+
+/// An unrolled linked list of byte strings.
+///
+/// Elements are packed back to back into chunks of at most `CHUNK_BYTES`,
+/// each one prefixed with its varint encoded length and followed by the
+/// length of the whole entry, readable backwards, so both ends can be
+/// popped without an index. Small lists fit into a single chunk,
+/// long ones don't pay a pointer pair per element.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct QuickList {
+    chunks: VecDeque<Chunk>,
+    len: usize,
+}
+
+/// Packed entries, see `QuickList`
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+struct Chunk {
+    data: Vec<u8>,
+    count: usize,
+}
+
+impl QuickList {
+    /// Size a chunk is allowed to grow to before a new one is started.
+    /// Elements larger than this get a chunk of their own
+    pub const CHUNK_BYTES: usize = 4096;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Approximate number of heap bytes used by the list
+    pub fn mem_usage(&self) -> usize {
+        self.chunks.capacity() * size_of::<Chunk>()
+            + self.chunks.iter().map(|c| c.data.capacity()).sum::<usize>()
+    }
+
+    pub fn push_front(&mut self, value: &[u8]) {
+        let entry = Chunk::encode(value);
+        match self.chunks.front_mut() {
+            Some(chunk) if chunk.fits(entry.len()) => chunk.prepend(&entry),
+            _ => self.chunks.push_front(Chunk::with_entry(entry)),
+        }
+        self.len += 1;
+    }
+
+    pub fn push_back(&mut self, value: &[u8]) {
+        let entry = Chunk::encode(value);
+        match self.chunks.back_mut() {
+            Some(chunk) if chunk.fits(entry.len()) => chunk.append(&entry),
+            _ => self.chunks.push_back(Chunk::with_entry(entry)),
+        }
+        self.len += 1;
+    }
+
+    pub fn pop_front(&mut self) -> Option<Vec<u8>> {
+        let chunk = self.chunks.front_mut()?;
+        let value = chunk.pop_front();
+        if chunk.count == 0 {
+            self.chunks.pop_front();
+        }
+        self.len -= 1;
+        Some(value)
+    }
+
+    pub fn pop_back(&mut self) -> Option<Vec<u8>> {
+        let chunk = self.chunks.back_mut()?;
+        let value = chunk.pop_back();
+        if chunk.count == 0 {
+            self.chunks.pop_back();
+        }
+        self.len -= 1;
+        Some(value)
+    }
+
+    pub fn get(&self, index: usize) -> Option<&[u8]> {
+        self.iter_from(index).next()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &[u8]> {
+        self.iter_from(0)
+    }
+
+    /// Iterates from the element at `index`, skipping whole chunks to get there
+    pub fn iter_from(&self, mut index: usize) -> impl Iterator<Item = &[u8]> {
+        let mut first = 0;
+        for chunk in &self.chunks {
+            if index < chunk.count {
+                break;
+            }
+            index -= chunk.count;
+            first += 1;
+        }
+
+        self.chunks.range(first..).flat_map(Chunk::iter).skip(index)
+    }
+
+    /// Keeps only the elements in `start..end`
+    pub fn retain_range(&mut self, start: usize, end: usize) {
+        let end = end.min(self.len);
+        if start >= end {
+            self.clear();
+            return;
+        }
+
+        self.remove_back(self.len - end);
+        self.remove_front(start);
+    }
+
+    pub fn clear(&mut self) {
+        self.chunks.clear();
+        self.len = 0;
+    }
+
+    /// Removes the first `n` elements
+    fn remove_front(&mut self, mut n: usize) {
+        self.len -= n;
+        while let Some(chunk) = self.chunks.front_mut() {
+            if n < chunk.count {
+                chunk.remove_front(n);
+                return;
+            }
+            n -= chunk.count;
+            self.chunks.pop_front();
+        }
+    }
+
+    /// Removes the last `n` elements
+    fn remove_back(&mut self, mut n: usize) {
+        self.len -= n;
+        while let Some(chunk) = self.chunks.back_mut() {
+            if n < chunk.count {
+                chunk.remove_back(n);
+                return;
+            }
+            n -= chunk.count;
+            self.chunks.pop_back();
+        }
+    }
+}
+
+impl Chunk {
+    fn with_entry(entry: Vec<u8>) -> Self {
+        Self {
+            data: entry,
+            count: 1,
+        }
+    }
+
+    fn fits(&self, len: usize) -> bool {
+        self.data.len() + len <= QuickList::CHUNK_BYTES
+    }
+
+    fn append(&mut self, entry: &[u8]) {
+        self.data.extend_from_slice(entry);
+        self.count += 1;
+    }
+
+    fn prepend(&mut self, entry: &[u8]) {
+        self.data.splice(0..0, entry.iter().copied());
+        self.count += 1;
+    }
+
+    fn pop_front(&mut self) -> Vec<u8> {
+        let (value, end) = Self::decode(&self.data, 0);
+        let value = value.to_vec();
+        self.data.drain(..end);
+        self.count -= 1;
+        value
+    }
+
+    fn pop_back(&mut self) -> Vec<u8> {
+        let start = self.data.len() - read_backlen(&self.data);
+        let (value, _) = Self::decode(&self.data, start);
+        let value = value.to_vec();
+        self.data.truncate(start);
+        self.count -= 1;
+        value
+    }
+
+    fn remove_front(&mut self, n: usize) {
+        let mut end = 0;
+        for _ in 0..n {
+            end = Self::decode(&self.data, end).1;
+        }
+        self.data.drain(..end);
+        self.count -= n;
+    }
+
+    fn remove_back(&mut self, n: usize) {
+        let mut start = self.data.len();
+        for _ in 0..n {
+            start -= read_backlen(&self.data[..start]);
+        }
+        self.data.truncate(start);
+        self.count -= n;
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &[u8]> {
+        let mut pos = 0;
+        std::iter::from_fn(move || {
+            if pos == self.data.len() {
+                return None;
+            }
+            let (value, next) = Self::decode(&self.data, pos);
+            pos = next;
+            Some(value)
+        })
+    }
+
+    /// Encodes one entry: `varint(len) value backlen(entry len)`
+    fn encode(value: &[u8]) -> Vec<u8> {
+        let mut entry = Vec::with_capacity(value.len() + 6);
+        write_varint(&mut entry, value.len());
+        entry.extend_from_slice(value);
+        let len = entry.len();
+        write_backlen(&mut entry, len);
+        entry
+    }
+
+    /// Decodes the entry at `pos`, returning its value and the position of the next one
+    fn decode(data: &[u8], pos: usize) -> (&[u8], usize) {
+        let (len, header) = read_varint(&data[pos..]);
+        let start = pos + header;
+        let end = start + len;
+        let backlen = varint_len(end - pos);
+        (&data[start..end], end + backlen)
+    }
+}
+
+fn varint_len(mut n: usize) -> usize {
+    let mut len = 1;
+    while n >= 0x80 {
+        n >>= 7;
+        len += 1;
+    }
+    len
+}
+
+/// Writes `n` in 7 bit groups, least significant first,
+/// the high bit marks that more groups follow
+fn write_varint(buf: &mut Vec<u8>, mut n: usize) {
+    while n >= 0x80 {
+        buf.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    buf.push(n as u8);
+}
+
+/// Returns the decoded number with the number of bytes it took
+fn read_varint(buf: &[u8]) -> (usize, usize) {
+    let mut n = 0;
+    for (i, &b) in buf.iter().enumerate() {
+        n |= ((b & 0x7f) as usize) << (7 * i);
+        if b & 0x80 == 0 {
+            return (n, i + 1);
+        }
+    }
+    unreachable!("truncated varint")
+}
+
+/// Writes `n` in 7 bit groups, most significant first, so it can be read
+/// from the end of `buf`. The high bit is set on every byte but the first
+fn write_backlen(buf: &mut Vec<u8>, n: usize) {
+    let len = varint_len(n);
+    for i in (0..len).rev() {
+        let group = (n >> (7 * i)) as u8 & 0x7f;
+        buf.push(if i == len - 1 { group } else { group | 0x80 });
+    }
+}
+
+/// Reads the backlen at the end of `buf`, returning the size
+/// of the last entry including the backlen itself
+fn read_backlen(buf: &[u8]) -> usize {
+    let mut n = 0;
+    for (i, &b) in buf.iter().rev().enumerate() {
+        n |= ((b & 0x7f) as usize) << (7 * i);
+        if b & 0x80 == 0 {
+            return n + i + 1;
+        }
+    }
+    unreachable!("truncated backlen")
+}
+
+#[cfg(test)]
+mod test {
+    use super::QuickList;
+
+    fn collect(l: &QuickList) -> Vec<String> {
+        l.iter()
+            .map(|v| String::from_utf8(v.to_vec()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn push_pop() {
+        let mut l = QuickList::new();
+        l.push_back(b"b");
+        l.push_front(b"a");
+        l.push_back(b"c");
+        assert_eq!(collect(&l), ["a", "b", "c"]);
+        assert_eq!(l.len(), 3);
+
+        assert_eq!(l.pop_front().as_deref(), Some(&b"a"[..]));
+        assert_eq!(l.pop_back().as_deref(), Some(&b"c"[..]));
+        assert_eq!(l.pop_back().as_deref(), Some(&b"b"[..]));
+        assert_eq!(l.pop_back(), None);
+        assert!(l.is_empty());
+    }
+
+    #[test]
+    fn spans_chunks() {
+        let mut l = QuickList::new();
+        let big = vec![b'x'; 1000];
+        for i in 0..100 {
+            l.push_back(format!("{i}").as_bytes());
+            l.push_front(&big);
+        }
+        // long entries need multibyte lengths
+        l.push_back(&vec![b'y'; QuickList::CHUNK_BYTES * 2]);
+
+        assert!(l.chunks.len() > 1);
+        assert_eq!(l.len(), 201);
+        assert_eq!(l.get(100), Some(&b"0"[..]));
+        assert_eq!(l.get(199), Some(&b"99"[..]));
+        assert_eq!(
+            l.get(200).map(<[u8]>::len),
+            Some(QuickList::CHUNK_BYTES * 2)
+        );
+        assert_eq!(l.get(201), None);
+        assert_eq!(l.iter().count(), 201);
+
+        assert_eq!(
+            l.pop_back().map(|v| v.len()),
+            Some(QuickList::CHUNK_BYTES * 2)
+        );
+        for _ in 0..100 {
+            assert_eq!(l.pop_front(), Some(big.clone()));
+        }
+        assert_eq!(collect(&l).len(), 100);
+    }
+
+    #[test]
+    fn retain_range() {
+        let mut l = QuickList::new();
+        for i in 0..1000 {
+            l.push_back(format!("{i}").as_bytes());
+        }
+
+        l.retain_range(10, 990);
+        assert_eq!(l.len(), 980);
+        assert_eq!(l.get(0), Some(&b"10"[..]));
+        assert_eq!(l.iter().last(), Some(&b"989"[..]));
+
+        l.retain_range(5, 3);
+        assert!(l.is_empty());
+        assert_eq!(l.iter().count(), 0);
+    }
+}
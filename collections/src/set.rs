@@ -0,0 +1,260 @@
+use std::borrow::Cow;
+
+use rand::Rng;
+
+use crate::Dict;
+
+/// An unordered set of strings.
+///
+/// Sets made up of only integers, in their canonical form, are kept as
+/// a sorted array of `i64`s, looked up with binary search. Adding anything
+/// else, or more than `MAX_INTSET_LEN` members, converts it to a `Dict` for good.
+#[derive(Debug)]
+pub struct Set {
+    encoding: Encoding,
+    /// Bytes used by the members of a `Dict` encoded set
+    bytes: usize,
+}
+
+#[derive(Debug)]
+enum Encoding {
+    Ints(Vec<i64>),
+    Dict(Dict<()>),
+}
+
+impl Default for Set {
+    fn default() -> Self {
+        Self {
+            encoding: Encoding::Ints(Vec::new()),
+            bytes: 0,
+        }
+    }
+}
+
+impl Set {
+    /// Number of members an integer set can hold
+    pub const MAX_INTSET_LEN: usize = 512;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        match &self.encoding {
+            Encoding::Ints(ints) => ints.len(),
+            Encoding::Dict(dict) => dict.size(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether the set still uses the integer encoding
+    pub fn is_intset(&self) -> bool {
+        matches!(self.encoding, Encoding::Ints(_))
+    }
+
+    /// Approximate number of heap bytes used by the set
+    pub fn mem_usage(&self) -> usize {
+        match &self.encoding {
+            Encoding::Ints(ints) => ints.capacity() * size_of::<i64>(),
+            Encoding::Dict(dict) => {
+                let (primary, secondary) = dict.bucket_counts();
+                (primary + secondary) * size_of::<usize>() * 2
+                    + dict.size() * size_of::<String>()
+                    + self.bytes
+            }
+        }
+    }
+
+    pub fn contains(&self, member: &str) -> bool {
+        match &self.encoding {
+            Encoding::Ints(ints) => as_int(member).is_some_and(|n| ints.binary_search(&n).is_ok()),
+            Encoding::Dict(dict) => dict.peek(member).is_some(),
+        }
+    }
+
+    /// Adds `member`, returning whether it was new
+    pub fn insert(&mut self, member: &str) -> bool {
+        if let Encoding::Ints(ints) = &mut self.encoding
+            && let Some(n) = as_int(member)
+        {
+            let Err(idx) = ints.binary_search(&n) else {
+                return false;
+            };
+            if ints.len() < Self::MAX_INTSET_LEN {
+                ints.insert(idx, n);
+                return true;
+            }
+        }
+
+        self.convert();
+        let Encoding::Dict(dict) = &mut self.encoding else {
+            unreachable!("set was just converted")
+        };
+        let added = dict.insert(member, ()).is_none();
+        if added {
+            self.bytes += member.len();
+        }
+        added
+    }
+
+    /// Removes `member`, returning whether it was there
+    pub fn remove(&mut self, member: &str) -> bool {
+        match &mut self.encoding {
+            Encoding::Ints(ints) => {
+                let Some(Ok(idx)) = as_int(member).map(|n| ints.binary_search(&n)) else {
+                    return false;
+                };
+                ints.remove(idx);
+                true
+            }
+            Encoding::Dict(dict) => {
+                let removed = dict.remove(member).is_some();
+                if removed {
+                    self.bytes -= member.len();
+                }
+                removed
+            }
+        }
+    }
+
+    pub fn iter(&self) -> Box<dyn Iterator<Item = Cow<'_, str>> + '_> {
+        match &self.encoding {
+            Encoding::Ints(ints) => Box::new(ints.iter().map(|n| Cow::Owned(n.to_string()))),
+            Encoding::Dict(dict) => Box::new(dict.iter().map(|e| Cow::Borrowed(e.key()))),
+        }
+    }
+
+    /// Returns a member picked uniformly at random
+    pub fn random<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<Cow<'_, str>> {
+        match &self.encoding {
+            Encoding::Ints(ints) if ints.is_empty() => None,
+            Encoding::Ints(ints) => Some(Cow::Owned(
+                ints[rng.random_range(0..ints.len())].to_string(),
+            )),
+            Encoding::Dict(dict) => dict.random_entry(rng).map(|e| Cow::Borrowed(e.key())),
+        }
+    }
+
+    /// Removes and returns a member picked uniformly at random
+    pub fn pop_random<R: Rng + ?Sized>(&mut self, rng: &mut R) -> Option<String> {
+        let member = self.random(rng)?.into_owned();
+        self.remove(&member);
+        Some(member)
+    }
+
+    /// Visits the members at `cursor`, returning the cursor to continue from,
+    /// 0 once done. Integer sets are visited in one go, see `Dict::scan`
+    pub fn scan(&self, cursor: usize, mut f: impl FnMut(&str)) -> usize {
+        match &self.encoding {
+            Encoding::Ints(ints) => {
+                ints.iter().for_each(|n| f(&n.to_string()));
+                0
+            }
+            Encoding::Dict(dict) => dict.scan(cursor, |e| f(e.key())),
+        }
+    }
+
+    fn convert(&mut self) {
+        if let Encoding::Ints(ints) = &self.encoding {
+            let mut dict = Dict::default();
+            for n in ints {
+                let member = n.to_string();
+                self.bytes += member.len();
+                dict.insert(&member, ());
+            }
+            self.encoding = Encoding::Dict(dict);
+        }
+    }
+}
+
+/// Parses `s` if it is the canonical form of an integer,
+/// so it reads back the same once stored as one
+fn as_int(s: &str) -> Option<i64> {
+    let n = s.parse::<i64>().ok()?;
+    (n.to_string() == s).then_some(n)
+}
+
+impl<'a> FromIterator<&'a str> for Set {
+    fn from_iter<T: IntoIterator<Item = &'a str>>(iter: T) -> Self {
+        let mut set = Set::new();
+        for member in iter {
+            set.insert(member);
+        }
+        set
+    }
+}
+
+impl Clone for Set {
+    fn clone(&self) -> Self {
+        let encoding = match &self.encoding {
+            Encoding::Ints(ints) => Encoding::Ints(ints.clone()),
+            Encoding::Dict(dict) => {
+                let mut copy = Dict::default();
+                for e in dict.iter() {
+                    copy.insert(e.key(), ());
+                }
+                Encoding::Dict(copy)
+            }
+        };
+        Self {
+            encoding,
+            bytes: self.bytes,
+        }
+    }
+}
+
+impl PartialEq for Set {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().all(|m| other.contains(&m))
+    }
+}
+
+impl Eq for Set {}
+
+#[cfg(test)]
+mod test {
+    use super::Set;
+
+    #[test]
+    fn intset_converts() {
+        let mut s = Set::new();
+        assert!(s.insert("3"));
+        assert!(s.insert("-1"));
+        assert!(!s.insert("3"));
+        // not canonical, so not the same member as 3
+        assert!(!s.contains("03"));
+        assert!(s.is_intset());
+        assert_eq!(s.iter().collect::<Vec<_>>(), ["-1", "3"]);
+
+        assert!(s.insert("03"));
+        assert!(!s.is_intset());
+        assert!(s.contains("3") && s.contains("03"));
+        assert!(s.remove("-1"));
+        assert_eq!(s.len(), 2);
+
+        let mut big = Set::new();
+        for i in 0..=Set::MAX_INTSET_LEN {
+            big.insert(&i.to_string());
+        }
+        assert!(!big.is_intset());
+        assert_eq!(big.len(), Set::MAX_INTSET_LEN + 1);
+    }
+
+    #[test]
+    fn pop_random() {
+        let mut rng = rand::rng();
+        for mut s in [
+            ["1", "2", "3"].into_iter().collect::<Set>(),
+            ["a", "b", "c"].into_iter().collect::<Set>(),
+        ] {
+            let mut popped: Vec<String> = (0..3).filter_map(|_| s.pop_random(&mut rng)).collect();
+            popped.sort();
+            assert!(popped == ["1", "2", "3"] || popped == ["a", "b", "c"]);
+            assert!(s.is_empty());
+            assert_eq!(s.pop_random(&mut rng), None);
+        }
+    }
+}
@@ -0,0 +1,173 @@
+use std::{cmp::Ordering, collections::BTreeSet, ops::Bound};
+
+use crate::Dict;
+
+/// A set of strings ordered by a score, members with the same score
+/// ordered lexicographically.
+///
+/// Scores are looked up by member in a `Dict`, while the members are kept
+/// sorted by score in a `BTreeSet` for range queries, so every member is
+/// stored twice.
+#[derive(Debug, Default)]
+pub struct SortedSet {
+    scores: Dict<f64>,
+    order: BTreeSet<(Score, String)>,
+    /// Bytes used by the members
+    bytes: usize,
+}
+
+/// A score ordered with `f64::total_cmp`, NaN is never stored
+#[derive(Debug, Clone, Copy)]
+struct Score(f64);
+
+impl PartialEq for Score {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Score {}
+
+impl PartialOrd for Score {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Score {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+impl SortedSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+
+    /// Approximate number of heap bytes used by the sorted set
+    pub fn mem_usage(&self) -> usize {
+        let (primary, secondary) = self.scores.bucket_counts();
+        (primary + secondary) * size_of::<usize>() * 2
+            + self.len() * (2 * size_of::<String>() + 2 * size_of::<f64>())
+            + 2 * self.bytes
+    }
+
+    pub fn score(&self, member: &str) -> Option<f64> {
+        self.scores.peek(member).map(|e| *e.value())
+    }
+
+    /// Adds `member` or updates its score, returning the previous score
+    ///
+    /// # Panics
+    /// If `score` is NaN
+    pub fn insert(&mut self, member: &str, score: f64) -> Option<f64> {
+        assert!(!score.is_nan(), "sorted set scores can not be NaN");
+        let old = self.scores.insert(member, score);
+        match old {
+            Some(old) => {
+                self.order.remove(&(Score(old), member.to_string()));
+            }
+            None => self.bytes += member.len(),
+        }
+        self.order.insert((Score(score), member.to_string()));
+        old
+    }
+
+    /// Removes `member`, returning its score
+    pub fn remove(&mut self, member: &str) -> Option<f64> {
+        let score = *self.scores.remove(member)?.value();
+        self.order.remove(&(Score(score), member.to_string()));
+        self.bytes -= member.len();
+        Some(score)
+    }
+
+    /// The members ordered by score
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&str, f64)> {
+        self.order.iter().map(|(score, m)| (m.as_str(), score.0))
+    }
+
+    /// The members with a score in `min..max`, ordered by score
+    pub fn range_by_score(
+        &self,
+        min: f64,
+        max: f64,
+    ) -> impl DoubleEndedIterator<Item = (&str, f64)> {
+        // `BTreeSet::range` panics if `max` comes before `min`
+        let range = (min.total_cmp(&max) == Ordering::Less).then(|| {
+            let start = Bound::Included((Score(min), String::new()));
+            let end = Bound::Excluded((Score(max), String::new()));
+            self.order.range((start, end))
+        });
+        range
+            .into_iter()
+            .flatten()
+            .map(|(score, m)| (m.as_str(), score.0))
+    }
+}
+
+impl Clone for SortedSet {
+    fn clone(&self) -> Self {
+        let mut set = SortedSet::new();
+        for (member, score) in self.iter() {
+            set.insert(member, score);
+        }
+        set
+    }
+}
+
+impl PartialEq for SortedSet {
+    fn eq(&self, other: &Self) -> bool {
+        self.order == other.order
+    }
+}
+
+impl Eq for SortedSet {}
+
+#[cfg(test)]
+mod test {
+    use super::SortedSet;
+
+    #[test]
+    fn ordered_by_score_then_member() {
+        let mut set = SortedSet::new();
+        assert_eq!(set.insert("c", 2.0), None);
+        assert_eq!(set.insert("b", 1.0), None);
+        assert_eq!(set.insert("a", 2.0), None);
+        assert_eq!(set.insert("b", 3.0), Some(1.0));
+
+        let members: Vec<_> = set.iter().collect();
+        assert_eq!(members, [("a", 2.0), ("c", 2.0), ("b", 3.0)]);
+        assert_eq!(set.score("b"), Some(3.0));
+        assert_eq!(set.len(), 3);
+
+        assert_eq!(set.remove("a"), Some(2.0));
+        assert_eq!(set.remove("a"), None);
+        assert_eq!(set.score("a"), None);
+        assert_eq!(set.len(), 2);
+    }
+
+    #[test]
+    fn range_by_score() {
+        let mut set = SortedSet::new();
+        for i in 0..10 {
+            set.insert(&format!("m{i}"), i as f64);
+        }
+        let members: Vec<_> = set.range_by_score(3.0, 6.0).map(|(m, _)| m).collect();
+        assert_eq!(members, ["m3", "m4", "m5"]);
+        assert_eq!(set.range_by_score(6.0, 3.0).count(), 0);
+        assert_eq!(set.range_by_score(3.0, 3.0).count(), 0);
+        assert_eq!(
+            set.range_by_score(f64::NEG_INFINITY, f64::INFINITY).count(),
+            10
+        );
+    }
+}
@@ -0,0 +1,411 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    ops::RangeBounds,
+    str::FromStr,
+};
+
+/// Id of a stream entry, the milliseconds it was added at
+/// and a sequence number among the entries of the same millisecond
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StreamId {
+    pub ms: u64,
+    pub seq: u64,
+}
+
+impl StreamId {
+    pub const MIN: StreamId = StreamId { ms: 0, seq: 0 };
+    pub const MAX: StreamId = StreamId {
+        ms: u64::MAX,
+        seq: u64::MAX,
+    };
+
+    pub fn new(ms: u64, seq: u64) -> Self {
+        Self { ms, seq }
+    }
+
+    /// The id right after this one, `None` for `MAX`
+    pub fn next(self) -> Option<Self> {
+        match self.seq.checked_add(1) {
+            Some(seq) => Some(Self::new(self.ms, seq)),
+            None => Some(Self::new(self.ms.checked_add(1)?, 0)),
+        }
+    }
+
+    /// The id right before this one, `None` for `MIN`
+    pub fn prev(self) -> Option<Self> {
+        match self.seq.checked_sub(1) {
+            Some(seq) => Some(Self::new(self.ms, seq)),
+            None => Some(Self::new(self.ms.checked_sub(1)?, u64::MAX)),
+        }
+    }
+}
+
+impl fmt::Display for StreamId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
+    }
+}
+
+/// Parses `ms-seq`, or just `ms` meaning `ms-0`
+impl FromStr for StreamId {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('-') {
+            Some((ms, seq)) => Ok(Self::new(ms.parse()?, seq.parse()?)),
+            None => Ok(Self::new(s.parse()?, 0)),
+        }
+    }
+}
+
+/// The field value pairs of an entry
+pub type Fields = Vec<(String, Vec<u8>)>;
+
+/// An append only log of entries ordered by their ids, with consumer groups
+/// tracking which entries were delivered to whom and acknowledged
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Stream {
+    entries: BTreeMap<StreamId, Fields>,
+    /// Largest id ever added, even if that entry got deleted since
+    last_id: StreamId,
+    groups: BTreeMap<String, ConsumerGroup>,
+    /// Bytes used by the fields and values of the entries
+    bytes: usize,
+}
+
+impl Stream {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn last_id(&self) -> StreamId {
+        self.last_id
+    }
+
+    pub fn first_entry(&self) -> Option<(&StreamId, &Fields)> {
+        self.entries.first_key_value()
+    }
+
+    pub fn last_entry(&self) -> Option<(&StreamId, &Fields)> {
+        self.entries.last_key_value()
+    }
+
+    /// Approximate number of heap bytes used by the stream
+    pub fn mem_usage(&self) -> usize {
+        let pending: usize = self.groups.values().map(|g| g.pending.len()).sum();
+        self.bytes
+            + self.entries.len() * (size_of::<StreamId>() + size_of::<Fields>())
+            + pending * (size_of::<StreamId>() + size_of::<PendingEntry>())
+    }
+
+    /// The id of an entry added at `now_ms`, which is never before the last one,
+    /// so ids keep increasing even if the clock goes backwards.
+    /// `None` once the ids run out
+    pub fn next_id(&self, now_ms: u64) -> Option<StreamId> {
+        if now_ms > self.last_id.ms {
+            Some(StreamId::new(now_ms, 0))
+        } else {
+            self.last_id.next()
+        }
+    }
+
+    /// Appends an entry. `id` has to be larger than `last_id`,
+    /// returns `false` without adding it otherwise
+    pub fn add(&mut self, id: StreamId, fields: Fields) -> bool {
+        // 0-0 is never valid, as the last id of an empty stream
+        if id <= self.last_id {
+            return false;
+        }
+        self.bytes += fields_size(&fields);
+        self.entries.insert(id, fields);
+        self.last_id = id;
+        true
+    }
+
+    pub fn get(&self, id: &StreamId) -> Option<&Fields> {
+        self.entries.get(id)
+    }
+
+    pub fn range(
+        &self,
+        range: impl RangeBounds<StreamId>,
+    ) -> impl DoubleEndedIterator<Item = (&StreamId, &Fields)> {
+        self.entries.range(range)
+    }
+
+    /// Deletes an entry, it stays pending in the groups it was delivered by
+    pub fn remove(&mut self, id: &StreamId) -> bool {
+        match self.entries.remove(id) {
+            Some(fields) => {
+                self.bytes -= fields_size(&fields);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Deletes the oldest entries until at most `max_len` are left,
+    /// or `limit` were deleted. Returns the number of deleted entries
+    pub fn trim_max_len(&mut self, max_len: usize, limit: Option<usize>) -> usize {
+        let excess = self.len().saturating_sub(max_len);
+        self.trim_oldest(excess.min(limit.unwrap_or(usize::MAX)), |_| true)
+    }
+
+    /// Deletes the entries older than `min_id`, at most `limit` of them.
+    /// Returns the number of deleted entries
+    pub fn trim_min_id(&mut self, min_id: StreamId, limit: Option<usize>) -> usize {
+        self.trim_oldest(limit.unwrap_or(usize::MAX), |id| *id < min_id)
+    }
+
+    pub fn group(&self, name: &str) -> Option<&ConsumerGroup> {
+        self.groups.get(name)
+    }
+
+    pub fn group_mut(&mut self, name: &str) -> Option<&mut ConsumerGroup> {
+        self.groups.get_mut(name)
+    }
+
+    pub fn groups(&self) -> impl Iterator<Item = (&str, &ConsumerGroup)> {
+        self.groups.iter().map(|(name, g)| (name.as_str(), g))
+    }
+
+    /// Creates a group that delivers the entries after `last_delivered`,
+    /// returns `false` if it already exists
+    pub fn create_group(&mut self, name: &str, last_delivered: StreamId) -> bool {
+        if self.groups.contains_key(name) {
+            return false;
+        }
+        self.groups
+            .insert(name.to_string(), ConsumerGroup::new(last_delivered));
+        true
+    }
+
+    pub fn destroy_group(&mut self, name: &str) -> bool {
+        self.groups.remove(name).is_some()
+    }
+
+    fn trim_oldest(
+        &mut self,
+        limit: usize,
+        mut keep_going: impl FnMut(&StreamId) -> bool,
+    ) -> usize {
+        let mut deleted = 0;
+        while deleted < limit {
+            let Some(entry) = self.entries.first_entry() else {
+                break;
+            };
+            if !keep_going(entry.key()) {
+                break;
+            }
+            self.bytes -= fields_size(&entry.remove());
+            deleted += 1;
+        }
+        deleted
+    }
+}
+
+fn fields_size(fields: &Fields) -> usize {
+    fields.iter().map(|(f, v)| f.len() + v.len()).sum()
+}
+
+/// Delivers the entries of a stream among its consumers, remembering the
+/// delivered entries until they are acknowledged
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConsumerGroup {
+    /// Id of the last entry delivered to any consumer
+    pub last_delivered: StreamId,
+    /// Entries delivered but not acknowledged yet
+    pending: BTreeMap<StreamId, PendingEntry>,
+    consumers: BTreeMap<String, Consumer>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingEntry {
+    pub consumer: String,
+    /// When the entry was last delivered, in unix milliseconds
+    pub delivered_at: u64,
+    pub delivery_count: u64,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Consumer {
+    /// When the consumer last read or claimed something, in unix milliseconds
+    pub seen_at: u64,
+    pending: BTreeSet<StreamId>,
+}
+
+impl Consumer {
+    pub fn pending(&self) -> &BTreeSet<StreamId> {
+        &self.pending
+    }
+}
+
+impl ConsumerGroup {
+    fn new(last_delivered: StreamId) -> Self {
+        Self {
+            last_delivered,
+            pending: BTreeMap::new(),
+            consumers: BTreeMap::new(),
+        }
+    }
+
+    pub fn pending(&self) -> &BTreeMap<StreamId, PendingEntry> {
+        &self.pending
+    }
+
+    pub fn consumers(&self) -> &BTreeMap<String, Consumer> {
+        &self.consumers
+    }
+
+    /// Returns the consumer called `name`, creating it if needed
+    pub fn consumer(&mut self, name: &str, now_ms: u64) -> &mut Consumer {
+        let consumer = self.consumers.entry(name.to_string()).or_default();
+        consumer.seen_at = now_ms;
+        consumer
+    }
+
+    /// Creates a consumer, returns `false` if it already exists
+    pub fn create_consumer(&mut self, name: &str, now_ms: u64) -> bool {
+        if self.consumers.contains_key(name) {
+            return false;
+        }
+        self.consumer(name, now_ms);
+        true
+    }
+
+    /// Deletes a consumer and its pending entries, returning how many it had
+    pub fn delete_consumer(&mut self, name: &str) -> Option<usize> {
+        let consumer = self.consumers.remove(name)?;
+        for id in &consumer.pending {
+            self.pending.remove(id);
+        }
+        Some(consumer.pending.len())
+    }
+
+    /// Records that `id` was delivered to `consumer`, moving it over if
+    /// another consumer had it pending
+    pub fn deliver(&mut self, id: StreamId, consumer: &str, now_ms: u64) {
+        let count = match self.pending.remove(&id) {
+            Some(old) => {
+                if let Some(owner) = self.consumers.get_mut(&old.consumer) {
+                    owner.pending.remove(&id);
+                }
+                old.delivery_count + 1
+            }
+            None => 1,
+        };
+        self.set_pending(id, consumer, now_ms, count);
+    }
+
+    /// Hands a pending entry over to `consumer` without counting a delivery,
+    /// returns `false` if it isn't pending
+    pub fn claim(&mut self, id: StreamId, consumer: &str, now_ms: u64) -> bool {
+        let Some(old) = self.pending.remove(&id) else {
+            return false;
+        };
+        if let Some(owner) = self.consumers.get_mut(&old.consumer) {
+            owner.pending.remove(&id);
+        }
+        self.set_pending(id, consumer, now_ms, old.delivery_count);
+        true
+    }
+
+    /// Acknowledges an entry, returns `false` if it wasn't pending
+    pub fn ack(&mut self, id: &StreamId) -> bool {
+        let Some(entry) = self.pending.remove(id) else {
+            return false;
+        };
+        if let Some(owner) = self.consumers.get_mut(&entry.consumer) {
+            owner.pending.remove(id);
+        }
+        true
+    }
+
+    fn set_pending(&mut self, id: StreamId, consumer: &str, now_ms: u64, delivery_count: u64) {
+        self.consumer(consumer, now_ms).pending.insert(id);
+        self.pending.insert(
+            id,
+            PendingEntry {
+                consumer: consumer.to_string(),
+                delivered_at: now_ms,
+                delivery_count,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Stream, StreamId};
+
+    fn fields(value: &str) -> Vec<(String, Vec<u8>)> {
+        vec![("f".to_string(), value.as_bytes().to_vec())]
+    }
+
+    #[test]
+    fn ids_keep_increasing() {
+        let mut s = Stream::new();
+        assert!(!s.add(StreamId::MIN, fields("zero")));
+
+        let id = s.next_id(100).unwrap();
+        assert_eq!(id, StreamId::new(100, 0));
+        assert!(s.add(id, fields("a")));
+        // the clock went backwards
+        assert_eq!(s.next_id(50), Some(StreamId::new(100, 1)));
+        assert!(!s.add(StreamId::new(99, 5), fields("b")));
+        assert!(s.add(StreamId::new(200, 0), fields("b")));
+
+        assert!(s.remove(&StreamId::new(200, 0)));
+        assert_eq!(s.next_id(150), Some(StreamId::new(200, 1)));
+        assert_eq!("5".parse(), Ok(StreamId::new(5, 0)));
+        assert_eq!(StreamId::new(5, 0).prev(), Some(StreamId::new(4, u64::MAX)));
+    }
+
+    #[test]
+    fn trimming() {
+        let mut s = Stream::new();
+        for ms in 1..=10 {
+            s.add(StreamId::new(ms, 0), fields("x"));
+        }
+        assert_eq!(s.trim_max_len(8, None), 2);
+        assert_eq!(s.trim_max_len(0, Some(3)), 3);
+        assert_eq!(s.trim_min_id(StreamId::new(8, 0), None), 2);
+        assert_eq!(
+            s.first_entry().map(|(id, _)| *id),
+            Some(StreamId::new(8, 0))
+        );
+        assert_eq!(s.len(), 3);
+        assert_eq!(s.bytes, 6);
+    }
+
+    #[test]
+    fn groups_track_pending() {
+        let mut s = Stream::new();
+        assert!(s.create_group("g", StreamId::MIN));
+        assert!(!s.create_group("g", StreamId::MIN));
+
+        let g = s.group_mut("g").unwrap();
+        let (a, b) = (StreamId::new(1, 0), StreamId::new(2, 0));
+        g.deliver(a, "alice", 10);
+        g.deliver(b, "alice", 10);
+        g.deliver(a, "bob", 20);
+        assert_eq!(g.pending()[&a].delivery_count, 2);
+        assert_eq!(g.consumers()["alice"].pending().len(), 1);
+
+        assert!(g.claim(b, "bob", 30));
+        assert_eq!(g.pending()[&b].delivery_count, 1);
+        assert!(g.ack(&a));
+        assert!(!g.ack(&a));
+        assert_eq!(g.delete_consumer("bob"), Some(1));
+        assert!(g.pending().is_empty());
+    }
+}
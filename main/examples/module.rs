@@ -0,0 +1,44 @@
+//! A server with a module that adds a `HELLO.GREET name` command and logs
+//! every key written by clients.
+//!
+//! Run with `cargo run --example module -- --bind 127.0.0.1:6380`.
+
+use std::io;
+
+use log::info;
+use tcpserver::{
+    command_table::{CommandSpec, FAST, Keys, READONLY},
+    config::Config,
+    module::{Context, Module, ModuleError, Registry},
+    protocol::Reply,
+    server::Server,
+};
+
+struct Hello;
+
+impl Module for Hello {
+    fn name(&self) -> &str {
+        "hello"
+    }
+
+    fn load(&self, registry: &mut Registry) -> Result<(), ModuleError> {
+        registry.command(CommandSpec::module(
+            "hello.greet",
+            2,
+            READONLY | FAST,
+            Keys::None,
+            |_: &mut Context<'_>, args: &[String]| Reply::str(format!("Hello, {}!", args[0])),
+        ))?;
+        registry.on_keyspace_event(|e| info!("db {}: {} {}", e.db, e.event, e.key));
+        Ok(())
+    }
+}
+
+fn main() -> io::Result<()> {
+    env_logger::builder().init();
+
+    let config = Config::from_args(std::env::args().skip(1))
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+    Server::builder().config(config).module(Hello).run()?.join()
+}
@@ -0,0 +1,473 @@
+use std::collections::BTreeMap;
+
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::{
+    clients,
+    command_table::CommandSpec,
+    config::Config,
+    protocol::{Reply, ReplyFormat},
+    server::ServerState,
+    session::Session,
+    util::glob_match,
+};
+
+pub const DEFAULT_USER: &str = "default";
+
+const HELLO_NOAUTH: &str = "NOAUTH HELLO must be called with the client already authenticated, otherwise the HELLO AUTH <user> <pass> option can be used to authenticate the client at the same time";
+
+#[derive(Error, Debug)]
+pub enum AclError {
+    #[error("error in ACL rule '{0}'")]
+    InvalidRule(String),
+
+    #[error("invalid ACL user line '{0}'")]
+    InvalidUserLine(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum CommandRule {
+    All,
+    Category(String),
+    Command(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct User {
+    pub name: String,
+    enabled: bool,
+    nopass: bool,
+    /// Hex encoded sha256 hashes of the accepted passwords
+    password_hashes: Vec<String>,
+    /// Ordered allow/deny rules, the last matching one wins
+    command_rules: Vec<(bool, CommandRule)>,
+    key_patterns: Vec<String>,
+}
+
+impl User {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.into(),
+            enabled: false,
+            nopass: false,
+            password_hashes: Vec::new(),
+            command_rules: Vec::new(),
+            key_patterns: Vec::new(),
+        }
+    }
+
+    /// The user every connection starts as, unless a password is configured
+    fn default_user() -> Self {
+        let mut user = Self::new(DEFAULT_USER);
+        for rule in ["on", "nopass", "~*", "+@all"] {
+            user.apply(rule).unwrap();
+        }
+        user
+    }
+
+    /// Applies a single rule in the `ACL SETUSER` syntax
+    pub fn apply(&mut self, rule: &str) -> Result<(), AclError> {
+        let invalid = || AclError::InvalidRule(rule.into());
+
+        match rule {
+            "on" => self.enabled = true,
+            "off" => self.enabled = false,
+            "nopass" => {
+                self.nopass = true;
+                self.password_hashes.clear();
+            }
+            "resetpass" => {
+                self.nopass = false;
+                self.password_hashes.clear();
+            }
+            "allkeys" => self.key_patterns = vec!["*".into()],
+            "resetkeys" => self.key_patterns.clear(),
+            "allcommands" => self.command_rules = vec![(true, CommandRule::All)],
+            "nocommands" => self.command_rules.clear(),
+            "reset" => *self = Self::new(&self.name),
+            _ => match rule.split_at_checked(1).unwrap_or(("", rule)) {
+                (">", pass) => self.add_hash(hash_password(pass)),
+                ("#", hash) if hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit()) => {
+                    self.add_hash(hash.to_ascii_lowercase())
+                }
+                ("<", pass) => {
+                    let hash = hash_password(pass);
+                    self.password_hashes.retain(|h| *h != hash);
+                }
+                ("~", pattern) => self.key_patterns.push(pattern.into()),
+                (sign @ ("+" | "-"), target) if !target.is_empty() => {
+                    let cmd_rule = match target.strip_prefix('@') {
+                        Some("all") => CommandRule::All,
+                        Some(cat) => CommandRule::Category(cat.to_ascii_lowercase()),
+                        None => CommandRule::Command(target.to_ascii_lowercase()),
+                    };
+                    self.command_rules.push((sign == "+", cmd_rule));
+                }
+                _ => return Err(invalid()),
+            },
+        }
+        Ok(())
+    }
+
+    fn add_hash(&mut self, hash: String) {
+        self.nopass = false;
+        if !self.password_hashes.contains(&hash) {
+            self.password_hashes.push(hash);
+        }
+    }
+
+    pub fn check_password(&self, password: &str) -> bool {
+        self.enabled && (self.nopass || self.password_hashes.contains(&hash_password(password)))
+    }
+
+    pub fn can_run(&self, cmd: &CommandSpec) -> bool {
+        let cats = cmd.acl_categories();
+        let mut allowed = false;
+
+        for (allow, rule) in &self.command_rules {
+            let matches = match rule {
+                CommandRule::All => true,
+                CommandRule::Category(c) => cats.contains(&c.as_str()),
+                CommandRule::Command(c) => c == cmd.name,
+            };
+            if matches {
+                allowed = *allow;
+            }
+        }
+        allowed
+    }
+
+    pub fn can_access(&self, key: &str) -> bool {
+        self.key_patterns.iter().any(|p| glob_match(p, key))
+    }
+
+    /// Describes the user in the same syntax it can be configured with
+    pub fn describe(&self) -> String {
+        let mut parts = vec![
+            format!("user {}", self.name),
+            String::from(if self.enabled { "on" } else { "off" }),
+        ];
+        if self.nopass {
+            parts.push("nopass".into());
+        }
+        parts.extend(self.password_hashes.iter().map(|h| format!("#{h}")));
+        parts.extend(self.key_patterns.iter().map(|p| format!("~{p}")));
+        if self.command_rules.is_empty() {
+            parts.push("-@all".into());
+        }
+        for (allow, rule) in &self.command_rules {
+            let sign = if *allow { '+' } else { '-' };
+            parts.push(match rule {
+                CommandRule::All => format!("{sign}@all"),
+                CommandRule::Category(c) => format!("{sign}@{c}"),
+                CommandRule::Command(c) => format!("{sign}{c}"),
+            });
+        }
+        parts.join(" ")
+    }
+}
+
+pub fn hash_password(password: &str) -> String {
+    Sha256::digest(password.as_bytes())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+#[derive(Debug)]
+pub struct Acl {
+    users: BTreeMap<String, User>,
+}
+
+impl Acl {
+    pub fn user(&self, name: &str) -> Option<&User> {
+        self.users.get(name)
+    }
+
+    /// Creates or modifies `name` by applying `rules` in order
+    pub fn set_user<'a>(
+        &mut self,
+        name: &str,
+        rules: impl IntoIterator<Item = &'a str>,
+    ) -> Result<(), AclError> {
+        let mut user = match self.user(name) {
+            Some(u) => u.clone(),
+            None => User::new(name),
+        };
+        for rule in rules {
+            user.apply(rule)?;
+        }
+        self.users.insert(name.into(), user);
+        Ok(())
+    }
+
+    pub fn del_user(&mut self, name: &str) -> bool {
+        self.users.remove(name).is_some()
+    }
+
+    pub fn users(&self) -> impl Iterator<Item = &User> {
+        self.users.values()
+    }
+}
+
+impl Default for Acl {
+    fn default() -> Self {
+        let mut users = BTreeMap::new();
+        users.insert(DEFAULT_USER.into(), User::default_user());
+        Self { users }
+    }
+}
+
+impl Acl {
+    /// Loads the `user` lines and `requirepass` from the config
+    pub fn new(config: &Config) -> Result<Self, AclError> {
+        let mut acl = Self::default();
+
+        for line in &config.users {
+            let mut parts = line.split_whitespace();
+            let name = parts
+                .next()
+                .ok_or_else(|| AclError::InvalidUserLine(line.clone()))?;
+            acl.set_user(name, parts)?;
+        }
+
+        if let Some(pass) = &config.requirepass {
+            acl.set_user(DEFAULT_USER, ["resetpass", &format!(">{pass}")])?;
+        }
+        Ok(acl)
+    }
+
+    /// Logs in new connections as the default user, when it doesn't need a password
+    pub fn authenticate_default(&self, session: &mut Session) {
+        if self
+            .user(DEFAULT_USER)
+            .is_some_and(|u| u.enabled && u.nopass)
+        {
+            session.user = Some(DEFAULT_USER.into());
+        }
+    }
+
+    /// Checks whether `session` may run `cmd`, returning the error reply if not
+    pub fn check(
+        &self,
+        session: &Session,
+        spec: &CommandSpec,
+        cmd: &[String],
+    ) -> Result<(), Reply> {
+        let name = spec.name;
+        if matches!(name, "auth" | "hello") {
+            return Ok(());
+        }
+
+        let Some(user) = session.user.as_deref().and_then(|u| self.user(u)) else {
+            return Err(Reply::err("NOAUTH Authentication required."));
+        };
+
+        if !user.can_run(spec) {
+            return Err(Reply::err(format!(
+                "NOPERM User {} has no permissions to run the '{name}' command",
+                user.name
+            )));
+        }
+        if !spec.keys(cmd).iter().all(|k| user.can_access(k)) {
+            return Err(Reply::err("NOPERM No permissions to access a key"));
+        }
+        Ok(())
+    }
+}
+
+fn auth(acl: &Acl, session: &mut Session, user: &str, password: &str) -> Reply {
+    match acl.user(user) {
+        Some(u) if u.check_password(password) => {
+            session.user = Some(user.into());
+            Reply::ok()
+        }
+        _ => Reply::err("WRONGPASS invalid username-password pair or user is disabled."),
+    }
+}
+
+/// Handles `AUTH [username] password`
+pub fn auth_command(state: &mut ServerState, args: &[String], session: &mut Session) -> Reply {
+    match args {
+        [password] => auth(&state.acl, session, DEFAULT_USER, password),
+        [user, password] => auth(&state.acl, session, user, password),
+        _ => Reply::err("ERR wrong number of arguments for 'auth' command"),
+    }
+}
+
+/// Handles `HELLO [protover [AUTH username password] [SETNAME name]]`.
+/// Protocol 1 is the plain reply framing, 2 switches to typed replies
+pub fn hello_command(state: &mut ServerState, args: &[String], session: &mut Session) -> Reply {
+    let mut rest = args;
+    let mut format = session.format;
+    if let [protover, tail @ ..] = rest {
+        format = match protover.as_str() {
+            "1" => ReplyFormat::Plain,
+            "2" => ReplyFormat::Typed,
+            _ => return Reply::err("NOPROTO unsupported protocol version"),
+        };
+        rest = tail;
+    }
+
+    while !rest.is_empty() {
+        match rest {
+            [opt, user, pass, tail @ ..] if opt.eq_ignore_ascii_case("auth") => {
+                let reply = auth(&state.acl, session, user, pass);
+                if matches!(reply, Reply::Err(_)) {
+                    return reply;
+                }
+                rest = tail;
+            }
+            [opt, name, tail @ ..] if opt.eq_ignore_ascii_case("setname") => {
+                if session.user.is_none() {
+                    return Reply::err(HELLO_NOAUTH);
+                }
+                let setname = ["setname".into(), name.clone()];
+                let reply = clients::command(&mut state.clients, &setname, session);
+                if matches!(reply, Reply::Err(_)) {
+                    return reply;
+                }
+                rest = tail;
+            }
+            _ => return Reply::err("ERR syntax error in HELLO option"),
+        }
+    }
+
+    if session.user.is_none() {
+        return Reply::err(HELLO_NOAUTH);
+    }
+    session.format = format;
+    let proto = match format {
+        ReplyFormat::Plain => 1,
+        ReplyFormat::Typed => 2,
+    };
+
+    Reply::Arr(vec![
+        Reply::str("server"),
+        Reply::str("tcpserver"),
+        Reply::str("version"),
+        Reply::str(env!("CARGO_PKG_VERSION")),
+        Reply::str("proto"),
+        Reply::Int(proto),
+        Reply::str("id"),
+        Reply::Int(session.id as i64),
+        Reply::str("mode"),
+        Reply::str("standalone"),
+    ])
+}
+
+/// Handles `ACL WHOAMI | USERS | LIST | SETUSER name [rule ...] | DELUSER name [name ...]`
+pub fn acl_command(state: &mut ServerState, args: &[String], session: &mut Session) -> Reply {
+    let Some(sub) = args.first() else {
+        return Reply::err("ERR wrong number of arguments for 'acl' command");
+    };
+    let acl = &mut state.acl;
+
+    match (sub.to_ascii_lowercase().as_str(), &args[1..]) {
+        ("whoami", []) => match &session.user {
+            Some(user) => Reply::str(user),
+            None => Reply::Nil,
+        },
+        ("users", []) => Reply::Arr(acl.users().map(|u| Reply::str(&u.name)).collect()),
+        ("list", []) => Reply::Arr(acl.users().map(|u| Reply::str(u.describe())).collect()),
+        ("setuser", [name, rules @ ..]) => {
+            match acl.set_user(name, rules.iter().map(String::as_str)) {
+                Ok(()) => Reply::ok(),
+                Err(e) => Reply::err(format!("ERR {e}")),
+            }
+        }
+        ("deluser", names) if !names.is_empty() => {
+            let deleted = names
+                .iter()
+                .filter(|n| n.as_str() != DEFAULT_USER)
+                .filter(|n| acl.del_user(n))
+                .count();
+            Reply::Int(deleted as i64)
+        }
+        _ => Reply::err(format!(
+            "ERR unknown subcommand or wrong number of arguments for 'acl {sub}'"
+        )),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{command_table::CommandTable, net::PeerAddr};
+
+    fn user(rules: &str) -> User {
+        let mut u = User::new("u");
+        for r in rules.split_whitespace() {
+            u.apply(r).unwrap();
+        }
+        u
+    }
+
+    #[test]
+    fn passwords() {
+        let u = user("on >secret");
+        assert!(u.check_password("secret"));
+        assert!(!u.check_password("other"));
+
+        let u = user("off >secret");
+        assert!(!u.check_password("secret"));
+
+        let hash = hash_password("secret");
+        let u = user(&format!("on #{hash}"));
+        assert!(u.check_password("secret"));
+    }
+
+    #[test]
+    fn command_rules_last_match_wins() {
+        let commands = CommandTable::new();
+        let can_run = |u: &User, name| u.can_run(&commands.lookup(name).unwrap());
+
+        let u = user("on +@all -@dangerous +client");
+        assert!(can_run(&u, "get"));
+        assert!(!can_run(&u, "monitor"));
+        assert!(can_run(&u, "client"));
+
+        let u = user("on +@read");
+        assert!(can_run(&u, "get"));
+        assert!(!can_run(&u, "set"));
+    }
+
+    #[test]
+    fn hello_switches_reply_format() {
+        let config = Config {
+            requirepass: Some("secret".into()),
+            ..Config::default()
+        };
+        let mut state = ServerState::new(&config).unwrap();
+        let mut session = Session::new(1, PeerAddr::Unix(Default::default()));
+        let mut hello = |args: &[&str], session: &mut Session| {
+            hello_command(&mut state, &crate::commands::args(args), session)
+        };
+
+        assert_eq!(hello(&["2"], &mut session), Reply::err(HELLO_NOAUTH));
+        assert_eq!(session.format, ReplyFormat::Plain);
+        assert!(matches!(
+            hello(&["3"], &mut session),
+            Reply::Err(e) if e.starts_with("NOPROTO")
+        ));
+
+        let Reply::Arr(fields) = hello(&["2", "auth", "default", "secret"], &mut session) else {
+            panic!("HELLO should reply with the server properties");
+        };
+        assert_eq!(fields[4..6], [Reply::str("proto"), Reply::Int(2)]);
+        assert_eq!(session.format, ReplyFormat::Typed);
+
+        hello(&["1"], &mut session);
+        assert_eq!(session.format, ReplyFormat::Plain);
+    }
+
+    #[test]
+    fn key_patterns() {
+        let u = user("on ~cache:* ~session:?");
+        assert!(u.can_access("cache:foo"));
+        assert!(u.can_access("session:1"));
+        assert!(!u.can_access("session:12"));
+        assert!(!u.can_access("other"));
+    }
+}
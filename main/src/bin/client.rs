@@ -1,52 +1,276 @@
-use std::env::args;
-use std::io::{Read, Write};
-use std::net::TcpStream;
-use std::time::Duration;
-use tcpserver::protocol;
+use std::{
+    env::{self, args},
+    error::Error,
+    io::{self, BufRead, IsTerminal},
+    path::PathBuf,
+    process::ExitCode,
+    thread,
+    time::Duration,
+};
 
-fn write_message(sock: &mut TcpStream, cmds: Vec<String>) -> std::io::Result<()> {
-    let cap = cmds.len() as u32;
-    if cap as usize > protocol::MAX_ARGS {
-        panic!("to many args")
-    }
+use rustyline::{DefaultEditor, error::ReadlineError};
+use tcpserver::{
+    cli::{OutputMode, format_reply, split_line},
+    client::{Client, ClientError},
+    protocol::Reply,
+};
+
+const USAGE: &str = "\
+usage: client [options] [command [arg ...]]
+
+Without a command, starts an interactive shell. Prefixing a command
+with a number N in the shell runs it N times.
+
+options:
+  -h <host>      server host (default 127.0.0.1)
+  -p <port>      server port (default 8080)
+  -n <db>        database number
+  -a <password>  password to authenticate with
+  --user <name>  user to authenticate as
+  -r <count>     run the command count times, -1 runs it forever
+  -i <secs>      wait secs between repeated commands, can be fractional
+  --raw          print values only
+  --json         print replies as json
+  --csv          print replies as comma separated values
+  --help         show this message";
 
-    let mut buf = Vec::with_capacity(cap as usize);
+struct Options {
+    host: String,
+    port: u16,
+    db: usize,
+    user: Option<String>,
+    password: Option<String>,
+    repeat: i64,
+    interval: Duration,
+    mode: OutputMode,
+    command: Vec<String>,
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut options = Self {
+            host: "127.0.0.1".into(),
+            port: 8080,
+            db: 0,
+            user: None,
+            password: None,
+            repeat: 1,
+            interval: Duration::ZERO,
+            mode: OutputMode::default(),
+            command: Vec::new(),
+        };
 
-    buf.extend_from_slice(&cap.to_be_bytes());
-    for c in cmds {
-        buf.extend_from_slice(&(c.len() as u32).to_be_bytes());
-        buf.extend_from_slice(c.as_bytes());
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("missing value for {arg}"));
+            match arg.as_str() {
+                "-h" => options.host = value()?,
+                "-p" => options.port = parse(&arg, value()?)?,
+                "-n" => options.db = parse(&arg, value()?)?,
+                "-a" => options.password = Some(value()?),
+                "--user" => options.user = Some(value()?),
+                "-r" => options.repeat = parse(&arg, value()?)?,
+                "-i" => {
+                    let secs: f64 = parse(&arg, value()?)?;
+                    options.interval = Duration::try_from_secs_f64(secs)
+                        .map_err(|_| format!("invalid value for {arg}"))?;
+                }
+                "--raw" => options.mode = OutputMode::Raw,
+                "--json" => options.mode = OutputMode::Json,
+                "--csv" => options.mode = OutputMode::Csv,
+                "--help" => return Err(USAGE.into()),
+                _ if arg.starts_with('-') && options.command.is_empty() => {
+                    return Err(format!("unknown option {arg}\n\n{USAGE}"));
+                }
+                _ => {
+                    options.command.push(arg);
+                    options.command.extend(args);
+                    break;
+                }
+            }
+        }
+        Ok(options)
     }
+}
+
+fn parse<T: std::str::FromStr>(option: &str, value: String) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value for {option}: {value}"))
+}
 
-    sock.write_all(&buf)
+/// A connection that remembers what to restore when it has to reconnect
+struct Session {
+    options: Options,
+    client: Client,
+    /// Database selected with `SELECT`, shown in the prompt
+    db: usize,
 }
 
-fn read_message(sock: &mut TcpStream) -> std::io::Result<(u32, String)> {
-    let mut buf = [0u8; 1024];
-    let n = sock.read(&mut buf)?;
+impl Session {
+    fn connect(options: Options) -> Result<Self, ClientError> {
+        let client = Client::connect((options.host.as_str(), options.port))?;
+        let db = options.db;
+        let mut session = Self {
+            options,
+            client,
+            db,
+        };
+        session.setup()?;
+        Ok(session)
+    }
+
+    fn setup(&mut self) -> Result<(), ClientError> {
+        if let Some(password) = &self.options.password {
+            let user = self.options.user.as_deref().unwrap_or("default");
+            self.client.auth(user, password)?;
+        }
+        if self.db != 0 {
+            self.client.select(self.db)?;
+        }
+        Ok(())
+    }
+
+    fn reconnect(&mut self) -> Result<(), ClientError> {
+        self.client = Client::connect((self.options.host.as_str(), self.options.port))?;
+        self.setup()
+    }
 
-    let numbuf = &buf[..4];
-    let resp_len = u32::from_be_bytes([numbuf[0], numbuf[1], numbuf[2], numbuf[3]]) as usize;
-    assert_eq!(resp_len, n);
+    fn prompt(&self) -> String {
+        let addr = format!("{}:{}", self.options.host, self.options.port);
+        match self.db {
+            0 => format!("{addr}> "),
+            db => format!("{addr}[{db}]> "),
+        }
+    }
 
-    let numbuf = &buf[4..8];
-    let status_code = u32::from_be_bytes([numbuf[0], numbuf[1], numbuf[2], numbuf[3]]);
+    /// Runs `args` `times` times, forever if negative, printing every reply
+    fn run(&mut self, args: &[String], times: i64) -> Result<(), ClientError> {
+        let mut n = 0;
+        while times < 0 || n < times {
+            if n > 0 && !self.options.interval.is_zero() {
+                thread::sleep(self.options.interval);
+            }
+            if self.client.is_broken() {
+                self.reconnect()?;
+            }
+            let reply = self.client.query(args)?;
+            self.print(&reply);
+            n += 1;
 
-    let str = std::str::from_utf8(&buf[8..resp_len]).unwrap().to_string();
+            let name = args[0].to_ascii_lowercase();
+            if matches!(reply, Reply::Err(_)) {
+                continue;
+            }
+            if name == "select" {
+                self.db = args[1].parse().unwrap_or(self.db);
+            } else if name == "auth" {
+                // the server refused typed replies until now
+                self.client.hello(None)?;
+            } else if name == "monitor" {
+                loop {
+                    let reply = self.client.read_reply()?;
+                    self.print(&reply);
+                }
+            }
+        }
+        Ok(())
+    }
 
-    Ok((status_code, str))
+    fn print(&self, reply: &Reply) {
+        println!("{}", format_reply(reply, self.options.mode));
+    }
+
+    /// Runs one line typed in the shell or piped to stdin, returning
+    /// false once the user asked to quit
+    fn run_line(&mut self, line: &str) -> bool {
+        let args = match split_line(line) {
+            Ok(args) => args,
+            Err(e) => {
+                eprintln!("(error) invalid argument(s): {e}");
+                return true;
+            }
+        };
+        let (times, args) = match args.split_first() {
+            None => return true,
+            Some((first, _)) if first == "quit" || first == "exit" => return false,
+            Some((first, rest)) if !rest.is_empty() => match first.parse() {
+                Ok(times) => (times, rest),
+                Err(_) => (1, &args[..]),
+            },
+            Some(_) => (1, &args[..]),
+        };
+        if let Err(e) = self.run(args, times) {
+            eprintln!("(error) {e}");
+        }
+        true
+    }
 }
 
-fn main() -> std::io::Result<()> {
-    let mut sock = TcpStream::connect("127.0.0.1:8080")?;
-    sock.set_read_timeout(Some(Duration::from_secs(10)))?;
-    sock.set_write_timeout(Some(Duration::from_secs(10)))?;
+fn history_path() -> Option<PathBuf> {
+    env::var_os("HOME").map(|home| PathBuf::from(home).join(".tcpserver_history"))
+}
 
-    let cmds: Vec<String> = args().skip(1).map(|s| s.trim().to_string()).collect();
+fn repl(session: &mut Session) -> Result<(), Box<dyn Error>> {
+    let mut editor = DefaultEditor::new()?;
+    let history = history_path();
+    if let Some(path) = &history {
+        let _ = editor.load_history(path);
+    }
 
-    write_message(&mut sock, cmds)?;
-    let resp = read_message(&mut sock)?;
-    println!("{:?}", resp);
+    loop {
+        match editor.readline(&session.prompt()) {
+            Ok(line) => {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let _ = editor.add_history_entry(&line);
+                if !session.run_line(&line) {
+                    break;
+                }
+            }
+            Err(ReadlineError::Interrupted | ReadlineError::Eof) => break,
+            Err(e) => return Err(e.into()),
+        }
+    }
 
+    if let Some(path) = &history {
+        editor.save_history(path)?;
+    }
     Ok(())
 }
+
+fn try_main(mut options: Options) -> Result<(), Box<dyn Error>> {
+    let command = std::mem::take(&mut options.command);
+    let repeat = options.repeat;
+    let mut session = Session::connect(options)?;
+
+    if !command.is_empty() {
+        session.run(&command, repeat)?;
+    } else if io::stdin().is_terminal() {
+        repl(&mut session)?;
+    } else {
+        for line in io::stdin().lock().lines() {
+            if !session.run_line(&line?) {
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let options = match Options::parse(args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    match try_main(options) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("(error) {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
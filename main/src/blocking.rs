@@ -0,0 +1,424 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    commands::{
+        SYNTAX_ERROR,
+        list::{self, End},
+        wrong_arity,
+    },
+    module::Modules,
+    protocol::Reply,
+    server::ServerState,
+    session::Session,
+    storage::Db,
+};
+
+/// Clients waiting for a list to be pushed to, per server
+#[derive(Debug, Default)]
+pub struct Blocked {
+    waiters: HashMap<u64, Waiter>,
+}
+
+/// The side of blocking that lives in every database
+#[derive(Debug, Default)]
+pub struct WaitQueues {
+    /// Ids of the clients waiting for each key, in the order they blocked
+    by_key: HashMap<String, VecDeque<u64>>,
+    /// Keys pushed to since they were last served, that somebody waits for
+    ready: VecDeque<String>,
+}
+
+#[derive(Debug, Clone)]
+struct Waiter {
+    /// Index of the database the keys are in
+    db: usize,
+    keys: Vec<String>,
+    op: Op,
+    deadline: Option<Instant>,
+}
+
+/// What to do with the first key that gets an element
+#[derive(Debug, Clone)]
+enum Op {
+    Pop(End),
+    Move { dst: String, from: End, to: End },
+}
+
+impl Op {
+    /// The command that blocked
+    fn name(&self) -> &'static str {
+        match self {
+            Op::Pop(End::Left) => "blpop",
+            Op::Pop(End::Right) => "brpop",
+            Op::Move { .. } => "blmove",
+        }
+    }
+}
+
+impl Blocked {
+    fn remove(&mut self, dbs: &mut [Db], id: u64) -> Option<Waiter> {
+        let waiter = self.waiters.remove(&id)?;
+        let queues = &mut dbs[waiter.db].waiting;
+        for key in &waiter.keys {
+            if let Some(queue) = queues.by_key.get_mut(key) {
+                queue.retain(|&waiting| waiting != id);
+                if queue.is_empty() {
+                    queues.by_key.remove(key);
+                }
+            }
+        }
+        Some(waiter)
+    }
+
+    fn block(&mut self, db: &mut Db, session: &mut Session, waiter: Waiter) {
+        for key in &waiter.keys {
+            db.waiting
+                .by_key
+                .entry(key.clone())
+                .or_default()
+                .push_back(session.id);
+        }
+        self.waiters.insert(session.id, waiter);
+        session.blocked = true;
+    }
+
+    /// Forgets about a client that disconnected while blocked
+    pub fn unblock(&mut self, dbs: &mut [Db], session: &mut Session) {
+        if session.blocked {
+            self.remove(dbs, session.id);
+            session.blocked = false;
+        }
+    }
+
+    /// Serves the clients waiting for keys that got pushed to, the ones
+    /// that blocked first are served first. Returns the ids of the served
+    /// clients with their replies
+    pub fn serve_ready(&mut self, dbs: &mut [Db], modules: &Modules) -> Vec<(u64, Reply)> {
+        let mut served = Vec::new();
+
+        while let Some((id, index, key, op)) = self.next_ready(dbs) {
+            let name = op.name();
+            let db = &mut dbs[index];
+            // the waiter is gone already, so pushes below can't serve it twice
+            let reply = match op {
+                Op::Pop(end) => match list::pop(db, &key, end, 1) {
+                    Ok(Some(mut popped)) if !popped.is_empty() => {
+                        Reply::Arr(vec![Reply::str(&key), Reply::Str(popped.remove(0))])
+                    }
+                    Ok(_) => Reply::Nil,
+                    Err(e) => e,
+                },
+                Op::Move { dst, from, to } => list::move_element(db, &key, &dst, from, to),
+            };
+            match reply {
+                Reply::Err(_) => modules.discard_keyspace_events(dbs),
+                _ => modules.notify_keyspace_events(dbs, name),
+            }
+            served.push((id, reply));
+        }
+        served
+    }
+
+    /// Pops the first client waiting for a ready key that has elements
+    fn next_ready(&mut self, dbs: &mut [Db]) -> Option<(u64, usize, String, Op)> {
+        for index in 0..dbs.len() {
+            while let Some(key) = dbs[index].waiting.ready.front().cloned() {
+                let db = &mut dbs[index];
+                let first = db
+                    .waiting
+                    .by_key
+                    .get(&key)
+                    .and_then(|queue| queue.front().copied());
+                let has_elements = db
+                    .get(&key)
+                    .and_then(|obj| obj.value.as_list())
+                    .is_some_and(|list| !list.is_empty());
+
+                match first {
+                    Some(id) if has_elements => {
+                        let waiter = self.remove(dbs, id)?;
+                        return Some((id, index, key, waiter.op));
+                    }
+                    _ => {
+                        db.waiting.ready.pop_front();
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// Unblocks the clients whose timeout passed, returning their ids
+    pub fn take_timed_out(&mut self, dbs: &mut [Db], now: Instant) -> Vec<u64> {
+        let ids: Vec<u64> = self
+            .waiters
+            .iter()
+            .filter(|(_, w)| w.deadline.is_some_and(|d| d <= now))
+            .map(|(&id, _)| id)
+            .collect();
+
+        for id in &ids {
+            self.remove(dbs, *id);
+        }
+        ids
+    }
+
+    /// Time until the closest timeout, so the event loop can wake up for it
+    pub fn next_timeout(&self, now: Instant) -> Option<Duration> {
+        self.waiters
+            .values()
+            .filter_map(|w| w.deadline)
+            .min()
+            .map(|d| d.saturating_duration_since(now))
+    }
+}
+
+/// Handles `BLPOP key [key ...] timeout`
+pub fn blpop(state: &mut ServerState, args: &[String], session: &mut Session) -> Reply {
+    bpop(state, args, session, End::Left, "blpop")
+}
+
+/// Handles `BRPOP key [key ...] timeout`
+pub fn brpop(state: &mut ServerState, args: &[String], session: &mut Session) -> Reply {
+    bpop(state, args, session, End::Right, "brpop")
+}
+
+/// Handles `BLMOVE source destination LEFT|RIGHT LEFT|RIGHT timeout`
+pub fn blmove(state: &mut ServerState, args: &[String], session: &mut Session) -> Reply {
+    let [src, dst, from, to, timeout] = args else {
+        return wrong_arity("blmove");
+    };
+    let deadline = match parse_timeout(timeout) {
+        Ok(deadline) => deadline,
+        Err(e) => return e,
+    };
+
+    let (Some(from), Some(to)) = (End::parse(from), End::parse(to)) else {
+        return Reply::err(SYNTAX_ERROR);
+    };
+
+    let db = &mut state.dbs[session.db];
+    let reply = list::move_element(db, src, dst, from, to);
+    if reply != Reply::Nil {
+        return reply;
+    }
+    state.blocked.block(
+        db,
+        session,
+        Waiter {
+            db: session.db,
+            keys: vec![src.clone()],
+            op: Op::Move {
+                dst: dst.clone(),
+                from,
+                to,
+            },
+            deadline,
+        },
+    );
+    Reply::Nil
+}
+
+fn bpop(
+    state: &mut ServerState,
+    args: &[String],
+    session: &mut Session,
+    end: End,
+    name: &str,
+) -> Reply {
+    let [keys @ .., timeout] = args else {
+        return wrong_arity(name);
+    };
+    if keys.is_empty() {
+        return wrong_arity(name);
+    }
+    let deadline = match parse_timeout(timeout) {
+        Ok(deadline) => deadline,
+        Err(e) => return e,
+    };
+
+    let db = &mut state.dbs[session.db];
+    for key in keys {
+        match list::pop(db, key, end, 1) {
+            Ok(Some(mut popped)) => {
+                return Reply::Arr(vec![Reply::str(key), Reply::Str(popped.remove(0))]);
+            }
+            Ok(None) => {}
+            Err(e) => return e,
+        }
+    }
+
+    state.blocked.block(
+        db,
+        session,
+        Waiter {
+            db: session.db,
+            keys: keys.to_vec(),
+            op: Op::Pop(end),
+            deadline,
+        },
+    );
+    Reply::Nil
+}
+
+/// Parses a timeout in seconds, 0 meaning forever
+fn parse_timeout(timeout: &str) -> Result<Option<Instant>, Reply> {
+    let secs = match timeout.parse::<f64>() {
+        Ok(secs) if secs.is_finite() => secs,
+        _ => return Err(Reply::err("ERR timeout is not a float or out of range")),
+    };
+    if secs < 0.0 {
+        return Err(Reply::err("ERR timeout is negative"));
+    }
+    if secs == 0.0 {
+        return Ok(None);
+    }
+    Duration::try_from_secs_f64(secs)
+        .ok()
+        .and_then(|d| Instant::now().checked_add(d))
+        .map(Some)
+        .ok_or_else(|| Reply::err("ERR timeout is out of range"))
+}
+
+/// Marks `key` as ready to serve, if anybody waits for it
+pub fn signal(db: &mut Db, key: &str) {
+    let queues = &mut db.waiting;
+    if queues.by_key.contains_key(key) && !queues.ready.iter().any(|k| k == key) {
+        queues.ready.push_back(key.to_string());
+    }
+}
+
+/// Marks every key waited for in `db` as ready,
+/// for when its keys got replaced all at once
+pub fn signal_db(db: &mut Db) {
+    let queues = &mut db.waiting;
+    let mut keys: Vec<String> = queues.by_key.keys().cloned().collect();
+    keys.sort();
+    for key in keys {
+        if !queues.ready.contains(&key) {
+            queues.ready.push_back(key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{client::Client, commands::args, config::Config, net::PeerAddr, server::Server};
+
+    fn sessions(ids: &[u64]) -> Vec<Session> {
+        let addr = PeerAddr::Tcp(([127, 0, 0, 1], 0).into());
+        ids.iter()
+            .map(|&id| Session::new(id, addr.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn served_in_fifo_order() {
+        let mut state = ServerState::new(&Config::default()).unwrap();
+        let addr = PeerAddr::Tcp(([127, 0, 0, 1], 0).into());
+        let (mut first, mut second) = (Session::new(1001, addr.clone()), Session::new(1002, addr));
+
+        assert_eq!(
+            blpop(&mut state, &args(&["fifo", "0"]), &mut first),
+            Reply::Nil
+        );
+        assert_eq!(
+            brpop(&mut state, &args(&["other", "fifo", "0"]), &mut second),
+            Reply::Nil
+        );
+        assert!(first.blocked && second.blocked);
+
+        list::rpush(&mut state.dbs[0], &args(&["fifo", "a", "b", "c"]));
+        let served = state.blocked.serve_ready(&mut state.dbs, &state.modules);
+        let pair = |k: &str, v: &str| Reply::Arr(vec![Reply::str(k), Reply::str(v)]);
+        assert_eq!(
+            served,
+            [(1001, pair("fifo", "a")), (1002, pair("fifo", "c"))]
+        );
+        assert!(
+            state
+                .blocked
+                .serve_ready(&mut state.dbs, &state.modules)
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn wakes_clients_one_push_at_a_time() {
+        let mut state = ServerState::new(&Config::default()).unwrap();
+        for session in &mut sessions(&[1, 2, 3]) {
+            blpop(&mut state, &args(&["q", "0"]), session);
+        }
+
+        let mut order = Vec::new();
+        for value in ["a", "b", "c"] {
+            list::rpush(&mut state.dbs[0], &args(&["q", value]));
+            for (id, reply) in state.blocked.serve_ready(&mut state.dbs, &state.modules) {
+                order.push((id, reply));
+            }
+        }
+        let popped = |v: &str| Reply::Arr(vec![Reply::str("q"), Reply::str(v)]);
+        assert_eq!(
+            order,
+            [(1, popped("a")), (2, popped("b")), (3, popped("c"))]
+        );
+        assert_eq!(state.blocked.next_timeout(Instant::now()), None);
+    }
+
+    #[test]
+    fn times_out() {
+        let mut state = ServerState::new(&Config::default()).unwrap();
+        let mut sessions = sessions(&[1, 2]);
+        let now = Instant::now();
+        blpop(&mut state, &args(&["q", "0.05"]), &mut sessions[0]);
+        blpop(&mut state, &args(&["q", "0"]), &mut sessions[1]);
+
+        let timeout = state.blocked.next_timeout(Instant::now()).unwrap();
+        assert!(timeout <= Duration::from_millis(50));
+        assert!(state.blocked.take_timed_out(&mut state.dbs, now).is_empty());
+        let later = now + Duration::from_secs(1);
+        assert_eq!(state.blocked.take_timed_out(&mut state.dbs, later), [1]);
+        assert_eq!(state.blocked.next_timeout(later), None);
+
+        // only the client without a timeout is left to serve
+        list::rpush(&mut state.dbs[0], &args(&["q", "a", "b"]));
+        let served = state.blocked.serve_ready(&mut state.dbs, &state.modules);
+        assert_eq!(served.iter().map(|(id, _)| *id).collect::<Vec<_>>(), [2]);
+    }
+
+    #[test]
+    fn forgets_clients_that_disconnect() {
+        let mut state = ServerState::new(&Config::default()).unwrap();
+        let mut sessions = sessions(&[1, 2]);
+        blpop(&mut state, &args(&["q", "0"]), &mut sessions[0]);
+        blpop(&mut state, &args(&["q", "0"]), &mut sessions[1]);
+
+        state.blocked.unblock(&mut state.dbs, &mut sessions[0]);
+        assert!(!sessions[0].blocked);
+        list::rpush(&mut state.dbs[0], &args(&["q", "a", "b"]));
+        let served = state.blocked.serve_ready(&mut state.dbs, &state.modules);
+        assert_eq!(served.iter().map(|(id, _)| *id).collect::<Vec<_>>(), [2]);
+        assert_eq!(list::llen(&mut state.dbs[0], &args(&["q"])), Reply::Int(1));
+
+        state.blocked.unblock(&mut state.dbs, &mut sessions[1]);
+        assert!(state.dbs[0].waiting.by_key.is_empty());
+    }
+
+    #[test]
+    fn replies_nil_on_timeout_over_the_network() {
+        let server = Server::builder()
+            .bind("127.0.0.1:0".parse().unwrap())
+            .run()
+            .unwrap();
+        let mut client = Client::connect(server.addr().unwrap()).unwrap();
+
+        let start = Instant::now();
+        assert_eq!(client.query(&["blpop", "q", "0.05"]).unwrap(), Reply::Nil);
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert_eq!(client.dbsize().unwrap(), 0);
+    }
+}
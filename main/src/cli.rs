@@ -0,0 +1,308 @@
+//! Line parsing and reply formatting for the interactive client.
+
+use std::fmt::Write;
+
+use thiserror::Error;
+
+use crate::protocol::Reply;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum LineError {
+    #[error("unbalanced quotes")]
+    UnbalancedQuotes,
+
+    #[error("closing quote must be followed by a space")]
+    TrailingQuote,
+
+    #[error("argument is not valid utf-8")]
+    InvalidUtf8,
+}
+
+/// Splits a line into arguments. Double quoted arguments understand
+/// `\n`, `\r`, `\t`, `\"`, `\\` and `\xHH` escapes, single quoted ones
+/// only `\'`
+pub fn split_line(line: &str) -> Result<Vec<String>, LineError> {
+    let mut args = Vec::new();
+    let mut chars = line.chars().peekable();
+
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let Some(&first) = chars.peek() else {
+            return Ok(args);
+        };
+
+        let mut arg = Vec::new();
+        match first {
+            '"' => {
+                chars.next();
+                loop {
+                    match chars.next().ok_or(LineError::UnbalancedQuotes)? {
+                        '"' => break,
+                        '\\' => match chars.next().ok_or(LineError::UnbalancedQuotes)? {
+                            'n' => arg.push(b'\n'),
+                            'r' => arg.push(b'\r'),
+                            't' => arg.push(b'\t'),
+                            'x' => {
+                                let hex: String = chars.clone().take(2).collect();
+                                match u8::from_str_radix(&hex, 16) {
+                                    Ok(byte) if hex.len() == 2 => {
+                                        arg.push(byte);
+                                        chars.nth(1);
+                                    }
+                                    _ => arg.push(b'x'),
+                                }
+                            }
+                            c => push_char(&mut arg, c),
+                        },
+                        c => push_char(&mut arg, c),
+                    }
+                }
+                closing_quote(chars.peek())?;
+            }
+            '\'' => {
+                chars.next();
+                loop {
+                    match chars.next().ok_or(LineError::UnbalancedQuotes)? {
+                        '\'' => break,
+                        '\\' if chars.peek() == Some(&'\'') => {
+                            chars.next();
+                            arg.push(b'\'');
+                        }
+                        c => push_char(&mut arg, c),
+                    }
+                }
+                closing_quote(chars.peek())?;
+            }
+            _ => {
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                    push_char(&mut arg, c);
+                }
+            }
+        }
+        args.push(String::from_utf8(arg).map_err(|_| LineError::InvalidUtf8)?);
+    }
+}
+
+fn push_char(arg: &mut Vec<u8>, c: char) {
+    arg.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+}
+
+fn closing_quote(next: Option<&char>) -> Result<(), LineError> {
+    match next {
+        Some(c) if !c.is_whitespace() => Err(LineError::TrailingQuote),
+        _ => Ok(()),
+    }
+}
+
+/// How replies get printed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputMode {
+    /// Annotated with the type of every reply, arrays numbered
+    #[default]
+    Pretty,
+    /// Values only, one per line, for scripts
+    Raw,
+    Json,
+    Csv,
+}
+
+/// Formats `reply` for printing, without a trailing newline
+pub fn format_reply(reply: &Reply, mode: OutputMode) -> String {
+    let mut out = String::new();
+    match mode {
+        OutputMode::Pretty => pretty(reply, 0, &mut out),
+        OutputMode::Raw => raw(reply, &mut out),
+        OutputMode::Json => json(reply, &mut out),
+        OutputMode::Csv => csv(reply, &mut out),
+    }
+    out
+}
+
+fn pretty(reply: &Reply, indent: usize, out: &mut String) {
+    match reply {
+        Reply::Nil => out.push_str("(nil)"),
+        Reply::Err(e) => {
+            let _ = write!(out, "(error) {e}");
+        }
+        Reply::Str(s) => quote(s, out),
+        Reply::Int(n) => {
+            let _ = write!(out, "(integer) {n}");
+        }
+        Reply::Dbl(n) => {
+            let _ = write!(out, "(double) {n}");
+        }
+        Reply::Arr(items) if items.is_empty() => out.push_str("(empty array)"),
+        Reply::Arr(items) => {
+            let width = items.len().to_string().len();
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push('\n');
+                    out.push_str(&" ".repeat(indent));
+                }
+                let _ = write!(out, "{:>width$}) ", i + 1);
+                pretty(item, indent + width + 2, out);
+            }
+        }
+    }
+}
+
+/// Double quotes `s`, escaping what isn't printable ascii
+fn quote(s: &[u8], out: &mut String) {
+    out.push('"');
+    for &b in s {
+        match b {
+            b'"' => out.push_str("\\\""),
+            b'\\' => out.push_str("\\\\"),
+            b'\n' => out.push_str("\\n"),
+            b'\r' => out.push_str("\\r"),
+            b'\t' => out.push_str("\\t"),
+            b' '..=b'~' => out.push(b as char),
+            _ => {
+                let _ = write!(out, "\\x{b:02x}");
+            }
+        }
+    }
+    out.push('"');
+}
+
+fn raw(reply: &Reply, out: &mut String) {
+    match reply {
+        Reply::Nil => {}
+        Reply::Err(e) => out.push_str(e),
+        Reply::Str(s) => out.push_str(&String::from_utf8_lossy(s)),
+        Reply::Int(n) => {
+            let _ = write!(out, "{n}");
+        }
+        Reply::Dbl(n) => {
+            let _ = write!(out, "{n}");
+        }
+        Reply::Arr(items) => {
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push('\n');
+                }
+                raw(item, out);
+            }
+        }
+    }
+}
+
+fn json(reply: &Reply, out: &mut String) {
+    match reply {
+        Reply::Nil => out.push_str("null"),
+        Reply::Err(e) => {
+            out.push_str("{\"error\":");
+            json_string(e, out);
+            out.push('}');
+        }
+        Reply::Str(s) => json_string(&String::from_utf8_lossy(s), out),
+        Reply::Int(n) => {
+            let _ = write!(out, "{n}");
+        }
+        Reply::Dbl(n) if n.is_finite() => {
+            let _ = write!(out, "{n}");
+        }
+        Reply::Dbl(n) => json_string(&n.to_string(), out),
+        Reply::Arr(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                json(item, out);
+            }
+            out.push(']');
+        }
+    }
+}
+
+fn json_string(s: &str, out: &mut String) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c < ' ' => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// One line of comma separated values, nested arrays are flattened
+fn csv(reply: &Reply, out: &mut String) {
+    let mut fields = Vec::new();
+    csv_fields(reply, &mut fields);
+    out.push_str(&fields.join(","));
+}
+
+fn csv_fields(reply: &Reply, fields: &mut Vec<String>) {
+    match reply {
+        Reply::Nil => fields.push("NULL".into()),
+        Reply::Err(e) => {
+            fields.push("ERROR".into());
+            fields.push(csv_string(e.as_bytes()));
+        }
+        Reply::Str(s) => fields.push(csv_string(s)),
+        Reply::Int(n) => fields.push(n.to_string()),
+        Reply::Dbl(n) => fields.push(n.to_string()),
+        Reply::Arr(items) => {
+            for item in items {
+                csv_fields(item, fields);
+            }
+        }
+    }
+}
+
+fn csv_string(s: &[u8]) -> String {
+    format!("\"{}\"", String::from_utf8_lossy(s).replace('"', "\"\""))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn splits_quoted_arguments() {
+        assert_eq!(
+            split_line(r#"  set "a b" 'it\'s' "\x41\n\"" x  "#).unwrap(),
+            ["set", "a b", "it's", "A\n\"", "x"]
+        );
+        assert_eq!(split_line(r#"get """#).unwrap(), ["get", ""]);
+        assert!(split_line("").unwrap().is_empty());
+        assert_eq!(split_line(r#"get "k"#), Err(LineError::UnbalancedQuotes));
+        assert_eq!(split_line(r#"get "k"v"#), Err(LineError::TrailingQuote));
+        assert_eq!(split_line(r#"get "\xff""#), Err(LineError::InvalidUtf8));
+    }
+
+    #[test]
+    fn formats_replies() {
+        let reply = Reply::Arr(vec![
+            Reply::str("a \"b\""),
+            Reply::Int(3),
+            Reply::Nil,
+            Reply::Arr(vec![Reply::Dbl(1.5), Reply::Arr(vec![])]),
+        ]);
+        let format = |mode| format_reply(&reply, mode);
+
+        assert_eq!(
+            format(OutputMode::Pretty),
+            "1) \"a \\\"b\\\"\"\n2) (integer) 3\n3) (nil)\n4) 1) (double) 1.5\n   2) (empty array)"
+        );
+        assert_eq!(format(OutputMode::Raw), "a \"b\"\n3\n\n1.5\n");
+        assert_eq!(format(OutputMode::Json), r#"["a \"b\"",3,null,[1.5,[]]]"#);
+        assert_eq!(format(OutputMode::Csv), r#""a ""b""",3,NULL,1.5"#);
+
+        let err = Reply::err("ERR nope");
+        assert_eq!(format_reply(&err, OutputMode::Pretty), "(error) ERR nope");
+        assert_eq!(
+            format_reply(&err, OutputMode::Json),
+            r#"{"error":"ERR nope"}"#
+        );
+    }
+}
@@ -0,0 +1,666 @@
+//! Blocking client for the server, with typed helpers for the common
+//! commands, pipelining and a connection pool shared between threads.
+
+use std::{
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
+    ops::{Deref, DerefMut},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use thiserror::Error;
+
+use crate::protocol::{
+    MAX_ARGS, Reply, ReplyFormat,
+    request::{RES_ERR, RES_NX},
+};
+
+/// Size of the length and status code in front of every response
+const HEADER_LEN: usize = 8;
+
+/// Largest response accepted, so a corrupt length can't make the decoder
+/// buffer without end
+pub const MAX_REPLY_LEN: usize = 1 << 30;
+
+#[derive(Error, Debug)]
+pub enum ClientError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+
+    #[error("invalid response from server")]
+    Protocol,
+
+    #[error("too many arguments ({0})")]
+    TooManyArgs(usize),
+
+    /// The server replied with an error
+    #[error("{0}")]
+    Server(String),
+
+    #[error("unexpected reply {0:?}")]
+    UnexpectedReply(Reply),
+}
+
+/// Encodes one request, the command name followed by its arguments
+pub fn encode_request<S: AsRef<str>>(args: &[S], buf: &mut Vec<u8>) -> Result<(), ClientError> {
+    if args.len() > MAX_ARGS {
+        return Err(ClientError::TooManyArgs(args.len()));
+    }
+    buf.extend_from_slice(&(args.len() as u32).to_be_bytes());
+    for arg in args {
+        let arg = arg.as_ref().as_bytes();
+        buf.extend_from_slice(&(arg.len() as u32).to_be_bytes());
+        buf.extend_from_slice(arg);
+    }
+    Ok(())
+}
+
+/// Decodes the payload of a response sent in `format`. Plain payloads
+/// carry no type, so they come back as strings unless the status code
+/// says nil or error
+pub fn decode_reply(format: ReplyFormat, status: i32, data: &[u8]) -> Result<Reply, ClientError> {
+    match format {
+        ReplyFormat::Typed => match Reply::decode(data) {
+            Ok((reply, used)) if used == data.len() => Ok(reply),
+            _ => Err(ClientError::Protocol),
+        },
+        ReplyFormat::Plain => Ok(match status {
+            RES_NX => Reply::Nil,
+            RES_ERR => Reply::Err(String::from_utf8_lossy(data).into_owned()),
+            _ => Reply::Str(data.to_vec()),
+        }),
+    }
+}
+
+/// Splits the bytes read from the server into responses, however they
+/// were split by the network
+#[derive(Debug, Default)]
+pub struct Decoder {
+    buf: Vec<u8>,
+    format: ReplyFormat,
+}
+
+impl Decoder {
+    pub fn new(format: ReplyFormat) -> Self {
+        Self {
+            buf: Vec::new(),
+            format,
+        }
+    }
+
+    pub fn feed(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// Takes the next complete response, `None` until enough bytes
+    /// were fed
+    pub fn next_reply(&mut self) -> Result<Option<Reply>, ClientError> {
+        match self.next_frame()? {
+            Some((status, data)) => decode_reply(self.format, status, &data).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Takes the status code and payload of the next complete response
+    pub fn next_frame(&mut self) -> Result<Option<(i32, Vec<u8>)>, ClientError> {
+        if self.buf.len() < HEADER_LEN {
+            return Ok(None);
+        }
+        let len = u32::from_be_bytes(self.buf[..4].try_into().unwrap()) as usize;
+        if !(HEADER_LEN..=MAX_REPLY_LEN).contains(&len) {
+            return Err(ClientError::Protocol);
+        }
+        if self.buf.len() < len {
+            return Ok(None);
+        }
+
+        let status = i32::from_be_bytes(self.buf[4..HEADER_LEN].try_into().unwrap());
+        let data = self.buf[HEADER_LEN..len].to_vec();
+        self.buf.drain(..len);
+        Ok(Some((status, data)))
+    }
+}
+
+/// Converts a reply into the type a typed command returns
+pub trait FromReply: Sized {
+    fn from_reply(reply: Reply) -> Result<Self, ClientError>;
+}
+
+impl FromReply for Reply {
+    fn from_reply(reply: Reply) -> Result<Self, ClientError> {
+        Ok(reply)
+    }
+}
+
+impl FromReply for () {
+    fn from_reply(_: Reply) -> Result<Self, ClientError> {
+        Ok(())
+    }
+}
+
+impl FromReply for i64 {
+    fn from_reply(reply: Reply) -> Result<Self, ClientError> {
+        match reply {
+            Reply::Int(n) => Ok(n),
+            reply => Err(ClientError::UnexpectedReply(reply)),
+        }
+    }
+}
+
+impl FromReply for bool {
+    fn from_reply(reply: Reply) -> Result<Self, ClientError> {
+        i64::from_reply(reply).map(|n| n != 0)
+    }
+}
+
+impl FromReply for f64 {
+    fn from_reply(reply: Reply) -> Result<Self, ClientError> {
+        match reply {
+            Reply::Dbl(n) => Ok(n),
+            Reply::Int(n) => Ok(n as f64),
+            Reply::Str(s) => std::str::from_utf8(&s)
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .ok_or(ClientError::UnexpectedReply(Reply::Str(s))),
+            reply => Err(ClientError::UnexpectedReply(reply)),
+        }
+    }
+}
+
+impl FromReply for Vec<u8> {
+    fn from_reply(reply: Reply) -> Result<Self, ClientError> {
+        match reply {
+            Reply::Str(s) => Ok(s),
+            reply => Err(ClientError::UnexpectedReply(reply)),
+        }
+    }
+}
+
+impl FromReply for String {
+    fn from_reply(reply: Reply) -> Result<Self, ClientError> {
+        String::from_utf8(Vec::from_reply(reply)?)
+            .map_err(|e| ClientError::UnexpectedReply(Reply::Str(e.into_bytes())))
+    }
+}
+
+impl<T: FromReply> FromReply for Option<T> {
+    fn from_reply(reply: Reply) -> Result<Self, ClientError> {
+        match reply {
+            Reply::Nil => Ok(None),
+            reply => T::from_reply(reply).map(Some),
+        }
+    }
+}
+
+impl<T: FromReply> FromReply for Vec<T> {
+    fn from_reply(reply: Reply) -> Result<Self, ClientError> {
+        match reply {
+            Reply::Arr(items) => items.into_iter().map(T::from_reply).collect(),
+            reply => Err(ClientError::UnexpectedReply(reply)),
+        }
+    }
+}
+
+/// Whether the command changes the state of the connection on the server
+fn changes_state<S: AsRef<str>>(args: &[S]) -> bool {
+    args.first().is_some_and(|name| {
+        ["select", "auth", "hello", "monitor", "client"]
+            .iter()
+            .any(|cmd| name.as_ref().eq_ignore_ascii_case(cmd))
+    })
+}
+
+/// Requests sent in one write, their replies are read back in order
+#[derive(Debug, Default, Clone)]
+pub struct Pipeline {
+    buf: Vec<u8>,
+    len: usize,
+    /// Set if one of the requests changes the state of the connection
+    changes_state: bool,
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cmd<S: AsRef<str>>(&mut self, args: &[S]) -> Result<&mut Self, ClientError> {
+        encode_request(args, &mut self.buf)?;
+        self.len += 1;
+        self.changes_state |= changes_state(args);
+        Ok(self)
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+/// A connection to the server, asking for typed replies as soon as the
+/// server lets it
+#[derive(Debug)]
+pub struct Client {
+    stream: TcpStream,
+    decoder: Decoder,
+    /// Set once an I/O or protocol error left the connection in an
+    /// unknown state
+    broken: bool,
+    /// Set once a command like `SELECT` or `MONITOR` changed the state of
+    /// the connection, so a pool doesn't hand it to somebody else
+    dirty: bool,
+}
+
+impl Client {
+    pub fn connect(addr: impl ToSocketAddrs) -> Result<Self, ClientError> {
+        Self::new(TcpStream::connect(addr)?)
+    }
+
+    /// Connects, failing if it takes longer than `timeout`, which also
+    /// becomes the read and write timeout
+    pub fn connect_timeout(addr: SocketAddr, timeout: Duration) -> Result<Self, ClientError> {
+        let stream = TcpStream::connect_timeout(&addr, timeout)?;
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+        Self::new(stream)
+    }
+
+    fn new(stream: TcpStream) -> Result<Self, ClientError> {
+        let _ = stream.set_nodelay(true);
+        let mut client = Self {
+            stream,
+            decoder: Decoder::default(),
+            broken: false,
+            dirty: false,
+        };
+        // a server that wants a password refuses until `auth`
+        match client.hello(None) {
+            Ok(()) | Err(ClientError::Server(_)) => Ok(client),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Switches the connection to typed replies with `HELLO 2`,
+    /// authenticating at the same time if `auth` is given
+    pub fn hello(&mut self, auth: Option<(&str, &str)>) -> Result<(), ClientError> {
+        let mut args = vec!["hello", "2"];
+        if let Some((user, password)) = auth {
+            args.extend(["auth", user, password]);
+            self.dirty = true;
+        }
+        let mut buf = Vec::new();
+        encode_request(&args, &mut buf)?;
+        self.send(&buf)?;
+
+        // errors come in the format used so far, the rest in the new one
+        let (status, data) = self.read_frame()?;
+        let format = match status {
+            RES_ERR => self.decoder.format,
+            _ => ReplyFormat::Typed,
+        };
+        let reply = decode_reply(format, status, &data).inspect_err(|_| self.broken = true)?;
+        self.decoder.format = format;
+        match reply {
+            Reply::Err(e) => Err(ClientError::Server(e)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Read and write timeout, `None` waits forever
+    pub fn set_timeout(&self, timeout: Option<Duration>) -> Result<(), ClientError> {
+        self.stream.set_read_timeout(timeout)?;
+        self.stream.set_write_timeout(timeout)?;
+        Ok(())
+    }
+
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.stream.peer_addr()
+    }
+
+    pub fn is_broken(&self) -> bool {
+        self.broken
+    }
+
+    /// Whether a command changed the selected database, the user or other
+    /// state of the connection
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Sends a command and returns its reply as is, error replies
+    /// included
+    pub fn query<S: AsRef<str>>(&mut self, args: &[S]) -> Result<Reply, ClientError> {
+        let mut buf = Vec::new();
+        encode_request(args, &mut buf)?;
+        self.dirty |= changes_state(args);
+        self.send(&buf)?;
+        self.read_reply()
+    }
+
+    /// Sends a command and converts its reply, error replies become
+    /// [`ClientError::Server`]
+    pub fn query_as<T: FromReply, S: AsRef<str>>(&mut self, args: &[S]) -> Result<T, ClientError> {
+        match self.query(args)? {
+            Reply::Err(e) => Err(ClientError::Server(e)),
+            reply => T::from_reply(reply),
+        }
+    }
+
+    /// Sends every request of `pipeline` at once, then reads their replies
+    pub fn pipeline(&mut self, pipeline: &Pipeline) -> Result<Vec<Reply>, ClientError> {
+        self.dirty |= pipeline.changes_state;
+        self.send(&pipeline.buf)?;
+        (0..pipeline.len).map(|_| self.read_reply()).collect()
+    }
+
+    fn send(&mut self, buf: &[u8]) -> Result<(), ClientError> {
+        self.stream
+            .write_all(buf)
+            .inspect_err(|_| self.broken = true)?;
+        Ok(())
+    }
+
+    /// Reads the next reply, like the ones a `MONITOR` connection receives
+    pub fn read_reply(&mut self) -> Result<Reply, ClientError> {
+        let (status, data) = self.read_frame()?;
+        decode_reply(self.decoder.format, status, &data).inspect_err(|_| self.broken = true)
+    }
+
+    fn read_frame(&mut self) -> Result<(i32, Vec<u8>), ClientError> {
+        let mut buf = [0; 16 * 1024];
+        loop {
+            match self.decoder.next_frame() {
+                Ok(Some(frame)) => return Ok(frame),
+                Ok(None) => {}
+                Err(e) => {
+                    self.broken = true;
+                    return Err(e);
+                }
+            }
+            let e = match self.stream.read(&mut buf) {
+                Ok(0) => io::Error::from(io::ErrorKind::UnexpectedEof),
+                Ok(n) => {
+                    self.decoder.feed(&buf[..n]);
+                    continue;
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => e,
+            };
+            self.broken = true;
+            return Err(e.into());
+        }
+    }
+
+    /// Authenticates, switching to typed replies if that was refused
+    /// until now
+    pub fn auth(&mut self, user: &str, password: &str) -> Result<(), ClientError> {
+        self.hello(Some((user, password)))
+    }
+
+    pub fn select(&mut self, db: usize) -> Result<(), ClientError> {
+        self.query_as(&["select", &db.to_string()])
+    }
+
+    pub fn get(&mut self, key: &str) -> Result<Option<Vec<u8>>, ClientError> {
+        self.query_as(&["get", key])
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), ClientError> {
+        self.query_as(&["set", key, value])
+    }
+
+    /// Sets `key` to expire after `ttl`, rounded down to milliseconds
+    pub fn set_px(&mut self, key: &str, value: &str, ttl: Duration) -> Result<(), ClientError> {
+        self.query_as(&["set", key, value, "PX", &ttl.as_millis().to_string()])
+    }
+
+    /// Returns whether the key existed
+    pub fn del(&mut self, key: &str) -> Result<bool, ClientError> {
+        self.query_as::<Reply, _>(&["del", key])
+            .map(|reply| reply != Reply::Nil)
+    }
+
+    pub fn exists(&mut self, keys: &[&str]) -> Result<i64, ClientError> {
+        self.query_as(&with_name("exists", keys))
+    }
+
+    pub fn incr_by(&mut self, key: &str, by: i64) -> Result<i64, ClientError> {
+        self.query_as(&["incrby", key, &by.to_string()])
+    }
+
+    pub fn dbsize(&mut self) -> Result<i64, ClientError> {
+        self.query_as(&["dbsize"])
+    }
+
+    pub fn lpush(&mut self, key: &str, values: &[&str]) -> Result<i64, ClientError> {
+        self.query_as(&with_name("lpush", &with_name(key, values)))
+    }
+
+    pub fn rpush(&mut self, key: &str, values: &[&str]) -> Result<i64, ClientError> {
+        self.query_as(&with_name("rpush", &with_name(key, values)))
+    }
+
+    pub fn lpop(&mut self, key: &str) -> Result<Option<Vec<u8>>, ClientError> {
+        self.query_as(&["lpop", key])
+    }
+
+    pub fn lrange(
+        &mut self,
+        key: &str,
+        start: i64,
+        stop: i64,
+    ) -> Result<Vec<Vec<u8>>, ClientError> {
+        self.query_as(&["lrange", key, &start.to_string(), &stop.to_string()])
+    }
+
+    pub fn hset(&mut self, key: &str, field: &str, value: &str) -> Result<i64, ClientError> {
+        self.query_as(&["hset", key, field, value])
+    }
+
+    pub fn hget(&mut self, key: &str, field: &str) -> Result<Option<Vec<u8>>, ClientError> {
+        self.query_as(&["hget", key, field])
+    }
+
+    pub fn sadd(&mut self, key: &str, members: &[&str]) -> Result<i64, ClientError> {
+        self.query_as(&with_name("sadd", &with_name(key, members)))
+    }
+
+    pub fn smembers(&mut self, key: &str) -> Result<Vec<Vec<u8>>, ClientError> {
+        self.query_as(&["smembers", key])
+    }
+}
+
+fn with_name<'a>(name: &'a str, args: &[&'a str]) -> Vec<&'a str> {
+    let mut cmd = Vec::with_capacity(args.len() + 1);
+    cmd.push(name);
+    cmd.extend_from_slice(args);
+    cmd
+}
+
+/// Connections to one server shared between threads. Connections go
+/// back to the pool when dropped, unless they broke or ran a command
+/// changing their state, like `SELECT`, `AUTH` or `MONITOR`
+#[derive(Debug, Clone)]
+pub struct Pool {
+    inner: Arc<PoolInner>,
+}
+
+#[derive(Debug)]
+struct PoolInner {
+    addr: SocketAddr,
+    timeout: Option<Duration>,
+    max_idle: usize,
+    idle: Mutex<Vec<Client>>,
+}
+
+impl Pool {
+    /// Keeps at most `max_idle` connections open between uses, with
+    /// `timeout` for connecting, reading and writing
+    pub fn new(addr: SocketAddr, max_idle: usize, timeout: Option<Duration>) -> Self {
+        Self {
+            inner: Arc::new(PoolInner {
+                addr,
+                timeout,
+                max_idle,
+                idle: Mutex::new(Vec::new()),
+            }),
+        }
+    }
+
+    /// Takes an idle connection, or opens a new one
+    pub fn get(&self) -> Result<PooledClient, ClientError> {
+        let idle = self.inner.idle.lock().unwrap().pop();
+        let client = match idle {
+            Some(client) => client,
+            None => match self.inner.timeout {
+                Some(timeout) => Client::connect_timeout(self.inner.addr, timeout)?,
+                None => Client::connect(self.inner.addr)?,
+            },
+        };
+        Ok(PooledClient {
+            client: Some(client),
+            pool: self.inner.clone(),
+        })
+    }
+
+    pub fn idle(&self) -> usize {
+        self.inner.idle.lock().unwrap().len()
+    }
+}
+
+/// A connection borrowed from a [`Pool`]
+#[derive(Debug)]
+pub struct PooledClient {
+    client: Option<Client>,
+    pool: Arc<PoolInner>,
+}
+
+impl Deref for PooledClient {
+    type Target = Client;
+
+    fn deref(&self) -> &Client {
+        self.client.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for PooledClient {
+    fn deref_mut(&mut self) -> &mut Client {
+        self.client.as_mut().unwrap()
+    }
+}
+
+impl Drop for PooledClient {
+    fn drop(&mut self) {
+        let Some(client) = self.client.take() else {
+            return;
+        };
+        let mut idle = self.pool.idle.lock().unwrap();
+        if !client.broken && !client.dirty && idle.len() < self.pool.max_idle {
+            idle.push(client);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::thread;
+
+    use super::*;
+    use crate::server::Server;
+
+    #[test]
+    fn decodes_split_responses() {
+        let mut frames = Vec::new();
+        for reply in [Reply::str("a".repeat(5000)), Reply::Int(7)] {
+            crate::protocol::request::serialize_reply(&reply, ReplyFormat::Typed, &mut frames);
+        }
+
+        let mut decoder = Decoder::new(ReplyFormat::Typed);
+        let mut replies = Vec::new();
+        for chunk in frames.chunks(3) {
+            decoder.feed(chunk);
+            while let Some(reply) = decoder.next_reply().unwrap() {
+                replies.push(reply);
+            }
+        }
+        assert_eq!(replies, [Reply::str("a".repeat(5000)), Reply::Int(7)]);
+    }
+
+    #[test]
+    fn decodes_plain_responses() {
+        let mut frames = Vec::new();
+        for reply in [Reply::Nil, Reply::Int(7), Reply::err("ERR nope")] {
+            crate::protocol::request::serialize_reply(&reply, ReplyFormat::Plain, &mut frames);
+        }
+        assert_eq!(frames[..8], [0, 0, 0, 8, 0, 0, 0, 1]);
+
+        let mut decoder = Decoder::default();
+        decoder.feed(&frames);
+        assert_eq!(decoder.next_reply().unwrap(), Some(Reply::Nil));
+        assert_eq!(decoder.next_reply().unwrap(), Some(Reply::str("7")));
+        assert_eq!(decoder.next_reply().unwrap(), Some(Reply::err("ERR nope")));
+        assert_eq!(decoder.next_reply().unwrap(), None);
+
+        decoder.feed(&(MAX_REPLY_LEN as u32 + 1).to_be_bytes());
+        decoder.feed(&[0; 4]);
+        assert!(matches!(decoder.next_reply(), Err(ClientError::Protocol)));
+    }
+
+    #[test]
+    fn typed_commands_pipelines_and_pool() {
+        let server = Server::builder()
+            .bind("127.0.0.1:0".parse().unwrap())
+            .run()
+            .unwrap();
+        let addr = server.addr().unwrap();
+
+        let mut client = Client::connect(addr).unwrap();
+        client.set("k", "v").unwrap();
+        assert_eq!(client.get("k").unwrap().as_deref(), Some(&b"v"[..]));
+        assert_eq!(client.rpush("l", &["a", "b"]).unwrap(), 2);
+        assert_eq!(client.lrange("l", 0, -1).unwrap(), [b"a", b"b"]);
+        assert!(matches!(
+            client.incr_by("l", 1),
+            Err(ClientError::Server(e)) if e.starts_with("WRONGTYPE")
+        ));
+
+        let mut pipeline = Pipeline::new();
+        for n in 0..100 {
+            pipeline.cmd(&["incrby", "n", &n.to_string()]).unwrap();
+        }
+        let replies = client.pipeline(&pipeline).unwrap();
+        assert_eq!(replies.last(), Some(&Reply::Int(4950)));
+
+        let pool = Pool::new(addr, 2, Some(Duration::from_secs(5)));
+        let workers: Vec<_> = (0..4)
+            .map(|_| {
+                let pool = pool.clone();
+                thread::spawn(move || {
+                    for _ in 0..10 {
+                        pool.get().unwrap().incr_by("shared", 1).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+        assert_eq!(pool.get().unwrap().incr_by("shared", 0).unwrap(), 40);
+        assert!(pool.idle() <= 2);
+
+        // connections with changed state are closed instead of reused
+        let idle = pool.idle();
+        let mut pooled = pool.get().unwrap();
+        pooled.select(1).unwrap();
+        assert!(pooled.is_dirty());
+        drop(pooled);
+        assert_eq!(pool.idle(), idle - 1);
+        assert_eq!(pool.get().unwrap().dbsize().unwrap(), 4);
+
+        server.stop().unwrap();
+    }
+}
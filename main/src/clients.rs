@@ -0,0 +1,316 @@
+use std::{collections::BTreeMap, fmt::Write, time::Instant};
+
+use crate::{net::PeerAddr, protocol::Reply, session::Session};
+
+/// The connected clients of a server
+#[derive(Debug)]
+pub struct Clients {
+    registry: BTreeMap<u64, ClientInfo>,
+    next_id: u64,
+}
+
+/// What `CLIENT LIST` knows about a connection,
+/// kept up to date by the connection itself
+#[derive(Debug, Clone)]
+pub struct ClientInfo {
+    pub id: u64,
+    pub addr: PeerAddr,
+    pub name: Option<String>,
+    pub created: Instant,
+    pub last_interaction: Instant,
+    pub last_cmd: Option<&'static str>,
+    /// Bytes waiting to be parsed
+    pub qbuf: usize,
+    /// Bytes waiting to be written
+    pub obuf: usize,
+    /// Set by `CLIENT KILL`, the manager closes the connection after the current iteration
+    pub killed: bool,
+}
+
+impl ClientInfo {
+    fn line(&self, out: &mut String) {
+        let now = Instant::now();
+        let _ = writeln!(
+            out,
+            "id={} addr={} name={} age={} idle={} qbuf={} obuf={} cmd={}",
+            self.id,
+            self.addr,
+            self.name.as_deref().unwrap_or_default(),
+            now.duration_since(self.created).as_secs(),
+            now.duration_since(self.last_interaction).as_secs(),
+            self.qbuf,
+            self.obuf,
+            self.last_cmd.unwrap_or("NULL"),
+        );
+    }
+}
+
+impl Clients {
+    pub fn new() -> Self {
+        Self {
+            registry: BTreeMap::new(),
+            next_id: 1,
+        }
+    }
+
+    /// Registers a new client, returning its id
+    pub fn register(&mut self, addr: PeerAddr) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        let now = Instant::now();
+
+        self.registry.insert(
+            id,
+            ClientInfo {
+                id,
+                addr,
+                name: None,
+                created: now,
+                last_interaction: now,
+                last_cmd: None,
+                qbuf: 0,
+                obuf: 0,
+                killed: false,
+            },
+        );
+        id
+    }
+
+    pub fn unregister(&mut self, id: u64) {
+        self.registry.remove(&id);
+    }
+
+    pub fn update_buffers(&mut self, id: u64, qbuf: usize, obuf: usize) {
+        if let Some(info) = self.registry.get_mut(&id) {
+            info.qbuf = qbuf;
+            info.obuf = obuf;
+        }
+    }
+
+    pub fn record_command(&mut self, id: u64, name: &'static str) {
+        if let Some(info) = self.registry.get_mut(&id) {
+            info.last_cmd = Some(name);
+            info.last_interaction = Instant::now();
+        }
+    }
+
+    /// Returns the ids of the clients killed since the last call
+    pub fn take_killed(&mut self) -> Vec<u64> {
+        self.registry
+            .values_mut()
+            .filter(|info| info.killed)
+            .map(|info| {
+                info.killed = false;
+                info.id
+            })
+            .collect()
+    }
+}
+
+impl Default for Clients {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Handles `CLIENT ID | GETNAME | SETNAME name | LIST | KILL ...`
+pub fn command(clients: &mut Clients, args: &[String], session: &mut Session) -> Reply {
+    let Some(sub) = args.first() else {
+        return Reply::err("ERR wrong number of arguments for 'client' command");
+    };
+    let registry = &mut clients.registry;
+
+    match (sub.to_ascii_lowercase().as_str(), &args[1..]) {
+        ("id", []) => Reply::Int(session.id as i64),
+        ("getname", []) => match registry.get(&session.id).and_then(|i| i.name.as_ref()) {
+            Some(name) => Reply::str(name),
+            None => Reply::Nil,
+        },
+        ("setname", [name]) => {
+            if name.chars().any(|c| c <= ' ' || c > '~') {
+                return Reply::err(
+                    "ERR Client names cannot contain spaces, newlines or special characters.",
+                );
+            }
+            if let Some(info) = registry.get_mut(&session.id) {
+                info.name = (!name.is_empty()).then(|| name.clone());
+            }
+            Reply::ok()
+        }
+        ("list", []) => {
+            let mut out = String::new();
+            for info in registry.values() {
+                info.line(&mut out);
+            }
+            Reply::Str(out.into_bytes())
+        }
+        ("kill", [addr]) => {
+            // old form, kills exactly one client by address
+            match registry.values_mut().find(|i| i.addr.to_string() == *addr) {
+                Some(info) => {
+                    info.killed = true;
+                    Reply::ok()
+                }
+                None => Reply::err("ERR No such client"),
+            }
+        }
+        ("kill", filters) if filters.len() % 2 == 0 && !filters.is_empty() => {
+            let filter = match KillFilter::parse(filters) {
+                Ok(f) => f,
+                Err(e) => return e,
+            };
+
+            let mut killed = 0;
+            for info in registry.values_mut() {
+                if filter.skip_me && info.id == session.id {
+                    continue;
+                }
+                if filter.matches(info) {
+                    info.killed = true;
+                    killed += 1;
+                }
+            }
+            Reply::Int(killed)
+        }
+        _ => Reply::err(format!(
+            "ERR unknown subcommand or wrong number of arguments for 'client {sub}'"
+        )),
+    }
+}
+
+/// `CLIENT KILL` filters, every given filter has to match
+#[derive(Debug, Default)]
+struct KillFilter {
+    id: Option<u64>,
+    addr: Option<String>,
+    name: Option<String>,
+    skip_me: bool,
+}
+
+impl KillFilter {
+    fn parse(args: &[String]) -> Result<Self, Reply> {
+        let mut filter = KillFilter {
+            skip_me: true,
+            ..Default::default()
+        };
+
+        for pair in args.chunks(2) {
+            let value = &pair[1];
+            match pair[0].to_ascii_lowercase().as_str() {
+                "id" => {
+                    filter.id = Some(
+                        value
+                            .parse()
+                            .map_err(|_| Reply::err("ERR client-id should be greater than 0"))?,
+                    )
+                }
+                "addr" => filter.addr = Some(value.clone()),
+                "name" => filter.name = Some(value.clone()),
+                "skipme" => {
+                    filter.skip_me = match value.to_ascii_lowercase().as_str() {
+                        "yes" => true,
+                        "no" => false,
+                        _ => return Err(Reply::err("ERR syntax error")),
+                    }
+                }
+                _ => return Err(Reply::err("ERR syntax error")),
+            }
+        }
+
+        Ok(filter)
+    }
+
+    fn matches(&self, info: &ClientInfo) -> bool {
+        self.id.is_none_or(|id| id == info.id)
+            && self
+                .addr
+                .as_ref()
+                .is_none_or(|addr| *addr == info.addr.to_string())
+            && self
+                .name
+                .as_ref()
+                .is_none_or(|name| Some(name) == info.name.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::commands::args;
+
+    fn tcp(port: u16) -> PeerAddr {
+        PeerAddr::Tcp(([127, 0, 0, 1], port).into())
+    }
+
+    /// Registers clients named after their port, returning their sessions
+    fn connect(clients: &mut Clients, ports: &[u16]) -> Vec<Session> {
+        ports
+            .iter()
+            .map(|&port| {
+                let mut session = Session::new(clients.register(tcp(port)), tcp(port));
+                let setname = args(&["setname", &format!("c{port}")]);
+                assert_eq!(command(clients, &setname, &mut session), Reply::ok());
+                session
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parses_kill_filters() {
+        let filter = KillFilter::parse(&args(&["ID", "3", "name", "a", "SKIPME", "no"])).unwrap();
+        assert_eq!(filter.id, Some(3));
+        assert_eq!(filter.name.as_deref(), Some("a"));
+        assert!(!filter.skip_me);
+        assert!(KillFilter::parse(&args(&["addr", "x:1"])).unwrap().skip_me);
+
+        for bad in [&["id", "x"][..], &["skipme", "maybe"], &["user", "a"]] {
+            assert!(KillFilter::parse(&args(bad)).is_err(), "{bad:?}");
+        }
+    }
+
+    #[test]
+    fn setname_and_getname() {
+        let mut clients = Clients::new();
+        let mut session = connect(&mut clients, &[1]).remove(0);
+        let mut client = |cmd: &[&str]| command(&mut clients, &args(cmd), &mut session);
+
+        assert_eq!(client(&["getname"]), Reply::str("c1"));
+        assert!(matches!(client(&["setname", "a b"]), Reply::Err(_)));
+        assert!(matches!(client(&["setname", "a\n"]), Reply::Err(_)));
+        assert!(matches!(client(&["setname", "é"]), Reply::Err(_)));
+        assert_eq!(client(&["getname"]), Reply::str("c1"));
+
+        assert_eq!(client(&["setname", ""]), Reply::ok());
+        assert_eq!(client(&["getname"]), Reply::Nil);
+    }
+
+    #[test]
+    fn kill_by_id_addr_and_name() {
+        let mut clients = Clients::new();
+        let mut sessions = connect(&mut clients, &[1, 2, 3, 4]);
+        let me = &mut sessions[0];
+        let mut kill =
+            |filters: &[&str]| command(&mut clients, &args(&[&["kill"], filters].concat()), me);
+
+        assert_eq!(kill(&["127.0.0.1:2"]), Reply::ok());
+        assert!(matches!(kill(&["127.0.0.1:9"]), Reply::Err(_)));
+        assert_eq!(kill(&["id", "3"]), Reply::Int(1));
+        assert_eq!(kill(&["name", "c4", "addr", "127.0.0.1:3"]), Reply::Int(0));
+        assert_eq!(kill(&["name", "c4"]), Reply::Int(1));
+        assert_eq!(clients.take_killed(), [2, 3, 4]);
+        assert!(clients.take_killed().is_empty());
+    }
+
+    #[test]
+    fn kill_skips_the_caller_unless_told_otherwise() {
+        let mut clients = Clients::new();
+        let mut sessions = connect(&mut clients, &[1, 2]);
+        let me = &mut sessions[0];
+        let mut kill =
+            |filters: &[&str]| command(&mut clients, &args(&[&["kill"], filters].concat()), me);
+
+        assert_eq!(kill(&["addr", "127.0.0.1:1"]), Reply::Int(0));
+        assert_eq!(kill(&["id", "1", "skipme", "no"]), Reply::Int(1));
+        assert_eq!(clients.take_killed(), [1]);
+    }
+}
@@ -0,0 +1,532 @@
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{
+    acl, blocking, clients,
+    commands::{bitmap, geo, hash, hyperloglog, keyspace, list, set, stream, string},
+    module::{Command, Context},
+    protocol::Reply,
+    server::ServerState,
+    session::Session,
+    slowlog,
+    storage::Db,
+};
+
+/// May modify the dataset
+pub const WRITE: u8 = 1 << 0;
+/// Only reads the dataset
+pub const READONLY: u8 = 1 << 1;
+/// Administrative command, also makes it `@dangerous`
+pub const ADMIN: u8 = 1 << 2;
+/// Runs in constant or logarithmic time, anything else is `@slow`
+pub const FAST: u8 = 1 << 3;
+
+const FLAG_NAMES: [(u8, &str); 4] = [
+    (WRITE, "write"),
+    (READONLY, "readonly"),
+    (ADMIN, "admin"),
+    (FAST, "fast"),
+];
+
+/// Where the keys are in the arguments of a command, counting the command name
+#[derive(Clone, Copy)]
+pub enum Keys {
+    None,
+    /// Every `step`th argument from `first` to `last`, negative `last`
+    /// counts from the end
+    Range {
+        first: i32,
+        last: i32,
+        step: i32,
+    },
+    /// Keys that depend on the other arguments, like `XREAD ... STREAMS`
+    Movable(fn(&[String]) -> Vec<&str>),
+}
+
+const NO_KEYS: Keys = Keys::None;
+const KEY: Keys = Keys::Range {
+    first: 1,
+    last: 1,
+    step: 1,
+};
+const TWO_KEYS: Keys = Keys::Range {
+    first: 1,
+    last: 2,
+    step: 1,
+};
+const ALL_KEYS: Keys = Keys::Range {
+    first: 1,
+    last: -1,
+    step: 1,
+};
+
+pub enum Handler {
+    /// Runs against the database selected by the session
+    Db(fn(&mut Db, &[String]) -> Reply),
+    /// Needs more of the server than the selected database
+    Server(fn(&mut ServerState, &[String], &mut Session) -> Reply),
+    /// Registered by a module
+    Module(Box<dyn Command>),
+}
+
+pub struct CommandSpec {
+    pub name: &'static str,
+    /// Number of arguments including the name, negative for at least `-arity`
+    pub arity: i32,
+    pub flags: u8,
+    pub keys: Keys,
+    /// ACL categories besides the ones implied by `flags`
+    pub categories: &'static [&'static str],
+    pub handler: Handler,
+}
+
+impl CommandSpec {
+    /// Describes a command implemented by a module, with no ACL categories
+    /// besides the ones implied by `flags`
+    pub fn module(
+        name: &'static str,
+        arity: i32,
+        flags: u8,
+        keys: Keys,
+        command: impl Command + 'static,
+    ) -> Self {
+        spec(
+            name,
+            arity,
+            flags,
+            keys,
+            &[],
+            Handler::Module(Box::new(command)),
+        )
+    }
+
+    pub fn has(&self, flag: u8) -> bool {
+        self.flags & flag != 0
+    }
+
+    fn arity_matches(&self, argc: usize) -> bool {
+        let argc = argc as i32;
+        if self.arity >= 0 {
+            argc == self.arity
+        } else {
+            argc >= -self.arity
+        }
+    }
+
+    /// Every ACL category the command belongs to
+    pub fn acl_categories(&self) -> Vec<&'static str> {
+        let mut categories = Vec::new();
+        if self.has(WRITE) {
+            categories.push("write");
+        }
+        if self.has(READONLY) {
+            categories.push("read");
+        }
+        if self.has(ADMIN) {
+            categories.extend(["admin", "dangerous"]);
+        }
+        categories.push(if self.has(FAST) { "fast" } else { "slow" });
+        categories.extend(self.categories);
+        categories
+    }
+
+    /// Returns the keys accessed by `cmd`
+    pub fn keys<'a>(&self, cmd: &'a [String]) -> Vec<&'a str> {
+        match self.keys {
+            Keys::None => Vec::new(),
+            Keys::Range { first, last, step } => {
+                let last = if last < 0 {
+                    cmd.len() as i32 + last
+                } else {
+                    last
+                };
+                (first..=last.min(cmd.len() as i32 - 1))
+                    .step_by(step as usize)
+                    .map(|i| cmd[i as usize].as_str())
+                    .collect()
+            }
+            Keys::Movable(keys) => keys(cmd),
+        }
+    }
+
+    pub fn call(&self, state: &mut ServerState, session: &mut Session, args: &[String]) -> Reply {
+        match &self.handler {
+            Handler::Db(f) => f(&mut state.dbs[session.db], args),
+            Handler::Server(f) => f(state, args, session),
+            Handler::Module(command) => {
+                let mut ctx = Context {
+                    db: &mut state.dbs[session.db],
+                    session,
+                };
+                command.call(&mut ctx, args)
+            }
+        }
+    }
+
+    /// The `COMMAND INFO` entry of the command
+    fn info(&self) -> Reply {
+        let mut flags: Vec<_> = FLAG_NAMES
+            .iter()
+            .filter(|(flag, _)| self.has(*flag))
+            .map(|(_, name)| Reply::str(name))
+            .collect();
+        let (first, last, step) = match self.keys {
+            Keys::None => (0, 0, 0),
+            Keys::Range { first, last, step } => (first, last, step),
+            Keys::Movable(_) => {
+                flags.push(Reply::str("movablekeys"));
+                (0, 0, 0)
+            }
+        };
+        Reply::Arr(vec![
+            Reply::str(self.name),
+            Reply::Int(self.arity as i64),
+            Reply::Arr(flags),
+            Reply::Int(first as i64),
+            Reply::Int(last as i64),
+            Reply::Int(step as i64),
+            Reply::Arr(
+                self.acl_categories()
+                    .iter()
+                    .map(|c| Reply::str(format!("@{c}")))
+                    .collect(),
+            ),
+        ])
+    }
+}
+
+const fn spec(
+    name: &'static str,
+    arity: i32,
+    flags: u8,
+    keys: Keys,
+    categories: &'static [&'static str],
+    handler: Handler,
+) -> CommandSpec {
+    CommandSpec {
+        name,
+        arity,
+        flags,
+        keys,
+        categories,
+        handler,
+    }
+}
+
+use Handler::{Db as OnDb, Server as OnServer};
+
+/// Every command the server comes with
+#[rustfmt::skip]
+fn builtins() -> Vec<CommandSpec> {
+    vec![
+
+        spec("get", 2, READONLY | FAST, KEY, &["string"], OnDb(string::get)),
+        spec("set", -3, WRITE, KEY, &["string"], OnDb(string::set)),
+        spec("del", 2, WRITE, KEY, &["keyspace"], OnDb(keyspace::del)),
+        spec("mget", -2, READONLY | FAST, ALL_KEYS, &["string"], OnDb(string::mget)),
+        spec("mset", -3, WRITE, Keys::Range { first: 1, last: -1, step: 2 }, &["string"], OnDb(string::mset)),
+        spec("msetnx", -3, WRITE, Keys::Range { first: 1, last: -1, step: 2 }, &["string"], OnDb(string::msetnx)),
+        spec("getex", -2, WRITE, KEY, &["string"], OnDb(string::getex)),
+        spec("getdel", 2, WRITE, KEY, &["string"], OnDb(string::getdel)),
+        spec("getset", 3, WRITE, KEY, &["string"], OnDb(string::getset)),
+        spec("strlen", 2, READONLY | FAST, KEY, &["string"], OnDb(string::strlen)),
+        spec("append", 3, WRITE, KEY, &["string"], OnDb(string::append)),
+        spec("getrange", 4, READONLY, KEY, &["string"], OnDb(string::getrange)),
+        spec("setrange", 4, WRITE, KEY, &["string"], OnDb(string::setrange)),
+        spec("incr", 2, WRITE | FAST, KEY, &["string"], OnDb(string::incr)),
+        spec("decr", 2, WRITE | FAST, KEY, &["string"], OnDb(string::decr)),
+        spec("incrby", 3, WRITE | FAST, KEY, &["string"], OnDb(string::incrby)),
+        spec("decrby", 3, WRITE | FAST, KEY, &["string"], OnDb(string::decrby)),
+        spec("incrbyfloat", 3, WRITE | FAST, KEY, &["string"], OnDb(string::incrbyfloat)),
+        spec("setbit", 4, WRITE, KEY, &["bitmap"], OnDb(bitmap::setbit)),
+        spec("getbit", 3, READONLY | FAST, KEY, &["bitmap"], OnDb(bitmap::getbit)),
+        spec("bitcount", -2, READONLY, KEY, &["bitmap"], OnDb(bitmap::bitcount)),
+        spec("bitpos", -3, READONLY, KEY, &["bitmap"], OnDb(bitmap::bitpos)),
+        spec("bitop", -4, WRITE, Keys::Range { first: 2, last: -1, step: 1 }, &["bitmap"], OnDb(bitmap::bitop)),
+        spec("bitfield", -2, WRITE, KEY, &["bitmap"], OnDb(bitmap::bitfield)),
+        spec("bitfield_ro", -2, READONLY | FAST, KEY, &["bitmap"], OnDb(bitmap::bitfield_ro)),
+        spec("lpush", -3, WRITE | FAST, KEY, &["list"], OnDb(list::lpush)),
+        spec("rpush", -3, WRITE | FAST, KEY, &["list"], OnDb(list::rpush)),
+        spec("lpop", -2, WRITE | FAST, KEY, &["list"], OnDb(list::lpop)),
+        spec("rpop", -2, WRITE | FAST, KEY, &["list"], OnDb(list::rpop)),
+        spec("llen", 2, READONLY | FAST, KEY, &["list"], OnDb(list::llen)),
+        spec("lrange", 4, READONLY, KEY, &["list"], OnDb(list::lrange)),
+        spec("lindex", 3, READONLY | FAST, KEY, &["list"], OnDb(list::lindex)),
+        spec("ltrim", 4, WRITE, KEY, &["list"], OnDb(list::ltrim)),
+        spec("lmove", 5, WRITE, TWO_KEYS, &["list"], OnDb(list::lmove)),
+        spec("blpop", -3, WRITE, Keys::Range { first: 1, last: -2, step: 1 }, &["list", "blocking"],
+            OnServer(blocking::blpop)),
+        spec("brpop", -3, WRITE, Keys::Range { first: 1, last: -2, step: 1 }, &["list", "blocking"],
+            OnServer(blocking::brpop)),
+        spec("blmove", 6, WRITE, TWO_KEYS, &["list", "blocking"],
+            OnServer(blocking::blmove)),
+        spec("hset", -4, WRITE | FAST, KEY, &["hash"], OnDb(hash::hset)),
+        spec("hsetnx", 4, WRITE | FAST, KEY, &["hash"], OnDb(hash::hsetnx)),
+        spec("hmset", -4, WRITE | FAST, KEY, &["hash"], OnDb(hash::hmset)),
+        spec("hget", 3, READONLY | FAST, KEY, &["hash"], OnDb(hash::hget)),
+        spec("hmget", -3, READONLY | FAST, KEY, &["hash"], OnDb(hash::hmget)),
+        spec("hdel", -3, WRITE | FAST, KEY, &["hash"], OnDb(hash::hdel)),
+        spec("hlen", 2, READONLY | FAST, KEY, &["hash"], OnDb(hash::hlen)),
+        spec("hstrlen", 3, READONLY | FAST, KEY, &["hash"], OnDb(hash::hstrlen)),
+        spec("hexists", 3, READONLY | FAST, KEY, &["hash"], OnDb(hash::hexists)),
+        spec("hkeys", 2, READONLY, KEY, &["hash"], OnDb(hash::hkeys)),
+        spec("hvals", 2, READONLY, KEY, &["hash"], OnDb(hash::hvals)),
+        spec("hgetall", 2, READONLY, KEY, &["hash"], OnDb(hash::hgetall)),
+        spec("hincrby", 4, WRITE | FAST, KEY, &["hash"], OnDb(hash::hincrby)),
+        spec("hincrbyfloat", 4, WRITE | FAST, KEY, &["hash"], OnDb(hash::hincrbyfloat)),
+        spec("hrandfield", -2, READONLY, KEY, &["hash"], OnDb(hash::hrandfield)),
+        spec("hscan", -3, READONLY, KEY, &["hash"], OnDb(hash::hscan)),
+        spec("sadd", -3, WRITE | FAST, KEY, &["set"], OnDb(set::sadd)),
+        spec("srem", -3, WRITE | FAST, KEY, &["set"], OnDb(set::srem)),
+        spec("scard", 2, READONLY | FAST, KEY, &["set"], OnDb(set::scard)),
+        spec("sismember", 3, READONLY | FAST, KEY, &["set"], OnDb(set::sismember)),
+        spec("smismember", -3, READONLY | FAST, KEY, &["set"], OnDb(set::smismember)),
+        spec("smembers", 2, READONLY, KEY, &["set"], OnDb(set::smembers)),
+        spec("sinter", -2, READONLY, ALL_KEYS, &["set"], OnDb(set::sinter)),
+        spec("sunion", -2, READONLY, ALL_KEYS, &["set"], OnDb(set::sunion)),
+        spec("sdiff", -2, READONLY, ALL_KEYS, &["set"], OnDb(set::sdiff)),
+        spec("sinterstore", -3, WRITE, ALL_KEYS, &["set"], OnDb(set::sinterstore)),
+        spec("sunionstore", -3, WRITE, ALL_KEYS, &["set"], OnDb(set::sunionstore)),
+        spec("sdiffstore", -3, WRITE, ALL_KEYS, &["set"], OnDb(set::sdiffstore)),
+        spec("sintercard", -3, READONLY, Keys::Movable(numkeys), &["set"], OnDb(set::sintercard)),
+        spec("smove", 4, WRITE | FAST, TWO_KEYS, &["set"], OnDb(set::smove)),
+        spec("srandmember", -2, READONLY | FAST, KEY, &["set"], OnDb(set::srandmember)),
+        spec("spop", -2, WRITE | FAST, KEY, &["set"], OnDb(set::spop)),
+        spec("sscan", -3, READONLY, KEY, &["set"], OnDb(set::sscan)),
+        spec("pfadd", -2, WRITE | FAST, KEY, &["hyperloglog"], OnDb(hyperloglog::pfadd)),
+        spec("pfcount", -2, READONLY, ALL_KEYS, &["hyperloglog"], OnDb(hyperloglog::pfcount)),
+        spec("pfmerge", -2, WRITE, ALL_KEYS, &["hyperloglog"], OnDb(hyperloglog::pfmerge)),
+        spec("xadd", -5, WRITE | FAST, KEY, &["stream"], OnDb(stream::xadd)),
+        spec("xlen", 2, READONLY | FAST, KEY, &["stream"], OnDb(stream::xlen)),
+        spec("xrange", -4, READONLY, KEY, &["stream"], OnDb(stream::xrange)),
+        spec("xrevrange", -4, READONLY, KEY, &["stream"], OnDb(stream::xrevrange)),
+        spec("xdel", -3, WRITE, KEY, &["stream"], OnDb(stream::xdel)),
+        spec("xtrim", -4, WRITE, KEY, &["stream"], OnDb(stream::xtrim)),
+        spec("xread", -4, READONLY, Keys::Movable(streams), &["stream"], OnDb(stream::xread)),
+        spec("xgroup", -2, WRITE, Keys::Range { first: 2, last: 2, step: 1 }, &["stream"], OnDb(stream::xgroup)),
+        spec("xreadgroup", -7, WRITE, Keys::Movable(streams), &["stream"], OnDb(stream::xreadgroup)),
+        spec("xack", -4, WRITE | FAST, KEY, &["stream"], OnDb(stream::xack)),
+        spec("xpending", -3, READONLY, KEY, &["stream"], OnDb(stream::xpending)),
+        spec("xclaim", -6, WRITE | FAST, KEY, &["stream"], OnDb(stream::xclaim)),
+        spec("xautoclaim", -6, WRITE | FAST, KEY, &["stream"], OnDb(stream::xautoclaim)),
+        spec("geoadd", -5, WRITE, KEY, &["geo"], OnDb(geo::geoadd)),
+        spec("geopos", -2, READONLY, KEY, &["geo"], OnDb(geo::geopos)),
+        spec("geodist", -4, READONLY, KEY, &["geo"], OnDb(geo::geodist)),
+        spec("geohash", -2, READONLY, KEY, &["geo"], OnDb(geo::geohash)),
+        spec("geosearch", -7, READONLY, KEY, &["geo"], OnDb(geo::geosearch)),
+        spec("select", 2, FAST, NO_KEYS, &["connection"],
+            OnServer(|state, args, session| keyspace::select(&state.dbs, args, session))),
+        spec("flushdb", -1, WRITE, NO_KEYS, &["keyspace", "dangerous"], OnDb(keyspace::flushdb)),
+        spec("flushall", -1, WRITE, NO_KEYS, &["keyspace", "dangerous"],
+            OnServer(|state, args, _| keyspace::flushall(&mut state.dbs, args))),
+        spec("swapdb", 3, WRITE | FAST, NO_KEYS, &["keyspace", "dangerous"],
+            OnServer(|state, args, _| keyspace::swapdb(&mut state.dbs, args))),
+        spec("move", 3, WRITE | FAST, KEY, &["keyspace"],
+            OnServer(|state, args, session| keyspace::move_key(&mut state.dbs, session.db, args))),
+        spec("exists", -2, READONLY | FAST, ALL_KEYS, &["keyspace"], OnDb(keyspace::exists)),
+        spec("type", 2, READONLY | FAST, KEY, &["keyspace"], OnDb(keyspace::type_command)),
+        spec("rename", 3, WRITE, TWO_KEYS, &["keyspace"], OnDb(keyspace::rename)),
+        spec("renamenx", 3, WRITE | FAST, TWO_KEYS, &["keyspace"], OnDb(keyspace::renamenx)),
+        spec("dbsize", 1, READONLY | FAST, NO_KEYS, &["keyspace"], OnDb(keyspace::dbsize)),
+        spec("randomkey", 1, READONLY, NO_KEYS, &["keyspace"], OnDb(keyspace::randomkey)),
+        spec("copy", -3, WRITE, TWO_KEYS, &["keyspace"],
+            OnServer(|state, args, session| keyspace::copy(&mut state.dbs, session.db, args))),
+        spec("unlink", -2, WRITE | FAST, ALL_KEYS, &["keyspace"], OnDb(keyspace::unlink)),
+        spec("touch", -2, READONLY | FAST, ALL_KEYS, &["keyspace"], OnDb(keyspace::touch)),
+        spec("auth", -2, FAST, NO_KEYS, &["connection"], OnServer(acl::auth_command)),
+        spec("hello", -1, FAST, NO_KEYS, &["connection"], OnServer(acl::hello_command)),
+        spec("acl", -2, ADMIN, NO_KEYS, &[], OnServer(acl::acl_command)),
+        spec("client", -2, ADMIN, NO_KEYS, &["connection"], OnServer(|state, args, session| clients::command(&mut state.clients, args, session))),
+        spec("slowlog", -2, ADMIN, NO_KEYS, &[], OnServer(|state, args, _| slowlog::command(&mut state.slowlog, args))),
+        spec("monitor", 1, ADMIN, NO_KEYS, &[], OnServer(|state, _, session| {
+            state.monitors.subscribe(session);
+            Reply::ok()
+        })),
+        spec("command", -1, 0, NO_KEYS, &["connection"],
+            OnServer(|state, args, _| command(&state.commands, args))),
+    ]
+}
+
+/// `SINTERCARD numkeys key [key ...]`
+fn numkeys(cmd: &[String]) -> Vec<&str> {
+    let numkeys = cmd
+        .get(1)
+        .and_then(|n| n.parse::<usize>().ok())
+        .unwrap_or(0);
+    cmd[2.min(cmd.len())..(2 + numkeys).min(cmd.len())]
+        .iter()
+        .map(String::as_str)
+        .collect()
+}
+
+/// The keys of `XREAD`/`XREADGROUP`, the first half of everything after STREAMS
+fn streams(cmd: &[String]) -> Vec<&str> {
+    match cmd.iter().position(|a| a.eq_ignore_ascii_case("streams")) {
+        Some(i) => {
+            let rest = &cmd[i + 1..];
+            rest[..rest.len() / 2].iter().map(String::as_str).collect()
+        }
+        None => Vec::new(),
+    }
+}
+
+/// The commands of a server, the builtin ones and the ones added by modules
+pub struct CommandTable {
+    by_name: HashMap<String, Arc<CommandSpec>>,
+}
+
+impl CommandTable {
+    pub fn new() -> Self {
+        let by_name = builtins()
+            .into_iter()
+            .map(|spec| (spec.name.to_string(), Arc::new(spec)))
+            .collect();
+        Self { by_name }
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_name.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_name.is_empty()
+    }
+
+    /// Looks up a command by name, ignoring case
+    pub fn lookup(&self, name: &str) -> Option<Arc<CommandSpec>> {
+        match self.by_name.get(name) {
+            Some(spec) => Some(spec.clone()),
+            None => self.by_name.get(&name.to_ascii_lowercase()).cloned(),
+        }
+    }
+
+    /// Adds a command, returning false if one with the same name exists
+    pub fn register(&mut self, spec: CommandSpec) -> bool {
+        let name = spec.name.to_ascii_lowercase();
+        if self.by_name.contains_key(&name) {
+            return false;
+        }
+        self.by_name.insert(name, Arc::new(spec));
+        true
+    }
+
+    /// Finds the command `cmd` invokes, failing with the reply for unknown
+    /// commands or the wrong number of arguments
+    pub fn find(&self, cmd: &[String]) -> Result<Arc<CommandSpec>, Reply> {
+        let Some(name) = cmd.first() else {
+            return Err(Reply::err("ERR empty command"));
+        };
+        let Some(spec) = self.lookup(name) else {
+            let args: String = cmd[1..].iter().map(|a| format!("'{a}' ")).collect();
+            return Err(Reply::err(format!(
+                "ERR unknown command '{name}', with args beginning with: {args}"
+            )));
+        };
+        if !spec.arity_matches(cmd.len()) {
+            return Err(Reply::err(format!(
+                "ERR wrong number of arguments for '{}' command",
+                spec.name
+            )));
+        }
+        Ok(spec)
+    }
+
+    /// Every command ordered by name
+    fn all(&self) -> Vec<&CommandSpec> {
+        let mut all: Vec<_> = self.by_name.values().map(Arc::as_ref).collect();
+        all.sort_by_key(|spec| spec.name);
+        all
+    }
+}
+
+impl Default for CommandTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// `COMMAND [COUNT | INFO [name ...]]`
+pub fn command(commands: &CommandTable, args: &[String]) -> Reply {
+    let all = || Reply::Arr(commands.all().into_iter().map(CommandSpec::info).collect());
+    match args {
+        [] => all(),
+        [sub] if sub.eq_ignore_ascii_case("count") => Reply::Int(commands.len() as i64),
+        [sub, names @ ..] if sub.eq_ignore_ascii_case("info") => {
+            if names.is_empty() {
+                return all();
+            }
+            let infos = names
+                .iter()
+                .map(|name| commands.lookup(name).map_or(Reply::Nil, |spec| spec.info()))
+                .collect();
+            Reply::Arr(infos)
+        }
+        [sub, ..] => Reply::err(format!(
+            "ERR unknown subcommand or wrong number of arguments for 'command|{sub}'"
+        )),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::commands::args;
+
+    #[test]
+    fn lookup_and_arity() {
+        let commands = CommandTable::new();
+        let find = |cmd: &[String]| commands.find(cmd);
+        assert_eq!(find(&args(&["GeT", "k"])).map(|s| s.name).ok(), Some("get"));
+        assert!(matches!(
+            find(&args(&["get"])),
+            Err(Reply::Err(e)) if e == "ERR wrong number of arguments for 'get' command"
+        ));
+        assert!(find(&args(&["set", "k", "v", "EX", "10"])).is_ok());
+        assert!(matches!(
+            find(&args(&["nope", "a"])),
+            Err(Reply::Err(e)) if e == "ERR unknown command 'nope', with args beginning with: 'a' "
+        ));
+    }
+
+    #[test]
+    fn key_positions() {
+        let commands = CommandTable::new();
+        let keys = |cmd: &[&str]| {
+            let cmd = args(cmd);
+            commands
+                .lookup(&cmd[0])
+                .unwrap()
+                .keys(&cmd)
+                .iter()
+                .map(|k| k.to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(keys(&["get", "a"]), ["a"]);
+        assert_eq!(keys(&["mset", "a", "1", "b", "2"]), ["a", "b"]);
+        assert_eq!(keys(&["blpop", "a", "b", "0"]), ["a", "b"]);
+        assert_eq!(keys(&["bitop", "and", "d", "a", "b"]), ["d", "a", "b"]);
+        assert_eq!(
+            keys(&["sintercard", "2", "a", "b", "LIMIT", "1"]),
+            ["a", "b"]
+        );
+        assert_eq!(
+            keys(&["xread", "COUNT", "1", "STREAMS", "a", "b", "0", "0"]),
+            ["a", "b"]
+        );
+        assert!(keys(&["dbsize"]).is_empty());
+    }
+
+    #[test]
+    fn categories_follow_flags() {
+        let commands = CommandTable::new();
+        let categories = |name| commands.lookup(name).unwrap().acl_categories();
+        assert_eq!(categories("get"), ["read", "fast", "string"]);
+        assert_eq!(
+            categories("client"),
+            ["admin", "dangerous", "slow", "connection"]
+        );
+        assert_eq!(
+            categories("swapdb"),
+            ["write", "fast", "keyspace", "dangerous"]
+        );
+    }
+}
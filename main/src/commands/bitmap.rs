@@ -0,0 +1,606 @@
+use super::{NOT_AN_INTEGER, SYNTAX_ERROR, WRONGTYPE, parse_int, range, wrong_arity};
+use crate::{
+    protocol::Reply,
+    storage::{Db, Object, Value},
+};
+
+/// Bits are addressed with 32 bits, the same as the longest string
+const MAX_BITS: u64 = 1 << 32;
+
+const BAD_OFFSET: &str = "ERR bit offset is not an integer or out of range";
+
+/// Handles `SETBIT key offset value`, replying with the previous bit
+pub fn setbit(db: &mut Db, args: &[String]) -> Reply {
+    let [key, offset, bit] = args else {
+        return wrong_arity("setbit");
+    };
+    let offset = match parse_offset(offset) {
+        Ok(offset) => offset,
+        Err(e) => return e,
+    };
+    let bit = match bit.as_str() {
+        "0" => false,
+        "1" => true,
+        _ => return Reply::err("ERR bit is not an integer or out of range"),
+    };
+
+    write(db, key, |bytes| {
+        let old = get_bit(bytes, offset);
+        set_bit(bytes, offset, bit);
+        Reply::Int(old as i64)
+    })
+}
+
+/// Handles `GETBIT key offset`, bits past the end of the string are 0
+pub fn getbit(db: &mut Db, args: &[String]) -> Reply {
+    let [key, offset] = args else {
+        return wrong_arity("getbit");
+    };
+    let offset = match parse_offset(offset) {
+        Ok(offset) => offset,
+        Err(e) => return e,
+    };
+    read(db, key, |bytes| Reply::Int(get_bit(bytes, offset) as i64))
+}
+
+/// Handles `BITCOUNT key [start end [BYTE|BIT]]`
+pub fn bitcount(db: &mut Db, args: &[String]) -> Reply {
+    let (key, bounds) = match args {
+        [key] => (key, None),
+        [key, start, end, unit @ ..] if unit.len() <= 1 => (key, Some((start, end, unit.first()))),
+        [_, _] => return Reply::err(SYNTAX_ERROR),
+        _ => return wrong_arity("bitcount"),
+    };
+    let bounds = match bounds.map(|(start, end, unit)| parse_range(start, Some(end), unit)) {
+        Some(Ok(bounds)) => Some(bounds),
+        Some(Err(e)) => return e,
+        None => None,
+    };
+
+    read(db, key, |bytes| {
+        let bits = match bounds {
+            Some(bounds) => bit_range(bounds, bytes.len()),
+            None => (!bytes.is_empty()).then(|| (0, bytes.len() * 8 - 1)),
+        };
+        let count = bits.map_or(0, |(first, last)| count_ones(bytes, first, last));
+        Reply::Int(count as i64)
+    })
+}
+
+/// Handles `BITPOS key bit [start [end [BYTE|BIT]]]`, replying with the
+/// position of the first bit set to `bit` or -1
+pub fn bitpos(db: &mut Db, args: &[String]) -> Reply {
+    let [key, bit, bounds @ ..] = args else {
+        return wrong_arity("bitpos");
+    };
+    let bit = match bit.as_str() {
+        "0" => false,
+        "1" => true,
+        _ => return Reply::err("ERR The bit argument must be 1 or 0."),
+    };
+    let bounds = match bounds {
+        [] => None,
+        [start] => Some(parse_range(start, None, None)),
+        [start, end] => Some(parse_range(start, Some(end), None)),
+        [start, end, unit] => Some(parse_range(start, Some(end), Some(unit))),
+        _ => return Reply::err(SYNTAX_ERROR),
+    };
+    let bounds = match bounds.transpose() {
+        Ok(bounds) => bounds,
+        Err(e) => return e,
+    };
+    let end_given = bounds.is_some_and(|b| b.end.is_some());
+
+    read(db, key, |bytes| {
+        let bits = match bounds {
+            Some(bounds) => bit_range(bounds, bytes.len()),
+            None => (!bytes.is_empty()).then(|| (0, bytes.len() * 8 - 1)),
+        };
+        let Some((first, last)) = bits else {
+            // a missing key is all zeros
+            return Reply::Int(if !bit && bytes.is_empty() { 0 } else { -1 });
+        };
+        match find_bit(bytes, bit, first, last) {
+            Some(pos) => Reply::Int(pos as i64),
+            // without an end, the string counts as padded with zeros
+            None if !bit && !end_given => Reply::Int(last as i64 + 1),
+            None => Reply::Int(-1),
+        }
+    })
+}
+
+/// Handles `BITOP AND|OR|XOR|NOT destkey key [key ...]`. Shorter strings count
+/// as padded with zeros, replies with the length of the result
+pub fn bitop(db: &mut Db, args: &[String]) -> Reply {
+    let [op, dst, keys @ ..] = args else {
+        return wrong_arity("bitop");
+    };
+    if keys.is_empty() {
+        return wrong_arity("bitop");
+    }
+    let op = op.to_ascii_lowercase();
+    if op == "not" && keys.len() != 1 {
+        return Reply::err("ERR BITOP NOT must be called with a single source key.");
+    }
+
+    let mut sources = Vec::with_capacity(keys.len());
+    for key in keys {
+        match db.get(key).map(|obj| obj.value.to_bytes()) {
+            Some(Some(bytes)) => sources.push(bytes),
+            Some(None) => return Reply::err(WRONGTYPE),
+            None => sources.push(Vec::new()),
+        }
+    }
+
+    let len = sources.iter().map(Vec::len).max().unwrap_or(0);
+    let byte = |source: &Vec<u8>, i: usize| source.get(i).copied().unwrap_or(0);
+    let result: Vec<u8> = match op.as_str() {
+        "and" => (0..len)
+            .map(|i| sources.iter().fold(0xff, |acc, s| acc & byte(s, i)))
+            .collect(),
+        "or" => (0..len)
+            .map(|i| sources.iter().fold(0, |acc, s| acc | byte(s, i)))
+            .collect(),
+        "xor" => (0..len)
+            .map(|i| sources.iter().fold(0, |acc, s| acc ^ byte(s, i)))
+            .collect(),
+        "not" => sources[0].iter().map(|b| !b).collect(),
+        _ => return Reply::err(SYNTAX_ERROR),
+    };
+
+    if result.is_empty() {
+        db.remove(dst);
+    } else {
+        db.insert(dst, Object::new(Value::Str(result)));
+    }
+    Reply::Int(len as i64)
+}
+
+/// Handles `BITFIELD key [GET type offset] [SET type offset value]
+/// [INCRBY type offset increment] [OVERFLOW WRAP|SAT|FAIL] ...`,
+/// replying with one result per GET, SET and INCRBY
+pub fn bitfield(db: &mut Db, args: &[String]) -> Reply {
+    let [key, ops @ ..] = args else {
+        return wrong_arity("bitfield");
+    };
+    let ops = match parse_field_ops(ops, false) {
+        Ok(ops) => ops,
+        Err(e) => return e,
+    };
+
+    if ops.iter().all(|op| matches!(op, FieldOp::Get(_))) {
+        return read(db, key, |bytes| run_gets(bytes, &ops));
+    }
+    write(db, key, |bytes| {
+        Reply::Arr(ops.iter().map(|op| op.apply(bytes)).collect())
+    })
+}
+
+/// Handles `BITFIELD_RO key [GET type offset ...]`
+pub fn bitfield_ro(db: &mut Db, args: &[String]) -> Reply {
+    let [key, ops @ ..] = args else {
+        return wrong_arity("bitfield_ro");
+    };
+    match parse_field_ops(ops, true) {
+        Ok(ops) => read(db, key, |bytes| run_gets(bytes, &ops)),
+        Err(e) => e,
+    }
+}
+
+/// What to do when SET or INCRBY doesn't fit into a field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Overflow {
+    Wrap,
+    Sat,
+    Fail,
+}
+
+/// An integer field packed into a string, most significant bit first
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Field {
+    signed: bool,
+    bits: u32,
+    offset: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FieldOp {
+    Get(Field),
+    Set(Field, i64, Overflow),
+    IncrBy(Field, i64, Overflow),
+}
+
+fn parse_field_ops(args: &[String], read_only: bool) -> Result<Vec<FieldOp>, Reply> {
+    let mut ops = Vec::new();
+    let mut overflow = Overflow::Wrap;
+    let mut args = args.iter();
+
+    while let Some(op) = args.next() {
+        let op = op.to_ascii_lowercase();
+        if op == "overflow" && !read_only {
+            overflow = match args.next().map(|o| o.to_ascii_lowercase()).as_deref() {
+                Some("wrap") => Overflow::Wrap,
+                Some("sat") => Overflow::Sat,
+                Some("fail") => Overflow::Fail,
+                Some(_) => return Err(Reply::err("ERR Invalid OVERFLOW type specified")),
+                None => return Err(Reply::err(SYNTAX_ERROR)),
+            };
+            continue;
+        }
+
+        let (Some(ty), Some(offset)) = (args.next(), args.next()) else {
+            return Err(Reply::err(SYNTAX_ERROR));
+        };
+        let field = Field::parse(ty, offset)?;
+        ops.push(match op.as_str() {
+            "get" => FieldOp::Get(field),
+            "set" | "incrby" if read_only => {
+                return Err(Reply::err(
+                    "ERR BITFIELD_RO only supports the GET subcommand",
+                ));
+            }
+            "set" | "incrby" => {
+                let value = parse_int(args.next().ok_or_else(|| Reply::err(SYNTAX_ERROR))?)?;
+                if op == "set" {
+                    FieldOp::Set(field, value, overflow)
+                } else {
+                    FieldOp::IncrBy(field, value, overflow)
+                }
+            }
+            _ => return Err(Reply::err(SYNTAX_ERROR)),
+        });
+    }
+    Ok(ops)
+}
+
+fn run_gets(bytes: &[u8], ops: &[FieldOp]) -> Reply {
+    Reply::Arr(
+        ops.iter()
+            .filter_map(|op| match op {
+                FieldOp::Get(field) => Some(Reply::Int(field.get(bytes))),
+                _ => None,
+            })
+            .collect(),
+    )
+}
+
+impl FieldOp {
+    fn apply(&self, bytes: &mut Vec<u8>) -> Reply {
+        match *self {
+            FieldOp::Get(field) => Reply::Int(field.get(bytes)),
+            FieldOp::Set(field, value, overflow) => {
+                let old = field.get(bytes);
+                match field.fit(value as i128, overflow) {
+                    Some(value) => {
+                        field.set(bytes, value);
+                        Reply::Int(old)
+                    }
+                    None => Reply::Nil,
+                }
+            }
+            FieldOp::IncrBy(field, delta, overflow) => {
+                let new = field.get(bytes) as i128 + delta as i128;
+                match field.fit(new, overflow) {
+                    Some(new) => {
+                        field.set(bytes, new);
+                        Reply::Int(new)
+                    }
+                    None => Reply::Nil,
+                }
+            }
+        }
+    }
+}
+
+impl Field {
+    /// Parses a type like `i8` or `u16` and an offset, a `#` prefix
+    /// multiplying the offset by the width of the type
+    fn parse(ty: &str, offset: &str) -> Result<Self, Reply> {
+        let bad_type = || {
+            Reply::err(
+                "ERR Invalid bitfield type. Use something like i16 u8. \
+                 Note that u64 is not supported but i64 is.",
+            )
+        };
+        let signed = match ty.as_bytes().first() {
+            Some(b'i' | b'I') => true,
+            Some(b'u' | b'U') => false,
+            _ => return Err(bad_type()),
+        };
+        let bits = match ty[1..].parse::<u32>() {
+            Ok(bits) if bits >= 1 && (bits <= 63 || signed && bits == 64) => bits,
+            _ => return Err(bad_type()),
+        };
+
+        let offset = match offset.strip_prefix('#') {
+            Some(index) => index
+                .parse::<u64>()
+                .ok()
+                .and_then(|i| i.checked_mul(bits as u64)),
+            None => offset.parse::<u64>().ok(),
+        };
+        let end = offset.and_then(|offset| offset.checked_add(bits as u64));
+        match offset {
+            Some(offset) if end.is_some_and(|end| end <= MAX_BITS) => Ok(Field {
+                signed,
+                bits,
+                offset,
+            }),
+            _ => Err(Reply::err(BAD_OFFSET)),
+        }
+    }
+
+    fn get(&self, bytes: &[u8]) -> i64 {
+        let mut raw: u64 = 0;
+        for i in 0..self.bits as u64 {
+            raw = raw << 1 | get_bit(bytes, self.offset + i) as u64;
+        }
+        if self.signed && self.bits < 64 && raw >> (self.bits - 1) & 1 == 1 {
+            // sign extend
+            raw |= u64::MAX << self.bits;
+        }
+        raw as i64
+    }
+
+    fn set(&self, bytes: &mut Vec<u8>, value: i64) {
+        for i in 0..self.bits as u64 {
+            let bit = (value as u64) >> (self.bits as u64 - 1 - i) & 1 == 1;
+            set_bit(bytes, self.offset + i, bit);
+        }
+    }
+
+    /// Fits `value` into the range of the field, `None` if it overflows with `FAIL`
+    fn fit(&self, value: i128, overflow: Overflow) -> Option<i64> {
+        let (min, max) = if self.signed {
+            (-(1i128 << (self.bits - 1)), (1i128 << (self.bits - 1)) - 1)
+        } else {
+            (0, (1i128 << self.bits) - 1)
+        };
+        if (min..=max).contains(&value) {
+            return Some(value as i64);
+        }
+
+        match overflow {
+            Overflow::Fail => None,
+            Overflow::Sat => Some(value.clamp(min, max) as i64),
+            Overflow::Wrap => {
+                let wrapped = value.rem_euclid(1i128 << self.bits);
+                Some(if wrapped > max {
+                    wrapped - (1i128 << self.bits)
+                } else {
+                    wrapped
+                } as i64)
+            }
+        }
+    }
+}
+
+/// A range of `BITCOUNT` or `BITPOS`, in bytes or in bits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Bounds {
+    start: i64,
+    end: Option<i64>,
+    bits: bool,
+}
+
+fn parse_range(start: &str, end: Option<&String>, unit: Option<&String>) -> Result<Bounds, Reply> {
+    let bits = match unit.map(|u| u.to_ascii_lowercase()).as_deref() {
+        None | Some("byte") => false,
+        Some("bit") => true,
+        Some(_) => return Err(Reply::err(SYNTAX_ERROR)),
+    };
+    Ok(Bounds {
+        start: parse_int(start)?,
+        end: end.map(|e| parse_int(e)).transpose()?,
+        bits,
+    })
+}
+
+/// Resolves `bounds` to an inclusive range of bits of a `len` byte string
+fn bit_range(bounds: Bounds, len: usize) -> Option<(usize, usize)> {
+    let total = if bounds.bits { len * 8 } else { len };
+    let end = bounds.end.unwrap_or(-1);
+    // like GETRANGE, an end before the start of the string still selects the first unit
+    let end = end.max(-(total as i64));
+    let (start, end) = range(bounds.start, end, total)?;
+    if bounds.bits {
+        Some((start, end))
+    } else {
+        Some((start * 8, end * 8 + 7))
+    }
+}
+
+fn count_ones(bytes: &[u8], first: usize, last: usize) -> usize {
+    let (first_byte, last_byte) = (first / 8, last / 8);
+    let head = 0xffu8 >> (first % 8);
+    let tail = 0xffu8 << (7 - last % 8);
+    if first_byte == last_byte {
+        return (bytes[first_byte] & head & tail).count_ones() as usize;
+    }
+
+    let middle: usize = bytes[first_byte + 1..last_byte]
+        .iter()
+        .map(|b| b.count_ones() as usize)
+        .sum();
+    middle
+        + (bytes[first_byte] & head).count_ones() as usize
+        + (bytes[last_byte] & tail).count_ones() as usize
+}
+
+fn find_bit(bytes: &[u8], bit: bool, first: usize, last: usize) -> Option<usize> {
+    // whole bytes without the bit we look for are skipped
+    let skip = if bit { 0x00 } else { 0xff };
+    let mut pos = first;
+    while pos <= last {
+        if pos.is_multiple_of(8) && pos + 7 <= last && bytes[pos / 8] == skip {
+            pos += 8;
+            continue;
+        }
+        if get_bit(bytes, pos as u64) == bit {
+            return Some(pos);
+        }
+        pos += 1;
+    }
+    None
+}
+
+fn parse_offset(offset: &str) -> Result<u64, Reply> {
+    match offset.parse::<u64>() {
+        Ok(offset) if offset < MAX_BITS => Ok(offset),
+        Ok(_) => Err(Reply::err(BAD_OFFSET)),
+        Err(_) if offset.parse::<i64>().is_ok() => Err(Reply::err(BAD_OFFSET)),
+        Err(_) => Err(Reply::err(NOT_AN_INTEGER)),
+    }
+}
+
+fn get_bit(bytes: &[u8], offset: u64) -> bool {
+    bytes
+        .get((offset / 8) as usize)
+        .is_some_and(|b| b >> (7 - offset % 8) & 1 == 1)
+}
+
+/// Sets a bit, growing the string with zero bytes if needed
+fn set_bit(bytes: &mut Vec<u8>, offset: u64, bit: bool) {
+    let idx = (offset / 8) as usize;
+    if idx >= bytes.len() {
+        bytes.resize(idx + 1, 0);
+    }
+    let mask = 1 << (7 - offset % 8);
+    if bit {
+        bytes[idx] |= mask;
+    } else {
+        bytes[idx] &= !mask;
+    }
+}
+
+/// Runs `f` on the string at `key`, a missing key being empty
+fn read(db: &mut Db, key: &str, f: impl FnOnce(&[u8]) -> Reply) -> Reply {
+    match db.get(key).map(|obj| obj.value.as_bytes()) {
+        Some(Some(bytes)) => f(&bytes),
+        Some(None) => Reply::err(WRONGTYPE),
+        None => f(&[]),
+    }
+}
+
+/// Runs `f` on the string at `key`, creating it if `f` writes to it
+fn write(db: &mut Db, key: &str, f: impl FnOnce(&mut Vec<u8>) -> Reply) -> Reply {
+    let Some(mut obj) = db.get_mut(key) else {
+        let mut bytes = Vec::new();
+        let reply = f(&mut bytes);
+        if !bytes.is_empty() {
+            db.insert(key, Object::new(Value::Str(bytes)));
+        }
+        return reply;
+    };
+    match obj.value.make_raw() {
+        Some(bytes) => f(bytes),
+        None => Reply::err(WRONGTYPE),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::commands::args;
+
+    #[test]
+    fn bits() {
+        let mut db = Db::default();
+        assert_eq!(setbit(&mut db, &args(&["b", "7", "1"])), Reply::Int(0));
+        assert_eq!(setbit(&mut db, &args(&["b", "7", "1"])), Reply::Int(1));
+        assert_eq!(setbit(&mut db, &args(&["b", "20", "1"])), Reply::Int(0));
+        assert_eq!(getbit(&mut db, &args(&["b", "20"])), Reply::Int(1));
+        assert_eq!(getbit(&mut db, &args(&["b", "1000"])), Reply::Int(0));
+        assert_eq!(
+            crate::commands::string::get(&mut db, &args(&["b"])),
+            Reply::str([1, 0, 8])
+        );
+        assert_eq!(setbit(&mut db, &args(&["z", "100", "0"])), Reply::Int(0));
+        assert_eq!(
+            crate::commands::string::strlen(&mut db, &args(&["z"])),
+            Reply::Int(13)
+        );
+
+        assert_eq!(bitcount(&mut db, &args(&["b"])), Reply::Int(2));
+        assert_eq!(bitcount(&mut db, &args(&["b", "1", "-1"])), Reply::Int(1));
+        assert_eq!(
+            bitcount(&mut db, &args(&["b", "0", "7", "BIT"])),
+            Reply::Int(1)
+        );
+        assert_eq!(bitpos(&mut db, &args(&["b", "1"])), Reply::Int(7));
+        assert_eq!(bitpos(&mut db, &args(&["b", "1", "1"])), Reply::Int(20));
+        assert_eq!(bitpos(&mut db, &args(&["b", "0"])), Reply::Int(0));
+        assert_eq!(
+            bitpos(&mut db, &args(&["b", "1", "1", "1"])),
+            Reply::Int(-1)
+        );
+        assert_eq!(bitpos(&mut db, &args(&["missing", "0"])), Reply::Int(0));
+
+        db.insert("ones", Object::new(Value::Str(vec![0xff])));
+        assert_eq!(bitpos(&mut db, &args(&["ones", "0"])), Reply::Int(8));
+        assert_eq!(
+            bitpos(&mut db, &args(&["ones", "0", "0", "0"])),
+            Reply::Int(-1)
+        );
+    }
+
+    #[test]
+    fn bitop_pads_with_zeros() {
+        let mut db = Db::default();
+        db.insert("a", Object::new(Value::Str(vec![0b1100, 0xff])));
+        db.insert("b", Object::new(Value::Str(vec![0b1010])));
+
+        assert_eq!(
+            bitop(&mut db, &args(&["AND", "d", "a", "b"])),
+            Reply::Int(2)
+        );
+        assert_eq!(db.get("d").unwrap().value, Value::Str(vec![0b1000, 0]));
+        assert_eq!(
+            bitop(&mut db, &args(&["xor", "d", "a", "b", "c"])),
+            Reply::Int(2)
+        );
+        assert_eq!(db.get("d").unwrap().value, Value::Str(vec![0b0110, 0xff]));
+        assert_eq!(bitop(&mut db, &args(&["not", "d", "b"])), Reply::Int(1));
+        assert_eq!(db.get("d").unwrap().value, Value::Str(vec![!0b1010]));
+    }
+
+    #[test]
+    fn bitfield() {
+        let mut db = Db::default();
+        assert_eq!(
+            super::bitfield(
+                &mut db,
+                &args(&[
+                    "f", "SET", "u8", "#1", "255", "GET", "u4", "8", "GET", "i8", "8"
+                ])
+            ),
+            Reply::Arr(vec![Reply::Int(0), Reply::Int(15), Reply::Int(-1)])
+        );
+        assert_eq!(
+            super::bitfield(
+                &mut db,
+                &args(&[
+                    "f", "INCRBY", "u8", "8", "10", "OVERFLOW", "SAT", "INCRBY", "i8", "8", "-200",
+                    "OVERFLOW", "FAIL", "INCRBY", "i8", "8", "-1"
+                ])
+            ),
+            Reply::Arr(vec![Reply::Int(9), Reply::Int(-128), Reply::Nil])
+        );
+        assert_eq!(
+            bitfield_ro(&mut db, &args(&["f", "GET", "i64", "0"])),
+            Reply::Arr(vec![Reply::Int(0x80 << 48)])
+        );
+        assert_eq!(
+            super::bitfield(&mut db, &args(&["f", "GET", "u64", "0"])),
+            Reply::err(
+                "ERR Invalid bitfield type. Use something like i16 u8. \
+                 Note that u64 is not supported but i64 is."
+            )
+        );
+        let max = u64::MAX.to_string();
+        assert_eq!(
+            bitfield_ro(&mut db, &args(&["f", "GET", "i8", &max])),
+            Reply::err(BAD_OFFSET)
+        );
+    }
+}
@@ -0,0 +1,700 @@
+use std::collections::BTreeSet;
+
+use collections::SortedSet;
+
+use super::{SYNTAX_ERROR, WRONGTYPE, parse_float, wrong_arity};
+use crate::{
+    protocol::Reply,
+    storage::{Db, Object, Value},
+};
+
+/// Bits per coordinate of a geohash, interleaved into a 52 bit score
+/// that is exactly representable as an `f64`
+const STEP: u32 = 26;
+const LON_MIN: f64 = -180.0;
+const LON_MAX: f64 = 180.0;
+/// The latitudes covered by the web mercator projection
+const LAT_MIN: f64 = -85.05112878;
+const LAT_MAX: f64 = 85.05112878;
+/// The same radius the distances of other GEO implementations are based on
+const EARTH_RADIUS: f64 = 6372797.560856;
+
+const GEOHASH_ALPHABET: &[u8; 32] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+
+/// Handles `GEOADD key [NX|XX] [CH] longitude latitude member [...]`, replying
+/// with the number of members added, or changed with `CH`
+pub fn geoadd(db: &mut Db, args: &[String]) -> Reply {
+    let [key, rest @ ..] = args else {
+        return wrong_arity("geoadd");
+    };
+    let (mut nx, mut xx, mut ch) = (false, false, false);
+    let mut rest = rest;
+    while let [opt, tail @ ..] = rest {
+        match opt.to_ascii_lowercase().as_str() {
+            "nx" => nx = true,
+            "xx" => xx = true,
+            "ch" => ch = true,
+            _ => break,
+        }
+        rest = tail;
+    }
+    if nx && xx {
+        return Reply::err("ERR XX and NX options at the same time are not compatible");
+    }
+    if rest.is_empty() || !rest.len().is_multiple_of(3) {
+        return wrong_arity("geoadd");
+    }
+
+    let mut points = Vec::with_capacity(rest.len() / 3);
+    for triple in rest.chunks_exact(3) {
+        let (lon, lat) = match (parse_float(&triple[0]), parse_float(&triple[1])) {
+            (Ok(lon), Ok(lat)) => (lon, lat),
+            (Err(e), _) | (_, Err(e)) => return e,
+        };
+        if !(LON_MIN..=LON_MAX).contains(&lon) || !(LAT_MIN..=LAT_MAX).contains(&lat) {
+            return Reply::err(format!(
+                "ERR invalid longitude,latitude pair {lon:.6},{lat:.6}"
+            ));
+        }
+        points.push((&triple[2], encode(lon, lat) as f64));
+    }
+
+    let add = |zset: &mut SortedSet| {
+        let mut count = 0;
+        for (member, score) in points {
+            let old = zset.score(member);
+            if (nx && old.is_some()) || (xx && old.is_none()) {
+                continue;
+            }
+            zset.insert(member, score);
+            if old.is_none() || (ch && old != Some(score)) {
+                count += 1;
+            }
+        }
+        Reply::Int(count)
+    };
+
+    let Some(mut obj) = db.get_mut(key) else {
+        let mut zset = SortedSet::new();
+        let reply = add(&mut zset);
+        if !zset.is_empty() {
+            db.insert(key, Object::new(Value::SortedSet(zset)));
+        }
+        return reply;
+    };
+    match obj.value.as_sorted_set_mut() {
+        Some(zset) => add(zset),
+        None => Reply::err(WRONGTYPE),
+    }
+}
+
+/// Handles `GEOPOS key [member ...]`, replying with the longitude and
+/// latitude of every member, nil for missing ones
+pub fn geopos(db: &mut Db, args: &[String]) -> Reply {
+    let [key, members @ ..] = args else {
+        return wrong_arity("geopos");
+    };
+    let zset = match read(db, key) {
+        Ok(zset) => zset,
+        Err(e) => return e,
+    };
+    let positions = members.iter().map(|m| match zset.and_then(|z| z.score(m)) {
+        Some(score) => coord_reply(decode(score as u64)),
+        None => Reply::Nil,
+    });
+    Reply::Arr(positions.collect())
+}
+
+/// Handles `GEODIST key member1 member2 [M|KM|FT|MI]`,
+/// nil if either member is missing
+pub fn geodist(db: &mut Db, args: &[String]) -> Reply {
+    let (key, a, b, unit) = match args {
+        [key, a, b] => (key, a, b, None),
+        [key, a, b, unit] => (key, a, b, Some(unit)),
+        _ => return wrong_arity("geodist"),
+    };
+    let unit = match unit.map_or(Ok(1.0), |u| parse_unit(u)) {
+        Ok(unit) => unit,
+        Err(e) => return e,
+    };
+    let zset = match read(db, key) {
+        Ok(Some(zset)) => zset,
+        Ok(None) => return Reply::Nil,
+        Err(e) => return e,
+    };
+    match (zset.score(a), zset.score(b)) {
+        (Some(a), Some(b)) => {
+            let ((lon1, lat1), (lon2, lat2)) = (decode(a as u64), decode(b as u64));
+            dist_reply(distance(lon1, lat1, lon2, lat2) / unit)
+        }
+        _ => Reply::Nil,
+    }
+}
+
+/// Handles `GEOHASH key [member ...]`, replying with the standard 11 character
+/// geohash strings of the members, nil for missing ones
+pub fn geohash(db: &mut Db, args: &[String]) -> Reply {
+    let [key, members @ ..] = args else {
+        return wrong_arity("geohash");
+    };
+    let zset = match read(db, key) {
+        Ok(zset) => zset,
+        Err(e) => return e,
+    };
+    let hashes = members.iter().map(|m| match zset.and_then(|z| z.score(m)) {
+        Some(score) => Reply::str(geohash_string(score as u64)),
+        None => Reply::Nil,
+    });
+    Reply::Arr(hashes.collect())
+}
+
+/// Handles `GEOSEARCH key FROMMEMBER member|FROMLONLAT longitude latitude
+/// BYRADIUS radius unit|BYBOX width height unit [ASC|DESC] [COUNT count [ANY]]
+/// [WITHCOORD] [WITHDIST] [WITHHASH]`, replying with the matching members
+/// sorted by their distance from the center, nearest first unless `DESC`
+pub fn geosearch(db: &mut Db, args: &[String]) -> Reply {
+    let [key, opts @ ..] = args else {
+        return wrong_arity("geosearch");
+    };
+    let search = match Search::parse(opts) {
+        Ok(search) => search,
+        Err(e) => return e,
+    };
+    let zset = match read(db, key) {
+        Ok(Some(zset)) => zset,
+        Ok(None) => return Reply::Arr(Vec::new()),
+        Err(e) => return e,
+    };
+    let (lon, lat) = match &search.center {
+        Center::Member(member) => match zset.score(member) {
+            Some(score) => decode(score as u64),
+            None => return Reply::err("ERR could not decode requested zset member"),
+        },
+        Center::LonLat(lon, lat) => (*lon, *lat),
+    };
+
+    let mut found = Vec::new();
+    'cells: for (min, max) in candidate_ranges(lon, lat, search.shape) {
+        for (member, score) in zset.range_by_score(min as f64, max as f64) {
+            let hash = score as u64;
+            let (x, y) = decode(hash);
+            if let Some(dist) = search.shape.distance(lon, lat, x, y) {
+                found.push(Match {
+                    member,
+                    dist,
+                    hash,
+                    coord: (x, y),
+                });
+                if search.any && Some(found.len()) == search.count {
+                    break 'cells;
+                }
+            }
+        }
+    }
+
+    found.sort_by(|a, b| a.dist.total_cmp(&b.dist));
+    if search.desc {
+        found.reverse();
+    }
+    found.truncate(search.count.unwrap_or(usize::MAX));
+
+    let replies = found.into_iter().map(|m| {
+        if !(search.withdist || search.withhash || search.withcoord) {
+            return Reply::str(m.member);
+        }
+        let mut reply = vec![Reply::str(m.member)];
+        if search.withdist {
+            reply.push(dist_reply(m.dist / search.shape.unit()));
+        }
+        if search.withhash {
+            reply.push(Reply::Int(m.hash as i64));
+        }
+        if search.withcoord {
+            reply.push(coord_reply(m.coord));
+        }
+        Reply::Arr(reply)
+    });
+    Reply::Arr(replies.collect())
+}
+
+/// A member found by `GEOSEARCH`, its distance from the center in meters
+struct Match<'a> {
+    member: &'a str,
+    dist: f64,
+    hash: u64,
+    coord: (f64, f64),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Center {
+    Member(String),
+    LonLat(f64, f64),
+}
+
+/// The area searched around the center, in meters. `unit` is the size of
+/// the unit the dimensions were given in, distances are replied in it
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Shape {
+    Radius { radius: f64, unit: f64 },
+    Box { width: f64, height: f64, unit: f64 },
+}
+
+impl Shape {
+    fn unit(&self) -> f64 {
+        match *self {
+            Shape::Radius { unit, .. } | Shape::Box { unit, .. } => unit,
+        }
+    }
+
+    /// The distance of `(lon2, lat2)` from the center `(lon1, lat1)`,
+    /// `None` if it is outside the shape
+    fn distance(&self, lon1: f64, lat1: f64, lon2: f64, lat2: f64) -> Option<f64> {
+        match *self {
+            Shape::Radius { radius, .. } => {
+                let dist = distance(lon1, lat1, lon2, lat2);
+                (dist <= radius).then_some(dist)
+            }
+            Shape::Box { width, height, .. } => {
+                let lat_dist = EARTH_RADIUS * (lat2.to_radians() - lat1.to_radians()).abs();
+                if lat_dist > height / 2.0 || distance(lon1, lat2, lon2, lat2) > width / 2.0 {
+                    return None;
+                }
+                Some(distance(lon1, lat1, lon2, lat2))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Search {
+    center: Center,
+    shape: Shape,
+    desc: bool,
+    count: Option<usize>,
+    any: bool,
+    withcoord: bool,
+    withdist: bool,
+    withhash: bool,
+}
+
+impl Search {
+    fn parse(opts: &[String]) -> Result<Self, Reply> {
+        let (mut center, mut shape) = (None, None);
+        let (mut desc, mut count, mut any) = (false, None, false);
+        let (mut withcoord, mut withdist, mut withhash) = (false, false, false);
+
+        let mut opts = opts;
+        while let [opt, rest @ ..] = opts {
+            opts = match (opt.to_ascii_lowercase().as_str(), rest) {
+                ("frommember", [member, rest @ ..]) if center.is_none() => {
+                    center = Some(Center::Member(member.clone()));
+                    rest
+                }
+                ("fromlonlat", [lon, lat, rest @ ..]) if center.is_none() => {
+                    let (lon, lat) = (parse_float(lon)?, parse_float(lat)?);
+                    if !(LON_MIN..=LON_MAX).contains(&lon) || !(LAT_MIN..=LAT_MAX).contains(&lat) {
+                        return Err(Reply::err(format!(
+                            "ERR invalid longitude,latitude pair {lon:.6},{lat:.6}"
+                        )));
+                    }
+                    center = Some(Center::LonLat(lon, lat));
+                    rest
+                }
+                ("frommember" | "fromlonlat", _) if center.is_some() => {
+                    return Err(Reply::err(
+                        "ERR exactly one of FROMMEMBER or FROMLONLAT can be specified for GEOSEARCH",
+                    ));
+                }
+                ("byradius", [radius, unit, rest @ ..]) if shape.is_none() => {
+                    let (radius, unit) = (parse_dimension(radius)?, parse_unit(unit)?);
+                    shape = Some(Shape::Radius {
+                        radius: radius * unit,
+                        unit,
+                    });
+                    rest
+                }
+                ("bybox", [width, height, unit, rest @ ..]) if shape.is_none() => {
+                    let (width, height) = (parse_dimension(width)?, parse_dimension(height)?);
+                    let unit = parse_unit(unit)?;
+                    shape = Some(Shape::Box {
+                        width: width * unit,
+                        height: height * unit,
+                        unit,
+                    });
+                    rest
+                }
+                ("byradius" | "bybox", _) if shape.is_some() => {
+                    return Err(Reply::err(
+                        "ERR exactly one of BYRADIUS and BYBOX can be specified for GEOSEARCH",
+                    ));
+                }
+                ("asc", _) => {
+                    desc = false;
+                    rest
+                }
+                ("desc", _) => {
+                    desc = true;
+                    rest
+                }
+                ("count", [n, rest @ ..]) => {
+                    match n.parse::<i64>() {
+                        Ok(n) if n > 0 => count = Some(n as usize),
+                        _ => return Err(Reply::err("ERR COUNT must be > 0")),
+                    }
+                    match rest {
+                        [opt, rest @ ..] if opt.eq_ignore_ascii_case("any") => {
+                            any = true;
+                            rest
+                        }
+                        _ => rest,
+                    }
+                }
+                ("withcoord", _) => {
+                    withcoord = true;
+                    rest
+                }
+                ("withdist", _) => {
+                    withdist = true;
+                    rest
+                }
+                ("withhash", _) => {
+                    withhash = true;
+                    rest
+                }
+                _ => return Err(Reply::err(SYNTAX_ERROR)),
+            };
+        }
+
+        let Some(center) = center else {
+            return Err(Reply::err(
+                "ERR exactly one of FROMMEMBER or FROMLONLAT can be specified for GEOSEARCH",
+            ));
+        };
+        let Some(shape) = shape else {
+            return Err(Reply::err(
+                "ERR exactly one of BYRADIUS and BYBOX can be specified for GEOSEARCH",
+            ));
+        };
+        Ok(Search {
+            center,
+            shape,
+            desc,
+            count,
+            any,
+            withcoord,
+            withdist,
+            withhash,
+        })
+    }
+}
+
+/// Parses a radius, width or height, which can not be negative
+fn parse_dimension(arg: &str) -> Result<f64, Reply> {
+    match parse_float(arg)? {
+        n if n < 0.0 => Err(Reply::err("ERR radius cannot be negative")),
+        n => Ok(n),
+    }
+}
+
+/// The size of a unit in meters
+fn parse_unit(unit: &str) -> Result<f64, Reply> {
+    match unit.to_ascii_lowercase().as_str() {
+        "m" => Ok(1.0),
+        "km" => Ok(1000.0),
+        "ft" => Ok(0.3048),
+        "mi" => Ok(1609.34),
+        _ => Err(Reply::err(
+            "ERR unsupported unit provided. please use M, KM, FT, MI",
+        )),
+    }
+}
+
+fn dist_reply(dist: f64) -> Reply {
+    Reply::str(format!("{dist:.4}"))
+}
+
+fn coord_reply((lon, lat): (f64, f64)) -> Reply {
+    Reply::Arr(vec![
+        Reply::str(lon.to_string()),
+        Reply::str(lat.to_string()),
+    ])
+}
+
+/// Great circle distance in meters, with the haversine formula
+fn distance(lon1: f64, lat1: f64, lon2: f64, lat2: f64) -> f64 {
+    let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+    let u = ((lat2 - lat1) / 2.0).sin();
+    let v = ((lon2.to_radians() - lon1.to_radians()) / 2.0).sin();
+    2.0 * EARTH_RADIUS * (u * u + lat1.cos() * lat2.cos() * v * v).sqrt().asin()
+}
+
+/// Encodes a point into a geohash of `STEP` bits per coordinate,
+/// the longitude taking the odd bits and the latitude the even ones
+fn encode(lon: f64, lat: f64) -> u64 {
+    encode_in(lon, lat, LAT_MIN, LAT_MAX)
+}
+
+fn encode_in(lon: f64, lat: f64, lat_min: f64, lat_max: f64) -> u64 {
+    let cells = (1u64 << STEP) as f64;
+    let lat = ((lat - lat_min) / (lat_max - lat_min) * cells) as u64;
+    let lon = ((lon - LON_MIN) / (LON_MAX - LON_MIN) * cells) as u64;
+    let max = (1 << STEP) - 1;
+    interleave(lat.min(max) as u32, lon.min(max) as u32)
+}
+
+/// Decodes a geohash into the center of its cell
+fn decode(hash: u64) -> (f64, f64) {
+    let cells = (1u64 << STEP) as f64;
+    let (lat, lon) = (squash(hash), squash(hash >> 1));
+    let lat_cell = (LAT_MAX - LAT_MIN) / cells;
+    let lon_cell = (LON_MAX - LON_MIN) / cells;
+    let lat = LAT_MIN + (lat as f64 + 0.5) * lat_cell;
+    let lon = LON_MIN + (lon as f64 + 0.5) * lon_cell;
+    (lon.clamp(LON_MIN, LON_MAX), lat.clamp(LAT_MIN, LAT_MAX))
+}
+
+/// The geohash in the standard base32 form, which covers latitudes up to the poles
+fn geohash_string(hash: u64) -> String {
+    let (lon, lat) = decode(hash);
+    let bits = encode_in(lon, lat, -90.0, 90.0);
+    (0..11)
+        .map(|i| {
+            // 52 bits only fill 10 characters and a bit
+            let index = if i == 10 {
+                0
+            } else {
+                (bits >> (52 - (i + 1) * 5)) & 0x1f
+            };
+            GEOHASH_ALPHABET[index as usize] as char
+        })
+        .collect()
+}
+
+/// Spreads the bits of `lat` into the even bits and `lon` into the odd bits
+fn interleave(lat: u32, lon: u32) -> u64 {
+    spread(lat) | spread(lon) << 1
+}
+
+fn spread(n: u32) -> u64 {
+    let mut n = n as u64;
+    n = (n | n << 16) & 0x0000_ffff_0000_ffff;
+    n = (n | n << 8) & 0x00ff_00ff_00ff_00ff;
+    n = (n | n << 4) & 0x0f0f_0f0f_0f0f_0f0f;
+    n = (n | n << 2) & 0x3333_3333_3333_3333;
+    (n | n << 1) & 0x5555_5555_5555_5555
+}
+
+/// The inverse of `spread`, keeping the even bits
+fn squash(n: u64) -> u32 {
+    let mut n = n & 0x5555_5555_5555_5555;
+    n = (n | n >> 1) & 0x3333_3333_3333_3333;
+    n = (n | n >> 2) & 0x0f0f_0f0f_0f0f_0f0f;
+    n = (n | n >> 4) & 0x00ff_00ff_00ff_00ff;
+    n = (n | n >> 8) & 0x0000_ffff_0000_ffff;
+    (n | n >> 16) as u32
+}
+
+/// The score ranges of the geohash cells that cover the search area.
+///
+/// Picks the finest precision at which a cell is at least as large as the
+/// bounding box of the area, so at most 2x2 cells need to be looked at
+fn candidate_ranges(lon: f64, lat: f64, shape: Shape) -> BTreeSet<(u64, u64)> {
+    let (half_width, half_height) = match shape {
+        Shape::Radius { radius, .. } => (radius, radius),
+        Shape::Box { width, height, .. } => (width / 2.0, height / 2.0),
+    };
+    let dlat = (half_height / EARTH_RADIUS).to_degrees();
+    // the parallel furthest from the equator is where the box is the widest
+    let max_lat = (lat.abs() + dlat).min(90.0).to_radians();
+    let dlon = match (half_width / (2.0 * EARTH_RADIUS)).sin() / max_lat.cos() {
+        ratio if ratio < 1.0 => (2.0 * ratio.asin()).to_degrees().min(180.0),
+        _ => 180.0,
+    };
+
+    let step = (0..=STEP)
+        .rev()
+        .find(|&step| {
+            let cells = (1u64 << step) as f64;
+            (LAT_MAX - LAT_MIN) / cells >= 2.0 * dlat && (LON_MAX - LON_MIN) / cells >= 2.0 * dlon
+        })
+        .unwrap_or(0);
+    let cells = 1i64 << step;
+    let lat_cell = (LAT_MAX - LAT_MIN) / cells as f64;
+    let lon_cell = (LON_MAX - LON_MIN) / cells as f64;
+
+    let lat_index = |lat: f64| (((lat - LAT_MIN) / lat_cell).floor() as i64).clamp(0, cells - 1);
+    let lon_index = |lon: f64| ((lon - LON_MIN) / lon_cell).floor() as i64;
+    let shift = 2 * (STEP - step);
+
+    let mut ranges = BTreeSet::new();
+    for y in lat_index(lat - dlat)..=lat_index(lat + dlat) {
+        for x in lon_index(lon - dlon)..=lon_index(lon + dlon) {
+            // the area may wrap around the antimeridian
+            let x = x.rem_euclid(cells);
+            let start = interleave(y as u32, x as u32) << shift;
+            ranges.insert((start, start + (1 << shift)));
+        }
+    }
+    ranges
+}
+
+fn read<'a>(db: &'a mut Db, key: &str) -> Result<Option<&'a SortedSet>, Reply> {
+    match db.get(key).map(|obj| obj.value.as_sorted_set()) {
+        Some(Some(zset)) => Ok(Some(zset)),
+        Some(None) => Err(Reply::err(WRONGTYPE)),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::commands::args;
+
+    fn sicily(db: &mut Db) {
+        let reply = geoadd(
+            db,
+            &args(&[
+                "Sicily",
+                "13.361389",
+                "38.115556",
+                "Palermo",
+                "15.087269",
+                "37.502669",
+                "Catania",
+            ]),
+        );
+        assert_eq!(reply, Reply::Int(2));
+    }
+
+    #[test]
+    fn geohash_roundtrip() {
+        for (lon, lat) in [
+            (13.361389, 38.115556),
+            (-179.9, -85.0),
+            (179.9, 85.0),
+            (0.0, 0.0),
+        ] {
+            let (x, y) = decode(encode(lon, lat));
+            assert!(
+                (x - lon).abs() < 1e-5 && (y - lat).abs() < 1e-5,
+                "{lon},{lat}"
+            );
+        }
+        assert_eq!(squash(spread(0x3ff_ffff)), 0x3ff_ffff);
+    }
+
+    #[test]
+    fn positions_and_distances() {
+        let mut db = Db::default();
+        sicily(&mut db);
+
+        assert_eq!(
+            geodist(&mut db, &args(&["Sicily", "Palermo", "Catania", "km"])),
+            Reply::str("166.2742")
+        );
+        assert_eq!(
+            geodist(&mut db, &args(&["Sicily", "Palermo", "missing"])),
+            Reply::Nil
+        );
+        assert_eq!(
+            geohash(&mut db, &args(&["Sicily", "Palermo", "missing"])),
+            Reply::Arr(vec![Reply::str("sqc8b49rny0"), Reply::Nil])
+        );
+        let Reply::Arr(pos) = geopos(&mut db, &args(&["Sicily", "Catania"])) else {
+            panic!("expected an array");
+        };
+        assert!(matches!(&pos[0], Reply::Arr(coord) if coord.len() == 2));
+
+        assert_eq!(
+            geoadd(&mut db, &args(&["Sicily", "200", "10", "x"])),
+            Reply::err("ERR invalid longitude,latitude pair 200.000000,10.000000")
+        );
+        assert_eq!(
+            geodist(&mut db, &args(&["Sicily", "Palermo", "Catania", "yd"])),
+            Reply::err("ERR unsupported unit provided. please use M, KM, FT, MI")
+        );
+    }
+
+    #[test]
+    fn search() {
+        let mut db = Db::default();
+        sicily(&mut db);
+        geoadd(
+            &mut db,
+            &args(&[
+                "Sicily",
+                "12.758489",
+                "38.788135",
+                "edge1",
+                "17.241510",
+                "38.788135",
+                "edge2",
+            ]),
+        );
+
+        let search = |db: &mut Db, opts: &[&str]| {
+            let mut cmd = args(&["Sicily"]);
+            cmd.extend(args(opts));
+            geosearch(db, &cmd)
+        };
+        assert_eq!(
+            search(
+                &mut db,
+                &["FROMLONLAT", "15", "37", "BYRADIUS", "200", "km", "ASC"]
+            ),
+            Reply::Arr(vec![Reply::str("Catania"), Reply::str("Palermo")])
+        );
+        assert_eq!(
+            search(
+                &mut db,
+                &[
+                    "FROMMEMBER",
+                    "Palermo",
+                    "BYRADIUS",
+                    "200",
+                    "km",
+                    "DESC",
+                    "COUNT",
+                    "1"
+                ]
+            ),
+            Reply::Arr(vec![Reply::str("Catania")])
+        );
+        assert_eq!(
+            search(
+                &mut db,
+                &[
+                    "FROMLONLAT",
+                    "15",
+                    "37",
+                    "BYBOX",
+                    "400",
+                    "400",
+                    "km",
+                    "WITHDIST"
+                ]
+            ),
+            Reply::Arr(vec![
+                Reply::Arr(vec![Reply::str("Catania"), Reply::str("56.4413")]),
+                Reply::Arr(vec![Reply::str("Palermo"), Reply::str("190.4424")]),
+                Reply::Arr(vec![Reply::str("edge2"), Reply::str("279.7403")]),
+                Reply::Arr(vec![Reply::str("edge1"), Reply::str("279.7405")]),
+            ])
+        );
+        assert_eq!(
+            search(&mut db, &["FROMLONLAT", "15", "37", "BYRADIUS", "10", "km"]),
+            Reply::Arr(vec![])
+        );
+        assert_eq!(
+            search(&mut db, &["FROMMEMBER", "missing", "BYRADIUS", "10", "km"]),
+            Reply::err("ERR could not decode requested zset member")
+        );
+        assert_eq!(
+            search(&mut db, &["BYRADIUS", "10", "km"]),
+            Reply::err(
+                "ERR exactly one of FROMMEMBER or FROMLONLAT can be specified for GEOSEARCH"
+            )
+        );
+    }
+}
@@ -0,0 +1,446 @@
+use collections::FieldMap;
+use rand::seq::index;
+
+use super::{
+    SYNTAX_ERROR, ScanOptions, WRONGTYPE, parse_float, parse_int, repeated_picks, wrong_arity,
+};
+use crate::{
+    protocol::Reply,
+    storage::{Db, Object, Value},
+};
+
+/// Handles `HSET key field value [field value ...]`, replying with the number of new fields
+pub fn hset(db: &mut Db, args: &[String]) -> Reply {
+    match args {
+        [key, pairs @ ..] if !pairs.is_empty() && pairs.len().is_multiple_of(2) => {
+            update(db, key, |hash| {
+                let added = pairs
+                    .chunks(2)
+                    .filter(|pair| hash.insert(&pair[0], pair[1].as_bytes().to_vec()).is_none())
+                    .count();
+                Reply::Int(added as i64)
+            })
+        }
+        _ => wrong_arity("hset"),
+    }
+}
+
+/// Handles `HMSET key field value [field value ...]`, the deprecated form of `HSET`
+pub fn hmset(db: &mut Db, args: &[String]) -> Reply {
+    if args.len() < 3 || !args.len().is_multiple_of(2) {
+        return wrong_arity("hmset");
+    }
+    match hset(db, args) {
+        Reply::Int(_) => Reply::ok(),
+        e => e,
+    }
+}
+
+/// Handles `HSETNX key field value`, only setting fields that don't exist yet
+pub fn hsetnx(db: &mut Db, args: &[String]) -> Reply {
+    let [key, field, value] = args else {
+        return wrong_arity("hsetnx");
+    };
+    update(db, key, |hash| {
+        if hash.contains_key(field) {
+            return Reply::Int(0);
+        }
+        hash.insert(field, value.as_bytes().to_vec());
+        Reply::Int(1)
+    })
+}
+
+/// Handles `HGET key field`
+pub fn hget(db: &mut Db, args: &[String]) -> Reply {
+    let [key, field] = args else {
+        return wrong_arity("hget");
+    };
+    match read(db, key) {
+        Ok(Some(hash)) => hash.get(field).map_or(Reply::Nil, Reply::str),
+        Ok(None) => Reply::Nil,
+        Err(e) => e,
+    }
+}
+
+/// Handles `HMGET key field [field ...]`, replying with nil for missing fields
+pub fn hmget(db: &mut Db, args: &[String]) -> Reply {
+    let [key, fields @ ..] = args else {
+        return wrong_arity("hmget");
+    };
+    if fields.is_empty() {
+        return wrong_arity("hmget");
+    }
+    match read(db, key) {
+        Ok(hash) => Reply::Arr(
+            fields
+                .iter()
+                .map(|f| hash.and_then(|h| h.get(f)).map_or(Reply::Nil, Reply::str))
+                .collect(),
+        ),
+        Err(e) => e,
+    }
+}
+
+/// Handles `HDEL key field [field ...]`, deleting the key once the hash is empty
+pub fn hdel(db: &mut Db, args: &[String]) -> Reply {
+    let [key, fields @ ..] = args else {
+        return wrong_arity("hdel");
+    };
+    if fields.is_empty() {
+        return wrong_arity("hdel");
+    }
+    match read(db, key) {
+        Ok(Some(_)) => update(db, key, |hash| {
+            let removed = fields.iter().filter(|f| hash.remove(f).is_some()).count();
+            Reply::Int(removed as i64)
+        }),
+        Ok(None) => Reply::Int(0),
+        Err(e) => e,
+    }
+}
+
+/// Handles `HLEN key`
+pub fn hlen(db: &mut Db, args: &[String]) -> Reply {
+    let [key] = args else {
+        return wrong_arity("hlen");
+    };
+    match read(db, key) {
+        Ok(hash) => Reply::Int(hash.map_or(0, FieldMap::len) as i64),
+        Err(e) => e,
+    }
+}
+
+/// Handles `HSTRLEN key field`
+pub fn hstrlen(db: &mut Db, args: &[String]) -> Reply {
+    let [key, field] = args else {
+        return wrong_arity("hstrlen");
+    };
+    match read(db, key) {
+        Ok(hash) => Reply::Int(hash.and_then(|h| h.get(field)).map_or(0, <[u8]>::len) as i64),
+        Err(e) => e,
+    }
+}
+
+/// Handles `HEXISTS key field`
+pub fn hexists(db: &mut Db, args: &[String]) -> Reply {
+    let [key, field] = args else {
+        return wrong_arity("hexists");
+    };
+    match read(db, key) {
+        Ok(hash) => Reply::Int(hash.is_some_and(|h| h.contains_key(field)) as i64),
+        Err(e) => e,
+    }
+}
+
+/// Handles `HKEYS key`
+pub fn hkeys(db: &mut Db, args: &[String]) -> Reply {
+    let [key] = args else {
+        return wrong_arity("hkeys");
+    };
+    all(db, key, |field, _, out| out.push(Reply::str(field)))
+}
+
+/// Handles `HVALS key`
+pub fn hvals(db: &mut Db, args: &[String]) -> Reply {
+    let [key] = args else {
+        return wrong_arity("hvals");
+    };
+    all(db, key, |_, value, out| out.push(Reply::str(value)))
+}
+
+/// Handles `HGETALL key`, replying with a flat array of fields and values
+pub fn hgetall(db: &mut Db, args: &[String]) -> Reply {
+    let [key] = args else {
+        return wrong_arity("hgetall");
+    };
+    all(db, key, |field, value, out| {
+        out.push(Reply::str(field));
+        out.push(Reply::str(value));
+    })
+}
+
+/// Handles `HINCRBY key field increment`, a missing field counting as 0
+pub fn hincrby(db: &mut Db, args: &[String]) -> Reply {
+    let [key, field, delta] = args else {
+        return wrong_arity("hincrby");
+    };
+    let delta = match parse_int(delta) {
+        Ok(d) => d,
+        Err(e) => return e,
+    };
+
+    update(db, key, |hash| {
+        let current = match hash.get(field).map(std::str::from_utf8) {
+            None => 0,
+            Some(Ok(s)) => match s.parse::<i64>() {
+                Ok(n) => n,
+                Err(_) => return Reply::err("ERR hash value is not an integer"),
+            },
+            Some(Err(_)) => return Reply::err("ERR hash value is not an integer"),
+        };
+        let Some(new) = current.checked_add(delta) else {
+            return Reply::err("ERR increment or decrement would overflow");
+        };
+        hash.insert(field, new.to_string().into_bytes());
+        Reply::Int(new)
+    })
+}
+
+/// Handles `HINCRBYFLOAT key field increment`, replying with the new value as a string
+pub fn hincrbyfloat(db: &mut Db, args: &[String]) -> Reply {
+    let [key, field, delta] = args else {
+        return wrong_arity("hincrbyfloat");
+    };
+    let delta = match parse_float(delta) {
+        Ok(d) => d,
+        Err(e) => return e,
+    };
+
+    update(db, key, |hash| {
+        let current = match hash.get(field).map(std::str::from_utf8) {
+            None => 0.0,
+            Some(Ok(s)) => match parse_float(s) {
+                Ok(n) => n,
+                Err(_) => return Reply::err("ERR hash value is not a float"),
+            },
+            Some(Err(_)) => return Reply::err("ERR hash value is not a float"),
+        };
+        let new = current + delta;
+        if !new.is_finite() {
+            return Reply::err("ERR increment would produce NaN or Infinity");
+        }
+        let new = new.to_string();
+        hash.insert(field, new.clone().into_bytes());
+        Reply::str(new)
+    })
+}
+
+/// Handles `HRANDFIELD key [count [WITHVALUES]]`. A positive count picks distinct
+/// fields, a negative one may pick the same field more than once
+pub fn hrandfield(db: &mut Db, args: &[String]) -> Reply {
+    let (key, count, with_values) = match args {
+        [key] => (key, None, false),
+        [key, count] => (key, Some(count), false),
+        [key, count, opt] if opt.eq_ignore_ascii_case("withvalues") => (key, Some(count), true),
+        [_, _, _] => return Reply::err(SYNTAX_ERROR),
+        _ => return wrong_arity("hrandfield"),
+    };
+    let count = match count.map(|c| parse_int(c)).transpose() {
+        Ok(count) => count,
+        Err(e) => return e,
+    };
+
+    let hash = match read(db, key) {
+        Ok(Some(hash)) => hash,
+        Ok(None) if count.is_some() => return Reply::Arr(Vec::new()),
+        Ok(None) => return Reply::Nil,
+        Err(e) => return e,
+    };
+    let mut rng = rand::rng();
+
+    let Some(count) = count else {
+        return hash
+            .random(&mut rng)
+            .map_or(Reply::Nil, |(field, _)| Reply::str(field));
+    };
+    let picked: Vec<(&str, &[u8])> = if count >= 0 {
+        let fields: Vec<(&str, &[u8])> = hash.iter().collect();
+        let amount = (count as usize).min(fields.len());
+        let picked = index::sample(&mut rng, fields.len(), amount);
+        picked.into_iter().map(|i| fields[i]).collect()
+    } else {
+        match repeated_picks(count) {
+            Ok(n) => (0..n).filter_map(|_| hash.random(&mut rng)).collect(),
+            Err(e) => return e,
+        }
+    };
+
+    let mut out = Vec::new();
+    for (field, value) in picked {
+        out.push(Reply::str(field));
+        if with_values {
+            out.push(Reply::str(value));
+        }
+    }
+    Reply::Arr(out)
+}
+
+/// Handles `HSCAN key cursor [MATCH pattern] [COUNT count] [NOVALUES]`,
+/// replying with the next cursor and a flat array of fields and values
+pub fn hscan(db: &mut Db, args: &[String]) -> Reply {
+    let [key, cursor, opts @ ..] = args else {
+        return wrong_arity("hscan");
+    };
+    let scan = match ScanOptions::parse(cursor, opts, true) {
+        Ok(scan) => scan,
+        Err(e) => return e,
+    };
+
+    let hash = match read(db, key) {
+        Ok(Some(hash)) => hash,
+        Ok(None) => return Reply::Arr(vec![Reply::str("0"), Reply::Arr(Vec::new())]),
+        Err(e) => return e,
+    };
+    scan.run(|cursor, visited, out| {
+        hash.scan(cursor, |field, value| {
+            *visited += 1;
+            if scan.matches(field) {
+                out.push(Reply::str(field));
+                if !scan.novalues {
+                    out.push(Reply::str(value));
+                }
+            }
+        })
+    })
+}
+
+/// The hash stored at `key`, `None` if the key doesn't exist
+fn read<'a>(db: &'a mut Db, key: &str) -> Result<Option<&'a FieldMap>, Reply> {
+    match db.get(key).map(|obj| obj.value.as_hash()) {
+        Some(Some(hash)) => Ok(Some(hash)),
+        Some(None) => Err(Reply::err(WRONGTYPE)),
+        None => Ok(None),
+    }
+}
+
+/// Runs `f` on the hash at `key`, creating it if needed,
+/// and deleting the key if the hash ends up empty
+fn update(db: &mut Db, key: &str, f: impl FnOnce(&mut FieldMap) -> Reply) -> Reply {
+    let Some(mut obj) = db.get_mut(key) else {
+        let mut hash = FieldMap::new();
+        let reply = f(&mut hash);
+        if !hash.is_empty() {
+            db.insert(key, Object::new(Value::Hash(hash)));
+        }
+        return reply;
+    };
+    let Some(hash) = obj.value.as_hash_mut() else {
+        return Reply::err(WRONGTYPE);
+    };
+    let reply = f(hash);
+    if hash.is_empty() {
+        drop(obj);
+        db.remove(key);
+    }
+    reply
+}
+
+/// Collects every field of the hash at `key` with `f`
+fn all(db: &mut Db, key: &str, f: impl Fn(&str, &[u8], &mut Vec<Reply>)) -> Reply {
+    match read(db, key) {
+        Ok(hash) => {
+            let mut out = Vec::new();
+            for (field, value) in hash.into_iter().flat_map(FieldMap::iter) {
+                f(field, value, &mut out);
+            }
+            Reply::Arr(out)
+        }
+        Err(e) => e,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::commands::{NOT_A_FLOAT, args};
+
+    #[test]
+    fn set_get_delete() {
+        let mut db = Db::default();
+        assert_eq!(
+            hset(&mut db, &args(&["h", "a", "1", "b", "2"])),
+            Reply::Int(2)
+        );
+        assert_eq!(
+            hset(&mut db, &args(&["h", "a", "3", "c", "4"])),
+            Reply::Int(1)
+        );
+        assert_eq!(hsetnx(&mut db, &args(&["h", "a", "5"])), Reply::Int(0));
+        assert_eq!(hget(&mut db, &args(&["h", "a"])), Reply::str("3"));
+        assert_eq!(
+            hmget(&mut db, &args(&["h", "b", "x"])),
+            Reply::Arr(vec![Reply::str("2"), Reply::Nil])
+        );
+        assert_eq!(hlen(&mut db, &args(&["h"])), Reply::Int(3));
+        assert_eq!(hstrlen(&mut db, &args(&["h", "c"])), Reply::Int(1));
+
+        assert_eq!(hdel(&mut db, &args(&["h", "a", "b", "x"])), Reply::Int(2));
+        assert_eq!(
+            hgetall(&mut db, &args(&["h"])),
+            Reply::Arr(vec![Reply::str("c"), Reply::str("4")])
+        );
+        assert_eq!(hdel(&mut db, &args(&["h", "c"])), Reply::Int(1));
+        assert_eq!(db.size(), 0);
+        assert_eq!(hdel(&mut db, &args(&["h", "c"])), Reply::Int(0));
+    }
+
+    #[test]
+    fn increments() {
+        let mut db = Db::default();
+        assert_eq!(hincrby(&mut db, &args(&["h", "n", "5"])), Reply::Int(5));
+        assert_eq!(hincrby(&mut db, &args(&["h", "n", "-7"])), Reply::Int(-2));
+        assert_eq!(
+            hincrbyfloat(&mut db, &args(&["h", "n", "0.5"])),
+            Reply::str("-1.5")
+        );
+        assert_eq!(
+            hincrby(&mut db, &args(&["h", "n", "1"])),
+            Reply::err("ERR hash value is not an integer")
+        );
+        assert_eq!(
+            hincrbyfloat(&mut db, &args(&["h", "n", "x"])),
+            Reply::err(NOT_A_FLOAT)
+        );
+
+        // failed increments don't leave an empty hash behind
+        db.insert("s", Object::new("v"));
+        assert_eq!(
+            hincrby(&mut db, &args(&["s", "n", "1"])),
+            Reply::err(WRONGTYPE)
+        );
+        assert_eq!(hget(&mut db, &args(&["s", "n"])), Reply::err(WRONGTYPE));
+    }
+
+    #[test]
+    fn scan_large_hash() {
+        let mut db = Db::default();
+        for i in 0..500 {
+            hset(&mut db, &args(&["h", &format!("f{i}"), "v"]));
+        }
+
+        let mut fields = std::collections::HashSet::new();
+        let mut cursor = "0".to_string();
+        loop {
+            let reply = hscan(&mut db, &args(&["h", &cursor, "MATCH", "f1*", "NOVALUES"]));
+            let Reply::Arr(parts) = reply else { panic!() };
+            let [Reply::Str(next), Reply::Arr(page)] = parts.as_slice() else {
+                panic!()
+            };
+            for field in page {
+                let Reply::Str(f) = field else { panic!() };
+                fields.insert(f.clone());
+            }
+            cursor = String::from_utf8(next.clone()).unwrap();
+            if cursor == "0" {
+                break;
+            }
+        }
+        // f1, f10..f19, f100..f199
+        assert_eq!(fields.len(), 111);
+
+        let Reply::Arr(picked) = hrandfield(&mut db, &args(&["h", "600"])) else {
+            panic!()
+        };
+        assert_eq!(picked.len(), 500);
+
+        let Reply::Arr(picked) = hrandfield(&mut db, &args(&["h", "-3", "withvalues"])) else {
+            panic!()
+        };
+        assert_eq!(picked.len(), 6);
+        let min = i64::MIN.to_string();
+        assert_eq!(
+            hrandfield(&mut db, &args(&["h", &min])),
+            Reply::err("ERR value is out of range")
+        );
+    }
+}
@@ -0,0 +1,106 @@
+use collections::HyperLogLog;
+
+use super::{WRONGTYPE, wrong_arity};
+use crate::{
+    protocol::Reply,
+    storage::{Db, Object, Value},
+};
+
+/// Handles `PFADD key [element ...]`, replying with 1 if the estimate
+/// may have changed, or the key was created
+pub fn pfadd(db: &mut Db, args: &[String]) -> Reply {
+    let [key, elements @ ..] = args else {
+        return wrong_arity("pfadd");
+    };
+
+    let Some(mut obj) = db.get_mut(key) else {
+        let mut hll = HyperLogLog::new();
+        for element in elements {
+            hll.add(element.as_bytes());
+        }
+        db.insert(key, Object::new(Value::HyperLogLog(hll)));
+        return Reply::Int(1);
+    };
+    let Some(hll) = obj.value.as_hyperloglog_mut() else {
+        return Reply::err(WRONGTYPE);
+    };
+    let mut changed = false;
+    for element in elements {
+        changed |= hll.add(element.as_bytes());
+    }
+    Reply::Int(changed as i64)
+}
+
+/// Handles `PFCOUNT key [key ...]`, estimating the cardinality of the union
+/// of every given key
+pub fn pfcount(db: &mut Db, args: &[String]) -> Reply {
+    if args.is_empty() {
+        return wrong_arity("pfcount");
+    }
+    match union(db, args) {
+        Ok(hll) => Reply::Int(hll.count() as i64),
+        Err(e) => e,
+    }
+}
+
+/// Handles `PFMERGE destkey [sourcekey ...]`, the destination being
+/// one of the sources if it exists
+pub fn pfmerge(db: &mut Db, args: &[String]) -> Reply {
+    let [dst, sources @ ..] = args else {
+        return wrong_arity("pfmerge");
+    };
+
+    let keys: Vec<String> = std::iter::once(dst).chain(sources).cloned().collect();
+    let merged = match union(db, &keys) {
+        Ok(merged) => merged,
+        Err(e) => return e,
+    };
+
+    if let Some(mut obj) = db.get_mut(dst) {
+        obj.value = Value::HyperLogLog(merged);
+    } else {
+        db.insert(dst, Object::new(Value::HyperLogLog(merged)));
+    }
+    Reply::ok()
+}
+
+/// Merges the estimators at `keys`, missing keys counting as empty
+fn union(db: &mut Db, keys: &[String]) -> Result<HyperLogLog, Reply> {
+    let mut merged = HyperLogLog::new();
+    for key in keys {
+        match db.get(key).map(|obj| obj.value.as_hyperloglog()) {
+            Some(Some(hll)) if keys.len() == 1 => return Ok(hll.clone()),
+            Some(Some(hll)) => merged.merge(hll),
+            Some(None) => return Err(Reply::err(WRONGTYPE)),
+            None => {}
+        }
+    }
+    Ok(merged)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::commands::args;
+
+    #[test]
+    fn add_count_merge() {
+        let mut db = Db::default();
+        assert_eq!(pfadd(&mut db, &args(&["a"])), Reply::Int(1));
+        assert_eq!(pfadd(&mut db, &args(&["a", "x", "y", "z"])), Reply::Int(1));
+        assert_eq!(pfadd(&mut db, &args(&["a", "x"])), Reply::Int(0));
+        assert_eq!(pfadd(&mut db, &args(&["b", "z", "w"])), Reply::Int(1));
+
+        assert_eq!(pfcount(&mut db, &args(&["a"])), Reply::Int(3));
+        assert_eq!(
+            pfcount(&mut db, &args(&["a", "b", "missing"])),
+            Reply::Int(4)
+        );
+        assert_eq!(pfmerge(&mut db, &args(&["b", "a"])), Reply::ok());
+        assert_eq!(pfcount(&mut db, &args(&["b"])), Reply::Int(4));
+
+        db.insert("s", Object::new("v"));
+        assert_eq!(pfadd(&mut db, &args(&["s", "x"])), Reply::err(WRONGTYPE));
+        assert_eq!(pfcount(&mut db, &args(&["a", "s"])), Reply::err(WRONGTYPE));
+    }
+}
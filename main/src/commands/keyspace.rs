@@ -0,0 +1,380 @@
+use super::{NOT_AN_INTEGER, SYNTAX_ERROR, wrong_arity};
+use crate::{
+    blocking,
+    protocol::Reply,
+    session::Session,
+    storage::{Db, Object},
+};
+
+const DB_OUT_OF_RANGE: &str = "ERR DB index is out of range";
+
+/// Handles `SELECT index`, switching the database the session works with
+pub fn select(dbs: &[Db], args: &[String], session: &mut Session) -> Reply {
+    let [index] = args else {
+        return wrong_arity("select");
+    };
+    match parse_db(index, dbs.len()) {
+        Ok(index) => {
+            session.db = index;
+            Reply::ok()
+        }
+        Err(e) => e,
+    }
+}
+
+/// Handles `FLUSHDB [ASYNC|SYNC]`, deleting every key of the selected database.
+/// Flushing is always synchronous
+pub fn flushdb(db: &mut Db, args: &[String]) -> Reply {
+    if let Err(e) = parse_flush_mode(args, "flushdb") {
+        return e;
+    }
+    db.clear();
+    Reply::ok()
+}
+
+/// Handles `FLUSHALL [ASYNC|SYNC]`, deleting every key of every database
+pub fn flushall(dbs: &mut [Db], args: &[String]) -> Reply {
+    if let Err(e) = parse_flush_mode(args, "flushall") {
+        return e;
+    }
+    dbs.iter_mut().for_each(Db::clear);
+    Reply::ok()
+}
+
+/// Handles `SWAPDB index1 index2`, so clients connected to one of them
+/// see the keys of the other right away
+pub fn swapdb(dbs: &mut [Db], args: &[String]) -> Reply {
+    let [a, b] = args else {
+        return wrong_arity("swapdb");
+    };
+    let (a, b) = match (parse_db(a, dbs.len()), parse_db(b, dbs.len())) {
+        (Ok(a), Ok(b)) => (a.min(b), a.max(b)),
+        (Err(_), _) | (_, Err(_)) => return Reply::err("ERR invalid DB index"),
+    };
+    if a != b {
+        let (low, high) = dbs.split_at_mut(b);
+        low[a].swap_keys(&mut high[0]);
+        // clients blocked in either database may be served by the new keys
+        blocking::signal_db(&mut low[a]);
+        blocking::signal_db(&mut high[0]);
+    }
+    Reply::ok()
+}
+
+/// Handles `MOVE key db`, moving `key` from the database at `from` unless
+/// the target database has it already. Replies with 1 if it was moved
+pub fn move_key(dbs: &mut [Db], from: usize, args: &[String]) -> Reply {
+    let [key, to] = args else {
+        return wrong_arity("move");
+    };
+    let to = match parse_db(to, dbs.len()) {
+        Ok(to) => to,
+        Err(e) => return e,
+    };
+    if from == to {
+        return Reply::err("ERR source and destination objects are the same");
+    }
+
+    if dbs[to].peek(key).is_some() {
+        return Reply::Int(0);
+    }
+    let Some(obj) = dbs[from].remove(key) else {
+        return Reply::Int(0);
+    };
+    let is_list = obj.value.as_list().is_some();
+    dbs[to].insert(key, obj);
+    if is_list {
+        blocking::signal(&mut dbs[to], key);
+    }
+    Reply::Int(1)
+}
+
+/// Handles `DEL key`, strings reply with the deleted value
+pub fn del(db: &mut Db, args: &[String]) -> Reply {
+    let [key] = args else {
+        return wrong_arity("del");
+    };
+    match db.remove(key) {
+        Some(o) => o.value.to_bytes().map_or(Reply::Int(1), Reply::Str),
+        None => Reply::Nil,
+    }
+}
+
+/// Handles `EXISTS key [key ...]`, counting keys given more than once every time
+pub fn exists(db: &mut Db, args: &[String]) -> Reply {
+    if args.is_empty() {
+        return wrong_arity("exists");
+    }
+    Reply::Int(args.iter().filter(|key| db.peek(key).is_some()).count() as i64)
+}
+
+/// Handles `TYPE key`, replying with `none` for missing keys
+pub fn type_command(db: &mut Db, args: &[String]) -> Reply {
+    let [key] = args else {
+        return wrong_arity("type");
+    };
+    Reply::str(db.peek(key).map_or("none", |obj| obj.value.type_name()))
+}
+
+/// Handles `RENAME key newkey`, overwriting `newkey`. The expiry moves with the key
+pub fn rename(db: &mut Db, args: &[String]) -> Reply {
+    let [key, new] = args else {
+        return wrong_arity("rename");
+    };
+    match rename_key(db, key, new, true) {
+        Ok(_) => Reply::ok(),
+        Err(e) => e,
+    }
+}
+
+/// Handles `RENAMENX key newkey`, replying with 0 if `newkey` exists already
+pub fn renamenx(db: &mut Db, args: &[String]) -> Reply {
+    let [key, new] = args else {
+        return wrong_arity("renamenx");
+    };
+    match rename_key(db, key, new, false) {
+        Ok(renamed) => Reply::Int(renamed as i64),
+        Err(e) => e,
+    }
+}
+
+/// Handles `DBSIZE`
+pub fn dbsize(db: &mut Db, args: &[String]) -> Reply {
+    if !args.is_empty() {
+        return wrong_arity("dbsize");
+    }
+    Reply::Int(db.size() as i64)
+}
+
+/// Handles `RANDOMKEY`, nil if the database is empty
+pub fn randomkey(db: &mut Db, args: &[String]) -> Reply {
+    if !args.is_empty() {
+        return wrong_arity("randomkey");
+    }
+    db.random_key().map_or(Reply::Nil, Reply::str)
+}
+
+/// Handles `COPY source destination [DB destination-db] [REPLACE]`, copying
+/// the value and expiry of `source` from the database at `from`.
+/// Replies with 0 if `destination` exists and `REPLACE` wasn't given
+pub fn copy(dbs: &mut [Db], from: usize, args: &[String]) -> Reply {
+    let [src, dst, opts @ ..] = args else {
+        return wrong_arity("copy");
+    };
+    let (mut to, mut replace) = (from, false);
+    let mut opts = opts.iter();
+    while let Some(opt) = opts.next() {
+        match opt.to_ascii_lowercase().as_str() {
+            "db" => match opts.next().map(|index| parse_db(index, dbs.len())) {
+                Some(Ok(index)) => to = index,
+                Some(Err(e)) => return e,
+                None => return Reply::err(SYNTAX_ERROR),
+            },
+            "replace" => replace = true,
+            _ => return Reply::err(SYNTAX_ERROR),
+        }
+    }
+    if from == to && src == dst {
+        return Reply::err("ERR source and destination objects are the same");
+    }
+
+    let Some(obj) = dbs[from].peek(src) else {
+        return Reply::Int(0);
+    };
+    let mut copy = Object::new(obj.value.clone());
+    copy.expire_at = obj.expire_at;
+
+    if dbs[to].peek(dst).is_some() && !replace {
+        return Reply::Int(0);
+    }
+    let is_list = copy.value.as_list().is_some();
+    dbs[to].insert(dst, copy);
+    if is_list {
+        blocking::signal(&mut dbs[to], dst);
+    }
+    Reply::Int(1)
+}
+
+/// Handles `UNLINK key [key ...]`, replying with the number of deleted keys.
+/// Values are always freed right away
+pub fn unlink(db: &mut Db, args: &[String]) -> Reply {
+    if args.is_empty() {
+        return wrong_arity("unlink");
+    }
+    Reply::Int(args.iter().filter(|key| db.remove(key).is_some()).count() as i64)
+}
+
+/// Handles `TOUCH key [key ...]`, counting an access to every existing key
+pub fn touch(db: &mut Db, args: &[String]) -> Reply {
+    if args.is_empty() {
+        return wrong_arity("touch");
+    }
+    Reply::Int(args.iter().filter(|key| db.get(key).is_some()).count() as i64)
+}
+
+/// Moves `key` to `new`, overwriting it only if `overwrite` is set.
+/// Returns whether the key was renamed
+fn rename_key(db: &mut Db, key: &str, new: &str, overwrite: bool) -> Result<bool, Reply> {
+    if db.peek(key).is_none() {
+        return Err(Reply::err("ERR no such key"));
+    }
+    if key == new {
+        return Ok(overwrite);
+    }
+    if !overwrite && db.peek(new).is_some() {
+        return Ok(false);
+    }
+    let Some(obj) = db.remove(key) else {
+        return Err(Reply::err("ERR no such key"));
+    };
+    let is_list = obj.value.as_list().is_some();
+    db.insert(new, obj);
+    if is_list {
+        blocking::signal(db, new);
+    }
+    Ok(true)
+}
+
+/// Parses the index of one of `count` databases
+fn parse_db(arg: &str, count: usize) -> Result<usize, Reply> {
+    match arg.parse::<i64>() {
+        Ok(index) if (0..count as i64).contains(&index) => Ok(index as usize),
+        Ok(_) => Err(Reply::err(DB_OUT_OF_RANGE)),
+        Err(_) => Err(Reply::err(NOT_AN_INTEGER)),
+    }
+}
+
+fn parse_flush_mode(args: &[String], name: &str) -> Result<(), Reply> {
+    match args {
+        [] => Ok(()),
+        [mode] if mode.eq_ignore_ascii_case("async") || mode.eq_ignore_ascii_case("sync") => Ok(()),
+        [_] => Err(Reply::err(SYNTAX_ERROR)),
+        _ => Err(wrong_arity(name)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{commands::args, net::PeerAddr, storage::Value};
+
+    fn dbs(count: usize) -> Vec<Db> {
+        (0..count).map(Db::new).collect()
+    }
+
+    #[test]
+    fn select_and_move() {
+        let mut dbs = dbs(4);
+        let mut session = Session::new(1, PeerAddr::Tcp(([127, 0, 0, 1], 0).into()));
+        assert_eq!(select(&dbs, &args(&["3"]), &mut session), Reply::ok());
+        assert_eq!(session.db, 3);
+        assert_eq!(
+            select(&dbs, &args(&["4"]), &mut session),
+            Reply::err(DB_OUT_OF_RANGE)
+        );
+        assert_eq!(session.db, 3);
+
+        dbs[0].insert("k", Object::new("v"));
+        assert_eq!(move_key(&mut dbs, 0, &args(&["k", "2"])), Reply::Int(1));
+        assert!(dbs[0].get("k").is_none());
+        assert_eq!(
+            dbs[2].get("k").map(|obj| obj.value.clone()),
+            Some("v".into())
+        );
+        assert_eq!(move_key(&mut dbs, 0, &args(&["k", "2"])), Reply::Int(0));
+
+        dbs[0].insert("k", Object::new("other"));
+        assert_eq!(move_key(&mut dbs, 0, &args(&["k", "2"])), Reply::Int(0));
+        assert!(dbs[0].get("k").is_some());
+        assert_eq!(
+            move_key(&mut dbs, 2, &args(&["k", "2"])),
+            Reply::err("ERR source and destination objects are the same")
+        );
+    }
+
+    #[test]
+    fn swap_and_flush() {
+        let mut dbs = dbs(3);
+        dbs[0].insert("a", Object::new("1"));
+        dbs[2].insert("b", Object::new("2"));
+        dbs[2].insert("c", Object::new("3"));
+        let memory = dbs[2].used_memory();
+
+        assert_eq!(swapdb(&mut dbs, &args(&["2", "0"])), Reply::ok());
+        assert_eq!((dbs[0].size(), dbs[2].size()), (2, 1));
+        assert_eq!((dbs[0].index(), dbs[2].index()), (0, 2));
+        assert_eq!(dbs[0].used_memory(), memory);
+        assert_eq!(
+            swapdb(&mut dbs, &args(&["0", "3"])),
+            Reply::err("ERR invalid DB index")
+        );
+
+        assert_eq!(flushdb(&mut dbs[0], &args(&["async"])), Reply::ok());
+        assert_eq!((dbs[0].size(), dbs[0].used_memory()), (0, 0));
+        assert_eq!(dbs[2].size(), 1);
+        assert_eq!(flushall(&mut dbs, &[]), Reply::ok());
+        assert!(dbs.iter().all(|db| db.size() == 0));
+    }
+
+    #[test]
+    fn key_commands() {
+        let mut db = Db::new(0);
+        db.insert("a", Object::new("1"));
+        db.insert("l", Object::new(Value::List(Default::default())));
+        let mut expiring = Object::new("2");
+        expiring.expire_at = Some(u64::MAX);
+        db.insert("b", expiring);
+
+        assert_eq!(exists(&mut db, &args(&["a", "a", "x"])), Reply::Int(2));
+        assert_eq!(type_command(&mut db, &args(&["a"])), Reply::str("string"));
+        assert_eq!(type_command(&mut db, &args(&["l"])), Reply::str("list"));
+        assert_eq!(type_command(&mut db, &args(&["x"])), Reply::str("none"));
+        assert_eq!(dbsize(&mut db, &[]), Reply::Int(3));
+
+        assert_eq!(rename(&mut db, &args(&["b", "c"])), Reply::ok());
+        assert_eq!(db.peek("c").and_then(|obj| obj.expire_at), Some(u64::MAX));
+        assert_eq!(
+            rename(&mut db, &args(&["b", "c"])),
+            Reply::err("ERR no such key")
+        );
+        assert_eq!(renamenx(&mut db, &args(&["c", "a"])), Reply::Int(0));
+        assert_eq!(renamenx(&mut db, &args(&["c", "b"])), Reply::Int(1));
+        assert_eq!(rename(&mut db, &args(&["b", "b"])), Reply::ok());
+
+        assert_eq!(touch(&mut db, &args(&["a", "x", "l"])), Reply::Int(2));
+        assert_eq!(unlink(&mut db, &args(&["a", "x", "l"])), Reply::Int(2));
+        assert_eq!(randomkey(&mut db, &[]), Reply::str("b"));
+        assert_eq!(unlink(&mut db, &args(&["b"])), Reply::Int(1));
+        assert_eq!(randomkey(&mut db, &[]), Reply::Nil);
+    }
+
+    #[test]
+    fn copy_between_databases() {
+        let mut dbs = dbs(2);
+        dbs[0].insert("a", Object::new("1"));
+        dbs[1].insert("a", Object::new("2"));
+
+        assert_eq!(copy(&mut dbs, 0, &args(&["a", "b"])), Reply::Int(1));
+        assert_eq!(
+            copy(&mut dbs, 0, &args(&["a", "a", "DB", "1"])),
+            Reply::Int(0)
+        );
+        assert_eq!(
+            copy(&mut dbs, 0, &args(&["a", "a", "DB", "1", "REPLACE"])),
+            Reply::Int(1)
+        );
+        assert_eq!(
+            dbs[1].get("a").map(|obj| obj.value.clone()),
+            Some("1".into())
+        );
+        assert_eq!(copy(&mut dbs, 0, &args(&["x", "y"])), Reply::Int(0));
+        assert_eq!(
+            copy(&mut dbs, 0, &args(&["a", "a"])),
+            Reply::err("ERR source and destination objects are the same")
+        );
+        assert_eq!(
+            copy(&mut dbs, 0, &args(&["a", "b", "DB", "2"])),
+            Reply::err(DB_OUT_OF_RANGE)
+        );
+    }
+}
@@ -0,0 +1,322 @@
+use collections::QuickList;
+
+use super::{SYNTAX_ERROR, WRONGTYPE, parse_int, range, wrong_arity};
+use crate::{
+    blocking,
+    protocol::Reply,
+    storage::{Db, Object, Value},
+};
+
+/// Which end of a list an element is pushed to or popped from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum End {
+    Left,
+    Right,
+}
+
+impl End {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "left" => Some(End::Left),
+            "right" => Some(End::Right),
+            _ => None,
+        }
+    }
+}
+
+/// Handles `LPUSH key element [element ...]`, replying with the new length
+pub fn lpush(db: &mut Db, args: &[String]) -> Reply {
+    match args {
+        [key, elements @ ..] if !elements.is_empty() => push(db, key, elements, End::Left),
+        _ => wrong_arity("lpush"),
+    }
+}
+
+/// Handles `RPUSH key element [element ...]`, replying with the new length
+pub fn rpush(db: &mut Db, args: &[String]) -> Reply {
+    match args {
+        [key, elements @ ..] if !elements.is_empty() => push(db, key, elements, End::Right),
+        _ => wrong_arity("rpush"),
+    }
+}
+
+/// Handles `LPOP key [count]`
+pub fn lpop(db: &mut Db, args: &[String]) -> Reply {
+    pop_command(db, args, End::Left, "lpop")
+}
+
+/// Handles `RPOP key [count]`
+pub fn rpop(db: &mut Db, args: &[String]) -> Reply {
+    pop_command(db, args, End::Right, "rpop")
+}
+
+/// Handles `LLEN key`
+pub fn llen(db: &mut Db, args: &[String]) -> Reply {
+    let [key] = args else {
+        return wrong_arity("llen");
+    };
+    match db.get(key).map(|obj| obj.value.as_list()) {
+        Some(Some(list)) => Reply::Int(list.len() as i64),
+        Some(None) => Reply::err(WRONGTYPE),
+        None => Reply::Int(0),
+    }
+}
+
+/// Handles `LRANGE key start stop`, both ends inclusive and negative ones counting from the end
+pub fn lrange(db: &mut Db, args: &[String]) -> Reply {
+    let [key, start, stop] = args else {
+        return wrong_arity("lrange");
+    };
+    let (start, stop) = match (parse_int(start), parse_int(stop)) {
+        (Ok(start), Ok(stop)) => (start, stop),
+        (Err(e), _) | (_, Err(e)) => return e,
+    };
+
+    let list = match db.get(key).map(|obj| obj.value.as_list()) {
+        Some(Some(list)) => list,
+        Some(None) => return Reply::err(WRONGTYPE),
+        None => return Reply::Arr(Vec::new()),
+    };
+    let Some((start, stop)) = range(start, stop, list.len()) else {
+        return Reply::Arr(Vec::new());
+    };
+
+    Reply::Arr(
+        list.iter_from(start)
+            .take(stop - start + 1)
+            .map(Reply::str)
+            .collect(),
+    )
+}
+
+/// Handles `LINDEX key index`, negative indexes counting from the end
+pub fn lindex(db: &mut Db, args: &[String]) -> Reply {
+    let [key, index] = args else {
+        return wrong_arity("lindex");
+    };
+    let index = match parse_int(index) {
+        Ok(index) => index,
+        Err(e) => return e,
+    };
+
+    let list = match db.get(key).map(|obj| obj.value.as_list()) {
+        Some(Some(list)) => list,
+        Some(None) => return Reply::err(WRONGTYPE),
+        None => return Reply::Nil,
+    };
+    let len = list.len() as i64;
+    let index = if index < 0 { len + index } else { index };
+    if !(0..len).contains(&index) {
+        return Reply::Nil;
+    }
+    list.get(index as usize).map_or(Reply::Nil, Reply::str)
+}
+
+/// Handles `LTRIM key start stop`, keeping only the given range
+pub fn ltrim(db: &mut Db, args: &[String]) -> Reply {
+    let [key, start, stop] = args else {
+        return wrong_arity("ltrim");
+    };
+    let (start, stop) = match (parse_int(start), parse_int(stop)) {
+        (Ok(start), Ok(stop)) => (start, stop),
+        (Err(e), _) | (_, Err(e)) => return e,
+    };
+
+    let Some(mut obj) = db.get_mut(key) else {
+        return Reply::ok();
+    };
+    let Some(list) = obj.value.as_list_mut() else {
+        return Reply::err(WRONGTYPE);
+    };
+    match range(start, stop, list.len()) {
+        Some((start, stop)) => list.retain_range(start, stop + 1),
+        None => list.clear(),
+    }
+
+    if list.is_empty() {
+        drop(obj);
+        db.remove(key);
+    }
+    Reply::ok()
+}
+
+/// Handles `LMOVE source destination LEFT|RIGHT LEFT|RIGHT`,
+/// replying with the moved element
+pub fn lmove(db: &mut Db, args: &[String]) -> Reply {
+    let [src, dst, from, to] = args else {
+        return wrong_arity("lmove");
+    };
+    let (Some(from), Some(to)) = (End::parse(from), End::parse(to)) else {
+        return Reply::err(SYNTAX_ERROR);
+    };
+    move_element(db, src, dst, from, to)
+}
+
+/// Moves one element from `src` to `dst`, replying with it, or nil if `src` doesn't exist
+pub fn move_element(db: &mut Db, src: &str, dst: &str, from: End, to: End) -> Reply {
+    if db.get(dst).is_some_and(|obj| obj.value.as_list().is_none()) {
+        return Reply::err(WRONGTYPE);
+    }
+
+    match pop(db, src, from, 1) {
+        Ok(Some(mut popped)) => {
+            let element = popped.remove(0);
+            push_bytes(db, dst, [element.as_slice()], to);
+            Reply::Str(element)
+        }
+        Ok(None) => Reply::Nil,
+        Err(e) => e,
+    }
+}
+
+fn push(db: &mut Db, key: &str, elements: &[String], end: End) -> Reply {
+    push_bytes(db, key, elements.iter().map(String::as_bytes), end)
+}
+
+/// Pushes `elements` one by one, creating the list if needed.
+/// Replies with the new length
+pub fn push_bytes<'a>(
+    db: &mut Db,
+    key: &str,
+    elements: impl IntoIterator<Item = &'a [u8]>,
+    end: End,
+) -> Reply {
+    let push = |list: &mut QuickList| {
+        for element in elements {
+            match end {
+                End::Left => list.push_front(element),
+                End::Right => list.push_back(element),
+            }
+        }
+        Reply::Int(list.len() as i64)
+    };
+
+    let reply = if let Some(mut obj) = db.get_mut(key) {
+        match obj.value.as_list_mut() {
+            Some(list) => push(list),
+            None => return Reply::err(WRONGTYPE),
+        }
+    } else {
+        let mut list = QuickList::new();
+        let reply = push(&mut list);
+        db.insert(key, Object::new(Value::List(list)));
+        reply
+    };
+    blocking::signal(db, key);
+    reply
+}
+
+/// Pops up to `count` elements, deleting the list once it is empty.
+/// `None` if the key doesn't exist
+pub fn pop(db: &mut Db, key: &str, end: End, count: usize) -> Result<Option<Vec<Vec<u8>>>, Reply> {
+    let Some(mut obj) = db.get_mut(key) else {
+        return Ok(None);
+    };
+    let Some(list) = obj.value.as_list_mut() else {
+        return Err(Reply::err(WRONGTYPE));
+    };
+
+    let popped = (0..count)
+        .map_while(|_| match end {
+            End::Left => list.pop_front(),
+            End::Right => list.pop_back(),
+        })
+        .collect();
+
+    if list.is_empty() {
+        drop(obj);
+        db.remove(key);
+    }
+    Ok(Some(popped))
+}
+
+/// Without a count it replies with one element, with one it replies with an array
+fn pop_command(db: &mut Db, args: &[String], end: End, name: &str) -> Reply {
+    let (key, count) = match args {
+        [key] => (key, None),
+        [key, count] => match count.parse::<usize>() {
+            Ok(count) => (key, Some(count)),
+            Err(_) => return Reply::err("ERR value is out of range, must be positive"),
+        },
+        _ => return wrong_arity(name),
+    };
+
+    match pop(db, key, end, count.unwrap_or(1)) {
+        Ok(Some(popped)) if count.is_some() => {
+            Reply::Arr(popped.into_iter().map(Reply::Str).collect())
+        }
+        Ok(Some(mut popped)) => popped.pop().map_or(Reply::Nil, Reply::Str),
+        Ok(None) => Reply::Nil,
+        Err(e) => e,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::commands::args;
+
+    fn strs(items: &[&str]) -> Reply {
+        Reply::Arr(items.iter().map(Reply::str).collect())
+    }
+
+    #[test]
+    fn push_pop_range() {
+        let mut db = Db::default();
+        assert_eq!(rpush(&mut db, &args(&["l", "b", "c"])), Reply::Int(2));
+        assert_eq!(lpush(&mut db, &args(&["l", "a", "z"])), Reply::Int(4));
+        assert_eq!(
+            lrange(&mut db, &args(&["l", "0", "-1"])),
+            strs(&["z", "a", "b", "c"])
+        );
+        assert_eq!(lrange(&mut db, &args(&["l", "-3", "1"])), strs(&["a"]));
+        assert_eq!(lrange(&mut db, &args(&["l", "5", "10"])), strs(&[]));
+        assert_eq!(lindex(&mut db, &args(&["l", "-1"])), Reply::str("c"));
+        assert_eq!(lindex(&mut db, &args(&["l", "4"])), Reply::Nil);
+
+        assert_eq!(lpop(&mut db, &args(&["l"])), Reply::str("z"));
+        assert_eq!(rpop(&mut db, &args(&["l", "5"])), strs(&["c", "b", "a"]));
+        assert_eq!(db.size(), 0);
+        assert_eq!(lpop(&mut db, &args(&["l"])), Reply::Nil);
+    }
+
+    #[test]
+    fn trim_and_move() {
+        let mut db = Db::default();
+        rpush(&mut db, &args(&["l", "a", "b", "c", "d"]));
+        assert_eq!(ltrim(&mut db, &args(&["l", "1", "-2"])), Reply::ok());
+        assert_eq!(lrange(&mut db, &args(&["l", "0", "-1"])), strs(&["b", "c"]));
+
+        assert_eq!(
+            lmove(&mut db, &args(&["l", "l", "left", "right"])),
+            Reply::str("b")
+        );
+        assert_eq!(
+            lmove(&mut db, &args(&["l", "m", "RIGHT", "LEFT"])),
+            Reply::str("b")
+        );
+        assert_eq!(lrange(&mut db, &args(&["l", "0", "-1"])), strs(&["c"]));
+        assert_eq!(lrange(&mut db, &args(&["m", "0", "-1"])), strs(&["b"]));
+
+        assert_eq!(ltrim(&mut db, &args(&["l", "1", "0"])), Reply::ok());
+        assert_eq!(db.size(), 1);
+    }
+
+    #[test]
+    fn wrong_type() {
+        let mut db = Db::default();
+        db.insert("s", Object::new("v"));
+        rpush(&mut db, &args(&["l", "a"]));
+
+        assert_eq!(lpush(&mut db, &args(&["s", "a"])), Reply::err(WRONGTYPE));
+        assert_eq!(
+            lmove(&mut db, &args(&["l", "s", "left", "left"])),
+            Reply::err(WRONGTYPE)
+        );
+        assert_eq!(llen(&mut db, &args(&["l"])), Reply::Int(1));
+        assert_eq!(
+            crate::commands::string::get(&mut db, &args(&["l"])),
+            Reply::err(WRONGTYPE)
+        );
+    }
+}
@@ -0,0 +1,124 @@
+//! Implementations of the data type commands, operating on a `Db`
+
+pub mod bitmap;
+pub mod geo;
+pub mod hash;
+pub mod hyperloglog;
+pub mod keyspace;
+pub mod list;
+pub mod set;
+pub mod stream;
+pub mod string;
+
+use crate::protocol::{MAX_ARGS, Reply};
+
+pub const NOT_AN_INTEGER: &str = "ERR value is not an integer or out of range";
+pub const NOT_A_FLOAT: &str = "ERR value is not a valid float";
+pub const SYNTAX_ERROR: &str = "ERR syntax error";
+pub const WRONGTYPE: &str = "WRONGTYPE Operation against a key holding the wrong kind of value";
+
+pub fn wrong_arity(name: &str) -> Reply {
+    Reply::err(format!(
+        "ERR wrong number of arguments for '{name}' command"
+    ))
+}
+
+pub fn parse_int(arg: &str) -> Result<i64, Reply> {
+    arg.parse().map_err(|_| Reply::err(NOT_AN_INTEGER))
+}
+
+/// Parses a float, rejecting NaN
+pub fn parse_float(arg: &str) -> Result<f64, Reply> {
+    match arg.parse::<f64>() {
+        Ok(n) if !n.is_nan() => Ok(n),
+        _ => Err(Reply::err(NOT_A_FLOAT)),
+    }
+}
+
+/// Number of picks a negative `*RAND*` count asks for. Every pick may
+/// repeat and costs a random lookup, so they are capped like the length
+/// of the arrays clients accept
+pub fn repeated_picks(count: i64) -> Result<usize, Reply> {
+    match count.unsigned_abs() {
+        n if n <= MAX_ARGS as u64 => Ok(n as usize),
+        _ => Err(Reply::err("ERR value is out of range")),
+    }
+}
+
+/// Resolves an inclusive `start..=end` range over `len` items,
+/// negative indexes counting from the end. `None` if the range is empty
+pub fn range(start: i64, end: i64, len: usize) -> Option<(usize, usize)> {
+    let len = len as i64;
+    let start = if start < 0 { len + start } else { start }.max(0);
+    let end = if end < 0 { len + end } else { end }.min(len - 1);
+    (start <= end).then_some((start as usize, end as usize))
+}
+
+/// Arguments shared by the `*SCAN` commands: `cursor [MATCH pattern] [COUNT count]`,
+/// and `NOVALUES` where it is allowed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScanOptions {
+    pub cursor: usize,
+    pub pattern: Option<String>,
+    /// How many elements to visit, only a hint since buckets are never split
+    pub count: usize,
+    pub novalues: bool,
+}
+
+impl ScanOptions {
+    pub fn parse(cursor: &str, opts: &[String], allow_novalues: bool) -> Result<Self, Reply> {
+        let Ok(cursor) = cursor.parse() else {
+            return Err(Reply::err("ERR invalid cursor"));
+        };
+        let mut scan = ScanOptions {
+            cursor,
+            pattern: None,
+            count: 10,
+            novalues: false,
+        };
+
+        let mut opts = opts.iter();
+        while let Some(opt) = opts.next() {
+            match opt.to_ascii_lowercase().as_str() {
+                "match" => match opts.next() {
+                    Some(p) => scan.pattern = Some(p.clone()),
+                    None => return Err(Reply::err(SYNTAX_ERROR)),
+                },
+                "count" => match opts.next().map(|c| c.parse::<usize>()) {
+                    Some(Ok(c)) if c > 0 => scan.count = c,
+                    Some(_) => return Err(Reply::err(NOT_AN_INTEGER)),
+                    None => return Err(Reply::err(SYNTAX_ERROR)),
+                },
+                "novalues" if allow_novalues => scan.novalues = true,
+                _ => return Err(Reply::err(SYNTAX_ERROR)),
+            }
+        }
+        Ok(scan)
+    }
+
+    pub fn matches(&self, s: &str) -> bool {
+        self.pattern
+            .as_ref()
+            .is_none_or(|p| crate::util::glob_match(p, s))
+    }
+
+    /// Calls `step` with the cursor until `count` elements were visited or the scan
+    /// is complete, replying with the next cursor and the collected elements.
+    /// `step` returns the next cursor, adding to the visited count
+    pub fn run(&self, mut step: impl FnMut(usize, &mut usize, &mut Vec<Reply>) -> usize) -> Reply {
+        let (mut cursor, mut visited, mut out) = (self.cursor, 0, Vec::new());
+        loop {
+            cursor = step(cursor, &mut visited, &mut out);
+            if cursor == 0 || visited >= self.count {
+                break;
+            }
+        }
+        Reply::Arr(vec![Reply::str(cursor.to_string()), Reply::Arr(out)])
+    }
+}
+
+/// Builds the argument list of a command, for tests
+#[cfg(test)]
+pub(crate) fn args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|s| s.to_string()).collect()
+}
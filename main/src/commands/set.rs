@@ -0,0 +1,495 @@
+use std::{borrow::Cow, collections::HashSet};
+
+use collections::Set;
+use rand::seq::index;
+
+use super::{
+    NOT_AN_INTEGER, SYNTAX_ERROR, ScanOptions, WRONGTYPE, parse_int, repeated_picks, wrong_arity,
+};
+use crate::{
+    protocol::Reply,
+    storage::{Db, Object, Value},
+};
+
+/// How the sets of a set algebra command are combined
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Inter,
+    Union,
+    Diff,
+}
+
+/// Handles `SADD key member [member ...]`, replying with the number of new members
+pub fn sadd(db: &mut Db, args: &[String]) -> Reply {
+    let [key, members @ ..] = args else {
+        return wrong_arity("sadd");
+    };
+    if members.is_empty() {
+        return wrong_arity("sadd");
+    }
+    update(db, key, |set| {
+        Reply::Int(members.iter().filter(|m| set.insert(m)).count() as i64)
+    })
+}
+
+/// Handles `SREM key member [member ...]`, deleting the key once the set is empty
+pub fn srem(db: &mut Db, args: &[String]) -> Reply {
+    let [key, members @ ..] = args else {
+        return wrong_arity("srem");
+    };
+    if members.is_empty() {
+        return wrong_arity("srem");
+    }
+    match read(db, key) {
+        Ok(Some(_)) => update(db, key, |set| {
+            Reply::Int(members.iter().filter(|m| set.remove(m)).count() as i64)
+        }),
+        Ok(None) => Reply::Int(0),
+        Err(e) => e,
+    }
+}
+
+/// Handles `SCARD key`
+pub fn scard(db: &mut Db, args: &[String]) -> Reply {
+    let [key] = args else {
+        return wrong_arity("scard");
+    };
+    match read(db, key) {
+        Ok(set) => Reply::Int(set.map_or(0, Set::len) as i64),
+        Err(e) => e,
+    }
+}
+
+/// Handles `SISMEMBER key member`
+pub fn sismember(db: &mut Db, args: &[String]) -> Reply {
+    let [key, member] = args else {
+        return wrong_arity("sismember");
+    };
+    match read(db, key) {
+        Ok(set) => Reply::Int(set.is_some_and(|s| s.contains(member)) as i64),
+        Err(e) => e,
+    }
+}
+
+/// Handles `SMISMEMBER key member [member ...]`
+pub fn smismember(db: &mut Db, args: &[String]) -> Reply {
+    let [key, members @ ..] = args else {
+        return wrong_arity("smismember");
+    };
+    if members.is_empty() {
+        return wrong_arity("smismember");
+    }
+    match read(db, key) {
+        Ok(set) => Reply::Arr(
+            members
+                .iter()
+                .map(|m| Reply::Int(set.is_some_and(|s| s.contains(m)) as i64))
+                .collect(),
+        ),
+        Err(e) => e,
+    }
+}
+
+/// Handles `SMEMBERS key`
+pub fn smembers(db: &mut Db, args: &[String]) -> Reply {
+    let [key] = args else {
+        return wrong_arity("smembers");
+    };
+    match read(db, key) {
+        Ok(set) => Reply::Arr(
+            set.iter()
+                .flat_map(|s| s.iter())
+                .map(|m| Reply::str(m.as_bytes()))
+                .collect(),
+        ),
+        Err(e) => e,
+    }
+}
+
+/// Handles `SINTER key [key ...]`
+pub fn sinter(db: &mut Db, args: &[String]) -> Reply {
+    algebra(db, args, Op::Inter, "sinter")
+}
+
+/// Handles `SUNION key [key ...]`
+pub fn sunion(db: &mut Db, args: &[String]) -> Reply {
+    algebra(db, args, Op::Union, "sunion")
+}
+
+/// Handles `SDIFF key [key ...]`, the members of the first set missing from the others
+pub fn sdiff(db: &mut Db, args: &[String]) -> Reply {
+    algebra(db, args, Op::Diff, "sdiff")
+}
+
+/// Handles `SINTERSTORE destination key [key ...]`
+pub fn sinterstore(db: &mut Db, args: &[String]) -> Reply {
+    store(db, args, Op::Inter, "sinterstore")
+}
+
+/// Handles `SUNIONSTORE destination key [key ...]`
+pub fn sunionstore(db: &mut Db, args: &[String]) -> Reply {
+    store(db, args, Op::Union, "sunionstore")
+}
+
+/// Handles `SDIFFSTORE destination key [key ...]`
+pub fn sdiffstore(db: &mut Db, args: &[String]) -> Reply {
+    store(db, args, Op::Diff, "sdiffstore")
+}
+
+/// Handles `SINTERCARD numkeys key [key ...] [LIMIT limit]`, 0 meaning no limit
+pub fn sintercard(db: &mut Db, args: &[String]) -> Reply {
+    let [numkeys, rest @ ..] = args else {
+        return wrong_arity("sintercard");
+    };
+    let numkeys = match numkeys.parse::<usize>() {
+        Ok(0) => return Reply::err("ERR numkeys should be greater than 0"),
+        Ok(n) => n,
+        Err(_) => return Reply::err(NOT_AN_INTEGER),
+    };
+    if rest.len() < numkeys {
+        return Reply::err("ERR Number of keys can't be greater than number of args");
+    }
+
+    let (keys, opts) = rest.split_at(numkeys);
+    let limit = match opts {
+        [] => 0,
+        [opt, limit] if opt.eq_ignore_ascii_case("limit") => match limit.parse::<usize>() {
+            Ok(limit) => limit,
+            Err(_) => return Reply::err("ERR LIMIT can't be negative"),
+        },
+        _ => return Reply::err(SYNTAX_ERROR),
+    };
+
+    match combine(db, keys, Op::Inter) {
+        Ok(set) if limit > 0 => Reply::Int(set.len().min(limit) as i64),
+        Ok(set) => Reply::Int(set.len() as i64),
+        Err(e) => e,
+    }
+}
+
+/// Handles `SMOVE source destination member`
+pub fn smove(db: &mut Db, args: &[String]) -> Reply {
+    let [src, dst, member] = args else {
+        return wrong_arity("smove");
+    };
+    if let Err(e) = read(db, dst) {
+        return e;
+    }
+    let has_member = match read(db, src) {
+        Ok(set) => set.is_some_and(|s| s.contains(member)),
+        Err(e) => return e,
+    };
+    if !has_member {
+        return Reply::Int(0);
+    }
+    if src != dst {
+        update(db, src, |set| Reply::Int(set.remove(member) as i64));
+        update(db, dst, |set| Reply::Int(set.insert(member) as i64));
+    }
+    Reply::Int(1)
+}
+
+/// Handles `SRANDMEMBER key [count]`. A positive count picks distinct
+/// members, a negative one may pick the same member more than once
+pub fn srandmember(db: &mut Db, args: &[String]) -> Reply {
+    let (key, count) = match args {
+        [key] => (key, None),
+        [key, count] => match parse_int(count) {
+            Ok(count) => (key, Some(count)),
+            Err(e) => return e,
+        },
+        _ => return wrong_arity("srandmember"),
+    };
+
+    let set = match read(db, key) {
+        Ok(Some(set)) => set,
+        Ok(None) if count.is_some() => return Reply::Arr(Vec::new()),
+        Ok(None) => return Reply::Nil,
+        Err(e) => return e,
+    };
+    let mut rng = rand::rng();
+
+    let Some(count) = count else {
+        return set
+            .random(&mut rng)
+            .map_or(Reply::Nil, |m| Reply::str(m.as_bytes()));
+    };
+    let members: Vec<Cow<str>> = if count < 0 {
+        match repeated_picks(count) {
+            Ok(n) => (0..n).filter_map(|_| set.random(&mut rng)).collect(),
+            Err(e) => return e,
+        }
+    } else if count as usize >= set.len() {
+        set.iter().collect()
+    } else if count as usize * 3 > set.len() {
+        // most of the set is picked anyway, so pick among all of it at once
+        let all: Vec<Cow<str>> = set.iter().collect();
+        let picked = index::sample(&mut rng, all.len(), count as usize);
+        let mut all: Vec<Option<Cow<str>>> = all.into_iter().map(Some).collect();
+        picked.into_iter().filter_map(|i| all[i].take()).collect()
+    } else {
+        let mut picked = HashSet::new();
+        while picked.len() < count as usize {
+            picked.extend(set.random(&mut rng));
+        }
+        picked.into_iter().collect()
+    };
+    Reply::Arr(
+        members
+            .into_iter()
+            .map(|m| Reply::str(m.as_bytes()))
+            .collect(),
+    )
+}
+
+/// Handles `SPOP key [count]`, removing random members
+pub fn spop(db: &mut Db, args: &[String]) -> Reply {
+    let (key, count) = match args {
+        [key] => (key, None),
+        [key, count] => match count.parse::<usize>() {
+            Ok(count) => (key, Some(count)),
+            Err(_) => return Reply::err("ERR value is out of range, must be positive"),
+        },
+        _ => return wrong_arity("spop"),
+    };
+
+    match read(db, key) {
+        Ok(Some(_)) => {}
+        Ok(None) if count.is_some() => return Reply::Arr(Vec::new()),
+        Ok(None) => return Reply::Nil,
+        Err(e) => return e,
+    }
+
+    let mut rng = rand::rng();
+    update(db, key, |set| match count {
+        None => set
+            .pop_random(&mut rng)
+            .map_or(Reply::Nil, |m| Reply::str(m.as_bytes())),
+        Some(count) => Reply::Arr(
+            (0..count)
+                .map_while(|_| set.pop_random(&mut rng))
+                .map(|m| Reply::str(m.as_bytes()))
+                .collect(),
+        ),
+    })
+}
+
+/// Handles `SSCAN key cursor [MATCH pattern] [COUNT count]`
+pub fn sscan(db: &mut Db, args: &[String]) -> Reply {
+    let [key, cursor, opts @ ..] = args else {
+        return wrong_arity("sscan");
+    };
+    let scan = match ScanOptions::parse(cursor, opts, false) {
+        Ok(scan) => scan,
+        Err(e) => return e,
+    };
+
+    let set = match read(db, key) {
+        Ok(Some(set)) => set,
+        Ok(None) => return Reply::Arr(vec![Reply::str("0"), Reply::Arr(Vec::new())]),
+        Err(e) => return e,
+    };
+    scan.run(|cursor, visited, out| {
+        set.scan(cursor, |member| {
+            *visited += 1;
+            if scan.matches(member) {
+                out.push(Reply::str(member));
+            }
+        })
+    })
+}
+
+fn algebra(db: &mut Db, keys: &[String], op: Op, name: &str) -> Reply {
+    if keys.is_empty() {
+        return wrong_arity(name);
+    }
+    match combine(db, keys, op) {
+        Ok(set) => Reply::Arr(set.iter().map(|m| Reply::str(m.as_bytes())).collect()),
+        Err(e) => e,
+    }
+}
+
+/// Stores the result at the destination, replacing whatever was there,
+/// and replies with its size
+fn store(db: &mut Db, args: &[String], op: Op, name: &str) -> Reply {
+    let [dst, keys @ ..] = args else {
+        return wrong_arity(name);
+    };
+    if keys.is_empty() {
+        return wrong_arity(name);
+    }
+    let set = match combine(db, keys, op) {
+        Ok(set) => set,
+        Err(e) => return e,
+    };
+
+    let len = set.len();
+    if set.is_empty() {
+        db.remove(dst);
+    } else {
+        db.insert(dst, Object::new(Value::Set(set)));
+    }
+    Reply::Int(len as i64)
+}
+
+/// Combines the sets at `keys`, missing keys counting as empty sets
+fn combine(db: &mut Db, keys: &[String], op: Op) -> Result<Set, Reply> {
+    // check every key first, so a wrong type is an error even after an empty set
+    let mut lens = Vec::with_capacity(keys.len());
+    for key in keys {
+        lens.push(read(db, key)?.map_or(0, Set::len));
+    }
+
+    let first = match op {
+        // walk the smallest set, checking the others
+        Op::Inter if lens.contains(&0) => return Ok(Set::new()),
+        Op::Inter => (0..keys.len()).min_by_key(|&i| lens[i]).unwrap_or(0),
+        Op::Union | Op::Diff => 0,
+    };
+    let mut members: Vec<String> = match read(db, &keys[first])? {
+        Some(set) => set.iter().map(Cow::into_owned).collect(),
+        None => Vec::new(),
+    };
+
+    for (i, key) in keys.iter().enumerate() {
+        let Some(set) = read(db, key)?.filter(|_| i != first) else {
+            continue;
+        };
+        match op {
+            Op::Inter => members.retain(|m| set.contains(m)),
+            Op::Diff => members.retain(|m| !set.contains(m)),
+            Op::Union => members.extend(set.iter().map(Cow::into_owned)),
+        }
+    }
+    Ok(members.iter().map(String::as_str).collect())
+}
+
+/// The set stored at `key`, `None` if the key doesn't exist
+fn read<'a>(db: &'a mut Db, key: &str) -> Result<Option<&'a Set>, Reply> {
+    match db.get(key).map(|obj| obj.value.as_set()) {
+        Some(Some(set)) => Ok(Some(set)),
+        Some(None) => Err(Reply::err(WRONGTYPE)),
+        None => Ok(None),
+    }
+}
+
+/// Runs `f` on the set at `key`, creating it if needed,
+/// and deleting the key if the set ends up empty
+fn update(db: &mut Db, key: &str, f: impl FnOnce(&mut Set) -> Reply) -> Reply {
+    let Some(mut obj) = db.get_mut(key) else {
+        let mut set = Set::new();
+        let reply = f(&mut set);
+        if !set.is_empty() {
+            db.insert(key, Object::new(Value::Set(set)));
+        }
+        return reply;
+    };
+    let Some(set) = obj.value.as_set_mut() else {
+        return Reply::err(WRONGTYPE);
+    };
+    let reply = f(set);
+    if set.is_empty() {
+        drop(obj);
+        db.remove(key);
+    }
+    reply
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::commands::args;
+
+    /// Members of an array reply, sorted since sets have no order
+    fn sorted(reply: Reply) -> Vec<String> {
+        let Reply::Arr(items) = reply else {
+            panic!("not an array: {reply:?}")
+        };
+        let mut out: Vec<String> = items
+            .into_iter()
+            .map(|r| match r {
+                Reply::Str(s) => String::from_utf8(s).unwrap(),
+                r => panic!("not a string: {r:?}"),
+            })
+            .collect();
+        out.sort();
+        out
+    }
+
+    #[test]
+    fn add_remove() {
+        let mut db = Db::default();
+        assert_eq!(
+            sadd(&mut db, &args(&["s", "1", "2", "a", "1"])),
+            Reply::Int(3)
+        );
+        assert_eq!(sismember(&mut db, &args(&["s", "a"])), Reply::Int(1));
+        assert_eq!(
+            smismember(&mut db, &args(&["s", "2", "3"])),
+            Reply::Arr(vec![Reply::Int(1), Reply::Int(0)])
+        );
+        assert_eq!(srem(&mut db, &args(&["s", "1", "x"])), Reply::Int(1));
+        assert_eq!(sorted(smembers(&mut db, &args(&["s"]))), ["2", "a"]);
+
+        assert_eq!(smove(&mut db, &args(&["s", "t", "a"])), Reply::Int(1));
+        assert_eq!(smove(&mut db, &args(&["s", "t", "a"])), Reply::Int(0));
+        assert_eq!(scard(&mut db, &args(&["t"])), Reply::Int(1));
+
+        assert_eq!(spop(&mut db, &args(&["s"])), Reply::str("2"));
+        assert_eq!(db.size(), 1);
+        assert_eq!(spop(&mut db, &args(&["s", "2"])), Reply::Arr(Vec::new()));
+    }
+
+    #[test]
+    fn algebra() {
+        let mut db = Db::default();
+        sadd(&mut db, &args(&["a", "1", "2", "3", "x"]));
+        sadd(&mut db, &args(&["b", "2", "3", "4"]));
+        sadd(&mut db, &args(&["c", "3", "x"]));
+
+        assert_eq!(sorted(sinter(&mut db, &args(&["a", "b", "c"]))), ["3"]);
+        assert_eq!(sorted(sinter(&mut db, &args(&["a", "missing"]))), [""; 0]);
+        assert_eq!(
+            sorted(sunion(&mut db, &args(&["a", "b"]))),
+            ["1", "2", "3", "4", "x"]
+        );
+        assert_eq!(sorted(sdiff(&mut db, &args(&["a", "b"]))), ["1", "x"]);
+        assert_eq!(
+            sintercard(&mut db, &args(&["2", "a", "b", "LIMIT", "1"])),
+            Reply::Int(1)
+        );
+
+        assert_eq!(sdiffstore(&mut db, &args(&["a", "a", "c"])), Reply::Int(2));
+        assert_eq!(sorted(smembers(&mut db, &args(&["a"]))), ["1", "2"]);
+        assert_eq!(sinterstore(&mut db, &args(&["d", "a", "c"])), Reply::Int(0));
+        assert!(db.get("d").is_none());
+
+        db.insert("str", Object::new("v"));
+        assert_eq!(
+            sunion(&mut db, &args(&["missing", "str"])),
+            Reply::err(WRONGTYPE)
+        );
+    }
+
+    #[test]
+    fn random_members() {
+        let mut db = Db::default();
+        sadd(&mut db, &args(&["s", "a", "b", "c", "d"]));
+
+        assert_eq!(sorted(srandmember(&mut db, &args(&["s", "10"]))).len(), 4);
+        for count in ["1", "2", "3"] {
+            let mut picked = sorted(srandmember(&mut db, &args(&["s", count])));
+            picked.dedup();
+            assert_eq!(picked.len().to_string(), count);
+        }
+        assert_eq!(sorted(srandmember(&mut db, &args(&["s", "-10"]))).len(), 10);
+        let min = i64::MIN.to_string();
+        assert_eq!(
+            srandmember(&mut db, &args(&["s", &min])),
+            Reply::err("ERR value is out of range")
+        );
+
+        assert_eq!(sorted(spop(&mut db, &args(&["s", "3"]))).len(), 3);
+        assert_eq!(scard(&mut db, &args(&["s"])), Reply::Int(1));
+    }
+}
@@ -0,0 +1,1088 @@
+use std::ops::Bound;
+
+use collections::{Fields, Stream, StreamId};
+
+use super::{NOT_AN_INTEGER, SYNTAX_ERROR, WRONGTYPE, wrong_arity};
+use crate::{
+    protocol::Reply,
+    storage::{Db, Object, Value, now_ms},
+};
+
+const INVALID_ID: &str = "ERR Invalid stream ID specified as stream command argument";
+
+/// How a stream is trimmed by `XADD` and `XTRIM`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Trim {
+    threshold: Threshold,
+    /// Most entries deleted at once, only allowed with `~`
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Threshold {
+    MaxLen(usize),
+    MinId(StreamId),
+}
+
+/// Handles `XADD key [NOMKSTREAM] [MAXLEN|MINID [=|~] threshold [LIMIT count]]
+/// *|id field value [field value ...]`, replying with the id of the new entry
+pub fn xadd(db: &mut Db, args: &[String]) -> Reply {
+    let [key, rest @ ..] = args else {
+        return wrong_arity("xadd");
+    };
+    let mut rest = rest;
+
+    let (mut create, mut trim) = (true, None);
+    loop {
+        match rest {
+            [opt, tail @ ..] if opt.eq_ignore_ascii_case("nomkstream") => {
+                create = false;
+                rest = tail;
+            }
+            [opt, ..] if is_trim_strategy(opt) => match parse_trim(rest) {
+                Ok((t, tail)) => {
+                    trim = Some(t);
+                    rest = tail;
+                }
+                Err(e) => return e,
+            },
+            _ => break,
+        }
+    }
+    let [id, pairs @ ..] = rest else {
+        return wrong_arity("xadd");
+    };
+    if pairs.is_empty() || !pairs.len().is_multiple_of(2) {
+        return wrong_arity("xadd");
+    }
+    let fields: Fields = pairs
+        .chunks(2)
+        .map(|pair| (pair[0].clone(), pair[1].as_bytes().to_vec()))
+        .collect();
+
+    let add = |stream: &mut Stream| {
+        let id = match next_id(stream, id) {
+            Ok(id) => id,
+            Err(e) => return e,
+        };
+        if !stream.add(id, fields) {
+            return Reply::err(
+                "ERR The ID specified in XADD is equal or smaller than the target stream top item",
+            );
+        }
+        if let Some(trim) = trim {
+            apply_trim(stream, trim);
+        }
+        Reply::str(id.to_string())
+    };
+
+    match read(db, key) {
+        Ok(Some(_)) => update(db, key, add).unwrap_or(Reply::Nil),
+        Ok(None) if !create => Reply::Nil,
+        // only create the key once the entry is accepted
+        Ok(None) => {
+            let mut stream = Stream::new();
+            let reply = add(&mut stream);
+            if !matches!(reply, Reply::Err(_)) {
+                db.insert(key, Object::new(Value::Stream(stream)));
+            }
+            reply
+        }
+        Err(e) => e,
+    }
+}
+
+/// Handles `XLEN key`
+pub fn xlen(db: &mut Db, args: &[String]) -> Reply {
+    let [key] = args else {
+        return wrong_arity("xlen");
+    };
+    match read(db, key) {
+        Ok(stream) => Reply::Int(stream.map_or(0, Stream::len) as i64),
+        Err(e) => e,
+    }
+}
+
+/// Handles `XRANGE key start end [COUNT count]`, `-` and `+` being the
+/// smallest and largest ids and a `(` prefix excluding an id
+pub fn xrange(db: &mut Db, args: &[String]) -> Reply {
+    range_command(db, args, false, "xrange")
+}
+
+/// Handles `XREVRANGE key end start [COUNT count]`, replying with the newest entries first
+pub fn xrevrange(db: &mut Db, args: &[String]) -> Reply {
+    range_command(db, args, true, "xrevrange")
+}
+
+/// Handles `XDEL key id [id ...]`, replying with the number of deleted entries
+pub fn xdel(db: &mut Db, args: &[String]) -> Reply {
+    let [key, ids @ ..] = args else {
+        return wrong_arity("xdel");
+    };
+    if ids.is_empty() {
+        return wrong_arity("xdel");
+    }
+    let ids = match ids
+        .iter()
+        .map(|id| parse_id(id))
+        .collect::<Result<Vec<_>, _>>()
+    {
+        Ok(ids) => ids,
+        Err(e) => return e,
+    };
+
+    update(db, key, |stream| {
+        Reply::Int(ids.iter().filter(|id| stream.remove(id)).count() as i64)
+    })
+    .unwrap_or(Reply::Int(0))
+}
+
+/// Handles `XTRIM key MAXLEN|MINID [=|~] threshold [LIMIT count]`,
+/// replying with the number of deleted entries
+pub fn xtrim(db: &mut Db, args: &[String]) -> Reply {
+    let [key, rest @ ..] = args else {
+        return wrong_arity("xtrim");
+    };
+    let trim = match parse_trim(rest) {
+        Ok((trim, [])) => trim,
+        Ok(_) => return Reply::err(SYNTAX_ERROR),
+        Err(e) => return e,
+    };
+
+    update(
+        db,
+        key,
+        |stream| Reply::Int(apply_trim(stream, trim) as i64),
+    )
+    .unwrap_or(Reply::Int(0))
+}
+
+/// Handles `XREAD [COUNT count] STREAMS key [key ...] id [id ...]`, replying
+/// with the entries after each id, `$` being the last id of the stream
+pub fn xread(db: &mut Db, args: &[String]) -> Reply {
+    let mut count = None;
+    let mut rest = args;
+    let streams = loop {
+        match rest {
+            [opt, n, tail @ ..] if opt.eq_ignore_ascii_case("count") => {
+                count = match parse_count(n) {
+                    Ok(n) => n,
+                    Err(e) => return e,
+                };
+                rest = tail;
+            }
+            [opt, ..] if opt.eq_ignore_ascii_case("block") => {
+                return Reply::err("ERR XREAD BLOCK is not supported");
+            }
+            [opt, tail @ ..] if opt.eq_ignore_ascii_case("streams") => break tail,
+            [] => return wrong_arity("xread"),
+            _ => return Reply::err(SYNTAX_ERROR),
+        }
+    };
+    let (keys, ids) = match split_streams(streams, "xread") {
+        Ok(split) => split,
+        Err(e) => return e,
+    };
+
+    let mut out = Vec::new();
+    for (key, id) in keys.iter().zip(ids) {
+        let stream = match read(db, key) {
+            Ok(Some(stream)) => stream,
+            Ok(None) => continue,
+            Err(e) => return e,
+        };
+        let after = if id == "$" {
+            stream.last_id()
+        } else {
+            match parse_id(id) {
+                Ok(id) => id,
+                Err(e) => return e,
+            }
+        };
+        let entries: Vec<Reply> = stream
+            .range((Bound::Excluded(after), Bound::Unbounded))
+            .take(count.unwrap_or(usize::MAX))
+            .map(|(id, fields)| entry_reply(id, Some(fields)))
+            .collect();
+        if !entries.is_empty() {
+            out.push(Reply::Arr(vec![Reply::str(key), Reply::Arr(entries)]));
+        }
+    }
+    if out.is_empty() {
+        Reply::Nil
+    } else {
+        Reply::Arr(out)
+    }
+}
+
+/// Handles `XGROUP CREATE|DESTROY|CREATECONSUMER|DELCONSUMER|SETID ...`
+pub fn xgroup(db: &mut Db, args: &[String]) -> Reply {
+    let Some(sub) = args.first() else {
+        return wrong_arity("xgroup");
+    };
+    let now = now_ms();
+    match (sub.to_ascii_lowercase().as_str(), &args[1..]) {
+        ("create", [key, group, id, opts @ ..]) => {
+            let mkstream = match opts {
+                [] => false,
+                [opt] if opt.eq_ignore_ascii_case("mkstream") => true,
+                _ => return Reply::err(SYNTAX_ERROR),
+            };
+            match read(db, key) {
+                Ok(Some(_)) => {}
+                Ok(None) if mkstream => {
+                    db.insert(key, Object::new(Value::Stream(Stream::new())));
+                }
+                Ok(None) => {
+                    return Reply::err(
+                        "ERR The XGROUP subcommand requires the key to exist. Note that for \
+                         CREATE you may want to use the MKSTREAM option to create an empty \
+                         stream automatically.",
+                    );
+                }
+                Err(e) => return e,
+            }
+            update(db, key, |stream| {
+                let id = match group_start(stream, id) {
+                    Ok(id) => id,
+                    Err(e) => return e,
+                };
+                if stream.create_group(group, id) {
+                    Reply::ok()
+                } else {
+                    Reply::err("BUSYGROUP Consumer Group name already exists")
+                }
+            })
+            .unwrap_or(Reply::Nil)
+        }
+        ("setid", [key, group, id]) => with_group(db, key, group, |stream, group_name| {
+            let id = match group_start(stream, id) {
+                Ok(id) => id,
+                Err(e) => return e,
+            };
+            if let Some(group) = stream.group_mut(group_name) {
+                group.last_delivered = id;
+            }
+            Reply::ok()
+        }),
+        ("destroy", [key, group]) => update(db, key, |stream| {
+            Reply::Int(stream.destroy_group(group) as i64)
+        })
+        .unwrap_or_else(|| no_group(key, group)),
+        ("createconsumer", [key, group, consumer]) => {
+            with_group(db, key, group, |stream, group| {
+                match stream.group_mut(group) {
+                    Some(group) => Reply::Int(group.create_consumer(consumer, now) as i64),
+                    None => Reply::Int(0),
+                }
+            })
+        }
+        ("delconsumer", [key, group, consumer]) => with_group(db, key, group, |stream, group| {
+            let deleted = stream
+                .group_mut(group)
+                .and_then(|group| group.delete_consumer(consumer));
+            Reply::Int(deleted.unwrap_or(0) as i64)
+        }),
+        ("create" | "setid" | "destroy" | "createconsumer" | "delconsumer", _) => {
+            wrong_arity("xgroup")
+        }
+        _ => Reply::err(format!("ERR unknown subcommand '{sub}'")),
+    }
+}
+
+/// Handles `XREADGROUP GROUP group consumer [COUNT count] [NOACK] STREAMS key [key ...]
+/// id [id ...]`. `>` reads entries never delivered to the group, any other id
+/// rereads the entries pending for the consumer after it
+pub fn xreadgroup(db: &mut Db, args: &[String]) -> Reply {
+    let [opt, group, consumer, rest @ ..] = args else {
+        return wrong_arity("xreadgroup");
+    };
+    let mut rest = rest;
+    if !opt.eq_ignore_ascii_case("group") {
+        return Reply::err(SYNTAX_ERROR);
+    }
+    let (mut count, mut noack) = (None, false);
+    let streams = loop {
+        match rest {
+            [opt, n, tail @ ..] if opt.eq_ignore_ascii_case("count") => {
+                count = match parse_count(n) {
+                    Ok(n) => n,
+                    Err(e) => return e,
+                };
+                rest = tail;
+            }
+            [opt, tail @ ..] if opt.eq_ignore_ascii_case("noack") => {
+                noack = true;
+                rest = tail;
+            }
+            [opt, ..] if opt.eq_ignore_ascii_case("block") => {
+                return Reply::err("ERR XREADGROUP BLOCK is not supported");
+            }
+            [opt, tail @ ..] if opt.eq_ignore_ascii_case("streams") => break tail,
+            [] => return wrong_arity("xreadgroup"),
+            _ => return Reply::err(SYNTAX_ERROR),
+        }
+    };
+    let (keys, ids) = match split_streams(streams, "xreadgroup") {
+        Ok(split) => split,
+        Err(e) => return e,
+    };
+    // every group has to exist before anything is delivered
+    for key in keys {
+        match read(db, key) {
+            Ok(Some(stream)) if stream.group(group).is_some() => {}
+            Ok(_) => return no_group(key, group),
+            Err(e) => return e,
+        }
+    }
+
+    let (now, limit) = (now_ms(), count.unwrap_or(usize::MAX));
+    let mut out = Vec::new();
+    for (key, id) in keys.iter().zip(ids) {
+        let reply = if id == ">" {
+            update(db, key, |stream| {
+                deliver_new(stream, group, consumer, limit, noack, now)
+            })
+        } else {
+            let after = match parse_id(id) {
+                Ok(id) => id,
+                Err(e) => return e,
+            };
+            update(db, key, |stream| {
+                reread_pending(stream, group, consumer, after, limit, now)
+            })
+        };
+        match reply {
+            Some(Reply::Arr(entries)) if entries.is_empty() && id == ">" => {}
+            Some(entries @ Reply::Arr(_)) => {
+                out.push(Reply::Arr(vec![Reply::str(key), entries]));
+            }
+            Some(e) => return e,
+            None => return no_group(key, group),
+        }
+    }
+    if out.is_empty() {
+        Reply::Nil
+    } else {
+        Reply::Arr(out)
+    }
+}
+
+/// Handles `XACK key group id [id ...]`, replying with the number of acknowledged entries
+pub fn xack(db: &mut Db, args: &[String]) -> Reply {
+    let [key, group, ids @ ..] = args else {
+        return wrong_arity("xack");
+    };
+    if ids.is_empty() {
+        return wrong_arity("xack");
+    }
+    let ids = match ids
+        .iter()
+        .map(|id| parse_id(id))
+        .collect::<Result<Vec<_>, _>>()
+    {
+        Ok(ids) => ids,
+        Err(e) => return e,
+    };
+
+    update(db, key, |stream| match stream.group_mut(group) {
+        Some(group) => Reply::Int(ids.iter().filter(|id| group.ack(id)).count() as i64),
+        None => Reply::Int(0),
+    })
+    .unwrap_or(Reply::Int(0))
+}
+
+/// Handles `XPENDING key group [[IDLE min-idle-time] start end count [consumer]]`.
+/// Without a range it replies with a summary of the pending entries
+pub fn xpending(db: &mut Db, args: &[String]) -> Reply {
+    let [key, group, rest @ ..] = args else {
+        return wrong_arity("xpending");
+    };
+    let (min_idle, rest) = match rest {
+        [opt, idle, tail @ ..] if opt.eq_ignore_ascii_case("idle") => match idle.parse::<u64>() {
+            Ok(idle) => (idle, tail),
+            Err(_) => return Reply::err(NOT_AN_INTEGER),
+        },
+        _ => (0, rest),
+    };
+
+    let stream = match read(db, key) {
+        Ok(Some(stream)) => stream,
+        Ok(None) => return no_group(key, group),
+        Err(e) => return e,
+    };
+    let Some(group_state) = stream.group(group) else {
+        return no_group(key, group);
+    };
+    let pending = group_state.pending();
+
+    let (start, end, count, consumer) = match rest {
+        [] => {
+            let (Some((first, _)), Some((last, _))) =
+                (pending.first_key_value(), pending.last_key_value())
+            else {
+                return Reply::Arr(vec![Reply::Int(0), Reply::Nil, Reply::Nil, Reply::Nil]);
+            };
+            let consumers = group_state
+                .consumers()
+                .iter()
+                .filter(|(_, c)| !c.pending().is_empty())
+                .map(|(name, c)| {
+                    Reply::Arr(vec![
+                        Reply::str(name),
+                        Reply::str(c.pending().len().to_string()),
+                    ])
+                })
+                .collect();
+            return Reply::Arr(vec![
+                Reply::Int(pending.len() as i64),
+                Reply::str(first.to_string()),
+                Reply::str(last.to_string()),
+                Reply::Arr(consumers),
+            ]);
+        }
+        [start, end, count] => (start, end, count, None),
+        [start, end, count, consumer] => (start, end, count, Some(consumer)),
+        _ => return Reply::err(SYNTAX_ERROR),
+    };
+    let range = match parse_range(start, end) {
+        Ok(range) => range,
+        Err(e) => return e,
+    };
+    let Ok(count) = count.parse::<usize>() else {
+        return Reply::err(NOT_AN_INTEGER);
+    };
+
+    let now = now_ms();
+    let Some((start, end)) = range else {
+        return Reply::Arr(Vec::new());
+    };
+    Reply::Arr(
+        pending
+            .range(start..=end)
+            .filter(|(_, p)| consumer.is_none_or(|c| *c == p.consumer))
+            .filter(|(_, p)| now.saturating_sub(p.delivered_at) >= min_idle)
+            .take(count)
+            .map(|(id, p)| {
+                Reply::Arr(vec![
+                    Reply::str(id.to_string()),
+                    Reply::str(&p.consumer),
+                    Reply::Int(now.saturating_sub(p.delivered_at) as i64),
+                    Reply::Int(p.delivery_count as i64),
+                ])
+            })
+            .collect(),
+    )
+}
+
+/// Handles `XCLAIM key group consumer min-idle-time id [id ...] [FORCE] [JUSTID]`,
+/// handing the entries idle for at least `min-idle-time` ms over to `consumer`
+pub fn xclaim(db: &mut Db, args: &[String]) -> Reply {
+    let [key, group, consumer, min_idle, rest @ ..] = args else {
+        return wrong_arity("xclaim");
+    };
+    let Ok(min_idle) = min_idle.parse::<u64>() else {
+        return Reply::err("ERR Invalid min-idle-time argument for XCLAIM");
+    };
+
+    let (mut ids, mut force, mut justid) = (Vec::new(), false, false);
+    for arg in rest {
+        match arg.to_ascii_lowercase().as_str() {
+            "force" => force = true,
+            "justid" => justid = true,
+            _ if force || justid => return Reply::err(SYNTAX_ERROR),
+            _ => match parse_id(arg) {
+                Ok(id) => ids.push(id),
+                Err(e) => return e,
+            },
+        }
+    }
+    if ids.is_empty() {
+        return wrong_arity("xclaim");
+    }
+
+    let now = now_ms();
+    with_group(db, key, group, |stream, group| {
+        let mut out = Vec::new();
+        for id in ids {
+            let fields = stream.get(&id).cloned();
+            let Some(group) = stream.group_mut(group) else {
+                break;
+            };
+            let idle = group
+                .pending()
+                .get(&id)
+                .map(|p| now.saturating_sub(p.delivered_at));
+            match (idle, &fields) {
+                // deleted entries can't be processed anymore
+                (Some(_), None) => {
+                    group.ack(&id);
+                    continue;
+                }
+                (Some(idle), Some(_)) if idle >= min_idle => {}
+                (None, Some(_)) if force => {}
+                _ => continue,
+            }
+            if justid {
+                if !group.claim(id, consumer, now) {
+                    group.deliver(id, consumer, now);
+                }
+                out.push(Reply::str(id.to_string()));
+            } else {
+                group.deliver(id, consumer, now);
+                out.push(entry_reply(&id, fields.as_ref()));
+            }
+        }
+        Reply::Arr(out)
+    })
+}
+
+/// Handles `XAUTOCLAIM key group consumer min-idle-time start [COUNT count] [JUSTID]`,
+/// claiming up to `count` entries pending after `start`. Replies with the id to
+/// continue from, the claimed entries and the ids of entries deleted meanwhile
+pub fn xautoclaim(db: &mut Db, args: &[String]) -> Reply {
+    let [key, group, consumer, min_idle, start, opts @ ..] = args else {
+        return wrong_arity("xautoclaim");
+    };
+    let Ok(min_idle) = min_idle.parse::<u64>() else {
+        return Reply::err("ERR Invalid min-idle-time argument for XAUTOCLAIM");
+    };
+    let start = match parse_bound(start, 0) {
+        Ok(Bound::Included(id)) => id,
+        Ok(Bound::Excluded(id)) => match id.next() {
+            Some(id) => id,
+            None => return Reply::err(INVALID_ID),
+        },
+        Ok(Bound::Unbounded) => StreamId::MIN,
+        Err(e) => return e,
+    };
+    let (mut count, mut justid) = (100, false);
+    let mut opts = opts.iter();
+    while let Some(opt) = opts.next() {
+        match opt.to_ascii_lowercase().as_str() {
+            "count" => match opts.next().map(|n| n.parse::<usize>()) {
+                Some(Ok(n)) if n > 0 => count = n,
+                Some(_) => return Reply::err("ERR COUNT must be > 0"),
+                None => return Reply::err(SYNTAX_ERROR),
+            },
+            "justid" => justid = true,
+            _ => return Reply::err(SYNTAX_ERROR),
+        }
+    }
+
+    let now = now_ms();
+    with_group(db, key, group, |stream, group_name| {
+        let Some(group) = stream.group(group_name) else {
+            return no_group(key, group_name);
+        };
+        // scan a bounded number of entries, so a long idle free list can't stall us
+        let mut scanned = group.pending().range(start..).take(count * 10);
+        let mut candidates = Vec::new();
+        let mut next = StreamId::MIN;
+        for (id, pending) in scanned.by_ref() {
+            if candidates.len() == count {
+                next = *id;
+                break;
+            }
+            if now.saturating_sub(pending.delivered_at) >= min_idle {
+                candidates.push(*id);
+            }
+        }
+        if next == StreamId::MIN
+            && let Some((id, _)) = scanned.next()
+        {
+            next = *id;
+        }
+
+        let mut claimed = Vec::new();
+        let mut deleted = Vec::new();
+        for id in candidates {
+            let fields = stream.get(&id).cloned();
+            let Some(group) = stream.group_mut(group_name) else {
+                break;
+            };
+            match fields {
+                None => {
+                    group.ack(&id);
+                    deleted.push(Reply::str(id.to_string()));
+                }
+                Some(_) if justid => {
+                    group.claim(id, consumer, now);
+                    claimed.push(Reply::str(id.to_string()));
+                }
+                Some(fields) => {
+                    group.deliver(id, consumer, now);
+                    claimed.push(entry_reply(&id, Some(&fields)));
+                }
+            }
+        }
+        Reply::Arr(vec![
+            Reply::str(next.to_string()),
+            Reply::Arr(claimed),
+            Reply::Arr(deleted),
+        ])
+    })
+}
+
+fn range_command(db: &mut Db, args: &[String], rev: bool, name: &str) -> Reply {
+    let (key, start, end, count) = match args {
+        [key, a, b] => (key, a, b, None),
+        [key, a, b, opt, n] if opt.eq_ignore_ascii_case("count") => match n.parse::<usize>() {
+            Ok(n) => (key, a, b, Some(n)),
+            Err(_) => return Reply::err(NOT_AN_INTEGER),
+        },
+        [_, _, _, _, ..] => return Reply::err(SYNTAX_ERROR),
+        _ => return wrong_arity(name),
+    };
+    // XREVRANGE takes the end first
+    let (start, end) = if rev { (end, start) } else { (start, end) };
+    let range = match parse_range(start, end) {
+        Ok(range) => range,
+        Err(e) => return e,
+    };
+
+    let stream = match read(db, key) {
+        Ok(Some(stream)) => stream,
+        Ok(None) => return Reply::Arr(Vec::new()),
+        Err(e) => return e,
+    };
+    let Some((start, end)) = range else {
+        return Reply::Arr(Vec::new());
+    };
+    let entries = stream.range(start..=end);
+    let count = count.unwrap_or(usize::MAX);
+    let to_reply = |(id, fields)| entry_reply(id, Some(fields));
+    if rev {
+        Reply::Arr(entries.rev().take(count).map(to_reply).collect())
+    } else {
+        Reply::Arr(entries.take(count).map(to_reply).collect())
+    }
+}
+
+/// Delivers up to `count` entries the group hasn't delivered yet to `consumer`
+fn deliver_new(
+    stream: &mut Stream,
+    group: &str,
+    consumer: &str,
+    count: usize,
+    noack: bool,
+    now: u64,
+) -> Reply {
+    let Some(after) = stream.group(group).map(|g| g.last_delivered) else {
+        return Reply::Nil;
+    };
+    let entries: Vec<(StreamId, Fields)> = stream
+        .range((Bound::Excluded(after), Bound::Unbounded))
+        .take(count)
+        .map(|(id, fields)| (*id, fields.clone()))
+        .collect();
+
+    let Some(group) = stream.group_mut(group) else {
+        return Reply::Nil;
+    };
+    group.consumer(consumer, now);
+    Reply::Arr(
+        entries
+            .into_iter()
+            .map(|(id, fields)| {
+                group.last_delivered = id;
+                if !noack {
+                    group.deliver(id, consumer, now);
+                }
+                entry_reply(&id, Some(&fields))
+            })
+            .collect(),
+    )
+}
+
+/// Replies with the entries pending for `consumer` after `after`, without
+/// counting them as delivered again. Deleted entries have nil fields
+fn reread_pending(
+    stream: &mut Stream,
+    group: &str,
+    consumer: &str,
+    after: StreamId,
+    count: usize,
+    now: u64,
+) -> Reply {
+    let Some(group) = stream.group_mut(group) else {
+        return Reply::Nil;
+    };
+    let ids: Vec<StreamId> = group
+        .consumer(consumer, now)
+        .pending()
+        .range((Bound::Excluded(after), Bound::Unbounded))
+        .take(count)
+        .copied()
+        .collect();
+    Reply::Arr(
+        ids.iter()
+            .map(|id| entry_reply(id, stream.get(id)))
+            .collect(),
+    )
+}
+
+fn entry_reply(id: &StreamId, fields: Option<&Fields>) -> Reply {
+    let fields = fields.map_or(Reply::Nil, |fields| {
+        Reply::Arr(
+            fields
+                .iter()
+                .flat_map(|(f, v)| [Reply::str(f), Reply::str(v)])
+                .collect(),
+        )
+    });
+    Reply::Arr(vec![Reply::str(id.to_string()), fields])
+}
+
+/// Resolves the id given to `XADD`: `*`, `ms-*` or a full id
+fn next_id(stream: &Stream, id: &str) -> Result<StreamId, Reply> {
+    let last = stream.last_id();
+    let exhausted = || {
+        Reply::err("ERR The stream has exhausted the last possible ID, unable to add more items")
+    };
+    let id = if id == "*" {
+        stream.next_id(now_ms()).ok_or_else(exhausted)?
+    } else if let Some(ms) = id.strip_suffix("-*") {
+        let ms = ms.parse::<u64>().map_err(|_| Reply::err(INVALID_ID))?;
+        match ms.cmp(&last.ms) {
+            std::cmp::Ordering::Greater => StreamId::new(ms, 0),
+            std::cmp::Ordering::Equal => last.next().ok_or_else(exhausted)?,
+            std::cmp::Ordering::Less => StreamId::new(ms, 0),
+        }
+    } else {
+        parse_id(id)?
+    };
+
+    if id == StreamId::MIN {
+        return Err(Reply::err(
+            "ERR The ID specified in XADD must be greater than 0-0",
+        ));
+    }
+    Ok(id)
+}
+
+/// Resolves the id a group starts delivering after, `$` being the last id
+fn group_start(stream: &Stream, id: &str) -> Result<StreamId, Reply> {
+    if id == "$" {
+        Ok(stream.last_id())
+    } else {
+        parse_id(id)
+    }
+}
+
+fn parse_id(id: &str) -> Result<StreamId, Reply> {
+    id.parse().map_err(|_| Reply::err(INVALID_ID))
+}
+
+/// Parses a range bound: `-`, `+`, an id or an id prefixed with `(` to exclude it.
+/// Ids without a sequence number get `default_seq`
+fn parse_bound(bound: &str, default_seq: u64) -> Result<Bound<StreamId>, Reply> {
+    let parse = |id: &str| match id.split_once('-') {
+        Some(_) => parse_id(id),
+        None => id
+            .parse::<u64>()
+            .map(|ms| StreamId::new(ms, default_seq))
+            .map_err(|_| Reply::err(INVALID_ID)),
+    };
+    match bound {
+        "-" | "+" => Ok(Bound::Unbounded),
+        _ => match bound.strip_prefix('(') {
+            Some(id) => parse(id).map(Bound::Excluded),
+            None => parse(bound).map(Bound::Included),
+        },
+    }
+}
+
+/// Parses an `XRANGE` style range into inclusive ends, `None` if it is empty
+fn parse_range(start: &str, end: &str) -> Result<Option<(StreamId, StreamId)>, Reply> {
+    let start = match parse_bound(start, 0)? {
+        Bound::Unbounded => Some(StreamId::MIN),
+        Bound::Included(id) => Some(id),
+        Bound::Excluded(id) => id.next(),
+    };
+    let end = match parse_bound(end, u64::MAX)? {
+        Bound::Unbounded => Some(StreamId::MAX),
+        Bound::Included(id) => Some(id),
+        Bound::Excluded(id) => id.prev(),
+    };
+    Ok(start.zip(end).filter(|(start, end)| start <= end))
+}
+
+fn parse_count(count: &str) -> Result<Option<usize>, Reply> {
+    match count.parse::<usize>() {
+        Ok(0) => Ok(None),
+        Ok(n) => Ok(Some(n)),
+        Err(_) => Err(Reply::err(NOT_AN_INTEGER)),
+    }
+}
+
+fn is_trim_strategy(opt: &str) -> bool {
+    opt.eq_ignore_ascii_case("maxlen") || opt.eq_ignore_ascii_case("minid")
+}
+
+/// Parses `MAXLEN|MINID [=|~] threshold [LIMIT count]`, returning the rest of the arguments.
+/// Trimming is always exact, `~` only allows a `LIMIT`
+fn parse_trim(args: &[String]) -> Result<(Trim, &[String]), Reply> {
+    let [strategy, rest @ ..] = args else {
+        return Err(Reply::err(SYNTAX_ERROR));
+    };
+    let (approx, rest) = match rest {
+        [op, tail @ ..] if op == "~" => (true, tail),
+        [op, tail @ ..] if op == "=" => (false, tail),
+        _ => (false, rest),
+    };
+    let [threshold, rest @ ..] = rest else {
+        return Err(Reply::err(SYNTAX_ERROR));
+    };
+
+    let threshold = if strategy.eq_ignore_ascii_case("maxlen") {
+        match threshold.parse::<usize>() {
+            Ok(n) => Threshold::MaxLen(n),
+            Err(_) => return Err(Reply::err("ERR The MAXLEN argument must be >= 0.")),
+        }
+    } else {
+        Threshold::MinId(parse_id(threshold)?)
+    };
+
+    match rest {
+        [opt, limit, tail @ ..] if opt.eq_ignore_ascii_case("limit") => {
+            if !approx {
+                return Err(Reply::err(
+                    "ERR syntax error, LIMIT cannot be used without the special ~ option",
+                ));
+            }
+            match limit.parse::<usize>() {
+                Ok(0) => Ok((
+                    Trim {
+                        threshold,
+                        limit: None,
+                    },
+                    tail,
+                )),
+                Ok(n) => Ok((
+                    Trim {
+                        threshold,
+                        limit: Some(n),
+                    },
+                    tail,
+                )),
+                Err(_) => Err(Reply::err(NOT_AN_INTEGER)),
+            }
+        }
+        _ => Ok((
+            Trim {
+                threshold,
+                limit: None,
+            },
+            rest,
+        )),
+    }
+}
+
+fn apply_trim(stream: &mut Stream, trim: Trim) -> usize {
+    match trim.threshold {
+        Threshold::MaxLen(n) => stream.trim_max_len(n, trim.limit),
+        Threshold::MinId(id) => stream.trim_min_id(id, trim.limit),
+    }
+}
+
+/// Splits the arguments after `STREAMS` into the keys and their ids
+fn split_streams<'a>(
+    args: &'a [String],
+    name: &str,
+) -> Result<(&'a [String], &'a [String]), Reply> {
+    if args.is_empty() || !args.len().is_multiple_of(2) {
+        return Err(Reply::err(format!(
+            "ERR Unbalanced '{name}' list of streams: for each stream key an ID or '$' must be specified."
+        )));
+    }
+    Ok(args.split_at(args.len() / 2))
+}
+
+fn no_group(key: &str, group: &str) -> Reply {
+    Reply::err(format!(
+        "NOGROUP No such key '{key}' or consumer group '{group}'"
+    ))
+}
+
+/// The stream stored at `key`, `None` if the key doesn't exist
+fn read<'a>(db: &'a mut Db, key: &str) -> Result<Option<&'a Stream>, Reply> {
+    match db.get(key).map(|obj| obj.value.as_stream()) {
+        Some(Some(stream)) => Ok(Some(stream)),
+        Some(None) => Err(Reply::err(WRONGTYPE)),
+        None => Ok(None),
+    }
+}
+
+/// Runs `f` on the stream at `key`, `None` if the key doesn't exist.
+/// Empty streams are kept, unlike other empty values
+fn update(db: &mut Db, key: &str, f: impl FnOnce(&mut Stream) -> Reply) -> Option<Reply> {
+    let mut obj = db.get_mut(key)?;
+    match obj.value.as_stream_mut() {
+        Some(stream) => Some(f(stream)),
+        None => Some(Reply::err(WRONGTYPE)),
+    }
+}
+
+/// Runs `f` on the stream at `key` if it has a group called `group`
+fn with_group(
+    db: &mut Db,
+    key: &str,
+    group: &str,
+    f: impl FnOnce(&mut Stream, &str) -> Reply,
+) -> Reply {
+    update(db, key, |stream| {
+        if stream.group(group).is_none() {
+            return no_group(key, group);
+        }
+        f(stream, group)
+    })
+    .unwrap_or_else(|| no_group(key, group))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::commands::args;
+
+    fn ids(reply: &Reply) -> Vec<String> {
+        let Reply::Arr(entries) = reply else {
+            panic!("not an array: {reply:?}")
+        };
+        entries
+            .iter()
+            .map(|e| match e {
+                Reply::Arr(parts) => match &parts[0] {
+                    Reply::Str(id) => String::from_utf8(id.clone()).unwrap(),
+                    r => panic!("not an id: {r:?}"),
+                },
+                r => panic!("not an entry: {r:?}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn add_and_range() {
+        let mut db = Db::default();
+        assert_eq!(
+            xadd(&mut db, &args(&["s", "1-1", "a", "1"])),
+            Reply::str("1-1")
+        );
+        assert_eq!(
+            xadd(&mut db, &args(&["s", "1-*", "a", "2"])),
+            Reply::str("1-2")
+        );
+        assert_eq!(
+            xadd(&mut db, &args(&["s", "5", "a", "3"])),
+            Reply::str("5-0")
+        );
+        assert!(matches!(
+            xadd(&mut db, &args(&["s", "4-0", "a", "4"])),
+            Reply::Err(_)
+        ));
+        assert_eq!(
+            xadd(&mut db, &args(&["s", "MAXLEN", "=", "2", "6-0", "a", "5"])),
+            Reply::str("6-0")
+        );
+        assert_eq!(xlen(&mut db, &args(&["s"])), Reply::Int(2));
+
+        assert_eq!(
+            ids(&xrange(&mut db, &args(&["s", "-", "+"]))),
+            ["5-0", "6-0"]
+        );
+        assert_eq!(ids(&xrange(&mut db, &args(&["s", "(5-0", "+"]))), ["6-0"]);
+        assert_eq!(ids(&xrange(&mut db, &args(&["s", "5", "5"]))), ["5-0"]);
+        assert_eq!(
+            ids(&xrevrange(&mut db, &args(&["s", "+", "-", "COUNT", "1"]))),
+            ["6-0"]
+        );
+        assert_eq!(
+            xrange(&mut db, &args(&["s", "6", "6"])),
+            Reply::Arr(vec![Reply::Arr(vec![
+                Reply::str("6-0"),
+                Reply::Arr(vec![Reply::str("a"), Reply::str("5")])
+            ])])
+        );
+
+        assert_eq!(xtrim(&mut db, &args(&["s", "MINID", "6"])), Reply::Int(1));
+        assert_eq!(xdel(&mut db, &args(&["s", "6-0", "7-0"])), Reply::Int(1));
+        assert_eq!(xlen(&mut db, &args(&["s"])), Reply::Int(0));
+        // ids keep increasing after the entries are gone
+        assert_eq!(
+            xadd(&mut db, &args(&["s", "6-*", "a", "6"])),
+            Reply::str("6-1")
+        );
+        assert_eq!(
+            xadd(&mut db, &args(&["missing", "NOMKSTREAM", "*", "a", "1"])),
+            Reply::Nil
+        );
+        for id in ["0-0", "bogus"] {
+            assert!(matches!(
+                xadd(&mut db, &args(&["missing", id, "a", "1"])),
+                Reply::Err(_)
+            ));
+        }
+        assert!(db.get("missing").is_none());
+    }
+
+    #[test]
+    fn consumer_groups() {
+        let mut db = Db::default();
+        assert_eq!(
+            xgroup(&mut db, &args(&["CREATE", "s", "g", "$", "MKSTREAM"])),
+            Reply::ok()
+        );
+        for id in ["1", "2", "3"] {
+            xadd(&mut db, &args(&["s", id, "f", "v"]));
+        }
+
+        let read = |db: &mut Db, consumer: &str, id: &str| match xreadgroup(
+            db,
+            &args(&["GROUP", "g", consumer, "COUNT", "2", "STREAMS", "s", id]),
+        ) {
+            Reply::Arr(streams) => match &streams[0] {
+                Reply::Arr(parts) => ids(&parts[1]),
+                r => panic!("{r:?}"),
+            },
+            Reply::Nil => Vec::new(),
+            r => panic!("{r:?}"),
+        };
+        assert_eq!(read(&mut db, "alice", ">"), ["1-0", "2-0"]);
+        assert_eq!(read(&mut db, "bob", ">"), ["3-0"]);
+        assert!(read(&mut db, "bob", ">").is_empty());
+        assert_eq!(read(&mut db, "alice", "0"), ["1-0", "2-0"]);
+
+        assert_eq!(
+            xack(&mut db, &args(&["s", "g", "1-0", "3-0"])),
+            Reply::Int(2)
+        );
+        assert_eq!(
+            xpending(&mut db, &args(&["s", "g"])),
+            Reply::Arr(vec![
+                Reply::Int(1),
+                Reply::str("2-0"),
+                Reply::str("2-0"),
+                Reply::Arr(vec![Reply::Arr(vec![Reply::str("alice"), Reply::str("1")])]),
+            ])
+        );
+
+        assert_eq!(
+            xclaim(&mut db, &args(&["s", "g", "bob", "0", "2-0", "JUSTID"])),
+            Reply::Arr(vec![Reply::str("2-0")])
+        );
+        assert_eq!(read(&mut db, "bob", "0"), ["2-0"]);
+        xdel(&mut db, &args(&["s", "2-0"]));
+        assert_eq!(
+            xautoclaim(&mut db, &args(&["s", "g", "alice", "0", "-"])),
+            Reply::Arr(vec![
+                Reply::str("0-0"),
+                Reply::Arr(Vec::new()),
+                Reply::Arr(vec![Reply::str("2-0")]),
+            ])
+        );
+        assert!(matches!(
+            xreadgroup(&mut db, &args(&["GROUP", "nope", "a", "STREAMS", "s", ">"])),
+            Reply::Err(e) if e.starts_with("NOGROUP")
+        ));
+    }
+}
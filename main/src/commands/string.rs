@@ -0,0 +1,549 @@
+use super::{
+    NOT_A_FLOAT, NOT_AN_INTEGER, SYNTAX_ERROR, WRONGTYPE, parse_float, parse_int, range,
+    wrong_arity,
+};
+use crate::{
+    protocol::Reply,
+    storage::{Db, Object, Value, now_ms},
+};
+
+/// Longest string `SETRANGE` and `APPEND` are allowed to build
+const MAX_STRING_LEN: usize = 512 << 20;
+
+/// Handles `GET key`
+pub fn get(db: &mut Db, args: &[String]) -> Reply {
+    let [key] = args else {
+        return wrong_arity("get");
+    };
+    match db.get(key) {
+        Some(obj) => string_reply(&obj.value),
+        None => Reply::Nil,
+    }
+}
+
+/// Handles `SET key value [NX | XX] [GET] [EX s | PX ms | EXAT ts | PXAT ts | KEEPTTL]`.
+///
+/// Replies with the previous value, or the new one if there was none.
+/// With `GET` it replies with the previous value or nil,
+/// and nil if the `NX` or `XX` condition wasn't met
+pub fn set(db: &mut Db, args: &[String]) -> Reply {
+    let [key, value, opts @ ..] = args else {
+        return wrong_arity("set");
+    };
+
+    let (mut nx, mut xx, mut get, mut keepttl) = (false, false, false, false);
+    let mut expire_at = None;
+    let mut opts = opts.iter();
+    while let Some(opt) = opts.next() {
+        match opt.to_ascii_lowercase().as_str() {
+            "nx" if !xx => nx = true,
+            "xx" if !nx => xx = true,
+            "get" => get = true,
+            "keepttl" if expire_at.is_none() => keepttl = true,
+            unit @ ("ex" | "px" | "exat" | "pxat") if !keepttl && expire_at.is_none() => {
+                let Some(time) = opts.next() else {
+                    return Reply::err(SYNTAX_ERROR);
+                };
+                match parse_expiry(unit, time, "set") {
+                    Ok(at) => expire_at = Some(at),
+                    Err(e) => return e,
+                }
+            }
+            _ => return Reply::err(SYNTAX_ERROR),
+        }
+    }
+
+    // any type gets overwritten, but only strings can be returned
+    let old = db.get(key).map(|obj| (obj.value.to_bytes(), obj.expire_at));
+    if get && matches!(old, Some((None, _))) {
+        return Reply::err(WRONGTYPE);
+    }
+    let old_value = || match &old {
+        Some((Some(value), _)) => Reply::Str(value.clone()),
+        _ => Reply::Nil,
+    };
+    if (nx && old.is_some()) || (xx && old.is_none()) {
+        return if get { old_value() } else { Reply::Nil };
+    }
+
+    let mut obj = Object::new(value.as_str());
+    obj.expire_at = match &old {
+        Some((_, ttl)) if keepttl => *ttl,
+        _ => expire_at,
+    };
+    db.insert(key, obj);
+
+    match &old {
+        _ if get => old_value(),
+        Some((Some(value), _)) => Reply::Str(value.clone()),
+        _ => Reply::str(value),
+    }
+}
+
+/// Handles `MGET key [key ...]`, replying with nil for missing keys and ones that aren't strings
+pub fn mget(db: &mut Db, args: &[String]) -> Reply {
+    if args.is_empty() {
+        return wrong_arity("mget");
+    }
+    Reply::Arr(
+        args.iter()
+            .map(
+                |key| match db.get(key).and_then(|obj| obj.value.to_bytes()) {
+                    Some(value) => Reply::Str(value),
+                    None => Reply::Nil,
+                },
+            )
+            .collect(),
+    )
+}
+
+/// Handles `MSET key value [key value ...]`
+pub fn mset(db: &mut Db, args: &[String]) -> Reply {
+    if args.is_empty() || !args.len().is_multiple_of(2) {
+        return wrong_arity("mset");
+    }
+    for pair in args.chunks(2) {
+        db.insert(&pair[0], Object::new(pair[1].as_str()));
+    }
+    Reply::ok()
+}
+
+/// Handles `MSETNX key value [key value ...]`, which sets nothing if any of the keys exists.
+/// Replies with 1 if the keys were set, 0 otherwise
+pub fn msetnx(db: &mut Db, args: &[String]) -> Reply {
+    if args.is_empty() || !args.len().is_multiple_of(2) {
+        return wrong_arity("msetnx");
+    }
+    if args.chunks(2).any(|pair| db.get(&pair[0]).is_some()) {
+        return Reply::Int(0);
+    }
+    mset(db, args);
+    Reply::Int(1)
+}
+
+/// Handles `GETEX key [EX s | PX ms | EXAT ts | PXAT ts | PERSIST]`
+pub fn getex(db: &mut Db, args: &[String]) -> Reply {
+    let (key, expire_at) = match args {
+        [key] => (key, None),
+        [key, persist] if persist.eq_ignore_ascii_case("persist") => (key, Some(None)),
+        [key, unit, time] => {
+            let unit = unit.to_ascii_lowercase();
+            if !matches!(unit.as_str(), "ex" | "px" | "exat" | "pxat") {
+                return Reply::err(SYNTAX_ERROR);
+            }
+            match parse_expiry(&unit, time, "getex") {
+                Ok(at) => (key, Some(Some(at))),
+                Err(e) => return e,
+            }
+        }
+        [] => return wrong_arity("getex"),
+        _ => return Reply::err(SYNTAX_ERROR),
+    };
+
+    let Some(mut obj) = db.get_mut(key) else {
+        return Reply::Nil;
+    };
+    if !obj.value.is_string() {
+        return Reply::err(WRONGTYPE);
+    }
+    if let Some(expire_at) = expire_at {
+        obj.expire_at = expire_at;
+    }
+    string_reply(&obj.value)
+}
+
+/// Handles `GETDEL key`
+pub fn getdel(db: &mut Db, args: &[String]) -> Reply {
+    let [key] = args else {
+        return wrong_arity("getdel");
+    };
+    match db.get(key) {
+        Some(obj) if !obj.value.is_string() => Reply::err(WRONGTYPE),
+        Some(_) => string_reply(&db.remove(key).unwrap().value),
+        None => Reply::Nil,
+    }
+}
+
+/// Handles `GETSET key value`, which also clears the expiry
+pub fn getset(db: &mut Db, args: &[String]) -> Reply {
+    let [key, value] = args else {
+        return wrong_arity("getset");
+    };
+    if db.get(key).is_some_and(|obj| !obj.value.is_string()) {
+        return Reply::err(WRONGTYPE);
+    }
+    match db.insert(key, Object::new(value.as_str())) {
+        Some(old) => string_reply(&old.value),
+        None => Reply::Nil,
+    }
+}
+
+/// Handles `STRLEN key`
+pub fn strlen(db: &mut Db, args: &[String]) -> Reply {
+    let [key] = args else {
+        return wrong_arity("strlen");
+    };
+    match db.get(key).map(|obj| obj.value.str_len()) {
+        Some(Some(len)) => Reply::Int(len as i64),
+        Some(None) => Reply::err(WRONGTYPE),
+        None => Reply::Int(0),
+    }
+}
+
+/// Handles `APPEND key value`, replying with the new length
+pub fn append(db: &mut Db, args: &[String]) -> Reply {
+    let [key, value] = args else {
+        return wrong_arity("append");
+    };
+
+    let Some(mut obj) = db.get_mut(key) else {
+        db.insert(key, Object::new(value.as_str()));
+        return Reply::Int(value.len() as i64);
+    };
+    let Some(bytes) = obj.value.make_raw() else {
+        return Reply::err(WRONGTYPE);
+    };
+    if bytes.len() + value.len() > MAX_STRING_LEN {
+        return Reply::err("ERR string exceeds maximum allowed size");
+    }
+    bytes.extend_from_slice(value.as_bytes());
+    Reply::Int(bytes.len() as i64)
+}
+
+/// Handles `GETRANGE key start end`, both ends inclusive and negative ones counting from the end
+pub fn getrange(db: &mut Db, args: &[String]) -> Reply {
+    let [key, start, end] = args else {
+        return wrong_arity("getrange");
+    };
+    let (start, end) = match (parse_int(start), parse_int(end)) {
+        (Ok(start), Ok(end)) => (start, end),
+        (Err(e), _) | (_, Err(e)) => return e,
+    };
+
+    let Some(obj) = db.get(key) else {
+        return Reply::str("");
+    };
+    let Some(bytes) = obj.value.to_bytes() else {
+        return Reply::err(WRONGTYPE);
+    };
+    // unlike with lists, an end before the first byte still selects the first byte
+    let end = end.max(-(bytes.len() as i64));
+    match range(start, end, bytes.len()) {
+        Some((start, end)) => Reply::str(&bytes[start..=end]),
+        None => Reply::str(""),
+    }
+}
+
+/// Handles `SETRANGE key offset value`, padding with zero bytes if needed.
+/// Replies with the new length
+pub fn setrange(db: &mut Db, args: &[String]) -> Reply {
+    let [key, offset, value] = args else {
+        return wrong_arity("setrange");
+    };
+    let offset = match offset.parse::<usize>() {
+        Ok(offset) => offset,
+        Err(_) => return Reply::err("ERR offset is out of range"),
+    };
+    let end = match offset.checked_add(value.len()) {
+        Some(end) if end <= MAX_STRING_LEN => end,
+        _ => return Reply::err("ERR string exceeds maximum allowed size"),
+    };
+
+    let Some(mut obj) = db.get_mut(key) else {
+        if value.is_empty() {
+            return Reply::Int(0);
+        }
+        let mut bytes = vec![0; offset];
+        bytes.extend_from_slice(value.as_bytes());
+        let len = bytes.len();
+        db.insert(key, Object::new(bytes));
+        return Reply::Int(len as i64);
+    };
+
+    if value.is_empty() {
+        return match obj.value.str_len() {
+            Some(len) => Reply::Int(len as i64),
+            None => Reply::err(WRONGTYPE),
+        };
+    }
+    let Some(bytes) = obj.value.make_raw() else {
+        return Reply::err(WRONGTYPE);
+    };
+    if bytes.len() < end {
+        bytes.resize(end, 0);
+    }
+    bytes[offset..end].copy_from_slice(value.as_bytes());
+    Reply::Int(bytes.len() as i64)
+}
+
+fn string_reply(value: &Value) -> Reply {
+    match value.to_bytes() {
+        Some(bytes) => Reply::Str(bytes),
+        None => Reply::err(WRONGTYPE),
+    }
+}
+
+/// Turns the `EX`, `PX`, `EXAT` or `PXAT` option into a unix time in milliseconds
+fn parse_expiry(unit: &str, time: &str, cmd: &str) -> Result<u64, Reply> {
+    let invalid = || Reply::err(format!("ERR invalid expire time in '{cmd}' command"));
+    let time = parse_int(time)?;
+    if time <= 0 {
+        return Err(invalid());
+    }
+
+    let time = time as u64;
+    match unit {
+        "ex" => time
+            .checked_mul(1000)
+            .and_then(|ms| ms.checked_add(now_ms())),
+        "px" => time.checked_add(now_ms()),
+        "exat" => time.checked_mul(1000),
+        _ => Some(time),
+    }
+    .ok_or_else(invalid)
+}
+
+/// Handles `INCR key`
+pub fn incr(db: &mut Db, args: &[String]) -> Reply {
+    match args {
+        [key] => incr_by(db, key, 1),
+        _ => wrong_arity("incr"),
+    }
+}
+
+/// Handles `DECR key`
+pub fn decr(db: &mut Db, args: &[String]) -> Reply {
+    match args {
+        [key] => incr_by(db, key, -1),
+        _ => wrong_arity("decr"),
+    }
+}
+
+/// Handles `INCRBY key increment`
+pub fn incrby(db: &mut Db, args: &[String]) -> Reply {
+    let [key, delta] = args else {
+        return wrong_arity("incrby");
+    };
+    match parse_int(delta) {
+        Ok(delta) => incr_by(db, key, delta),
+        Err(e) => e,
+    }
+}
+
+/// Handles `DECRBY key decrement`
+pub fn decrby(db: &mut Db, args: &[String]) -> Reply {
+    let [key, delta] = args else {
+        return wrong_arity("decrby");
+    };
+    match parse_int(delta).map(i64::checked_neg) {
+        Ok(Some(delta)) => incr_by(db, key, delta),
+        Ok(None) => Reply::err("ERR decrement would overflow"),
+        Err(e) => e,
+    }
+}
+
+/// Handles `INCRBYFLOAT key increment`, replying with the new value as a string
+pub fn incrbyfloat(db: &mut Db, args: &[String]) -> Reply {
+    let [key, delta] = args else {
+        return wrong_arity("incrbyfloat");
+    };
+    let delta = match parse_float(delta) {
+        Ok(d) => d,
+        Err(e) => return e,
+    };
+
+    let current = match db.get_mut(key) {
+        Some(obj) => match &obj.value {
+            Value::Int(n) => *n as f64,
+            Value::Str(s) => match std::str::from_utf8(s).map(parse_float) {
+                Ok(Ok(n)) => n,
+                _ => return Reply::err(NOT_A_FLOAT),
+            },
+            _ => return Reply::err(WRONGTYPE),
+        },
+        None => 0.0,
+    };
+
+    let new = current + delta;
+    if !new.is_finite() {
+        return Reply::err("ERR increment would produce NaN or Infinity");
+    }
+
+    let new = Value::from(new.to_string());
+    let reply = string_reply(&new);
+    if let Some(mut obj) = db.get_mut(key) {
+        obj.value = new;
+    } else {
+        db.insert(key, Object::new(new));
+    }
+    reply
+}
+
+/// Adds `delta` to the integer stored at `key`, which counts as 0 if it doesn't exist
+fn incr_by(db: &mut Db, key: &str, delta: i64) -> Reply {
+    let Some(mut obj) = db.get_mut(key) else {
+        db.insert(key, Object::new(delta));
+        return Reply::Int(delta);
+    };
+
+    if !obj.value.is_string() {
+        return Reply::err(WRONGTYPE);
+    }
+    let Some(current) = obj.value.as_int() else {
+        return Reply::err(NOT_AN_INTEGER);
+    };
+    match current.checked_add(delta) {
+        Some(new) => {
+            obj.value = Value::Int(new);
+            Reply::Int(new)
+        }
+        None => Reply::err("ERR increment or decrement would overflow"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::commands::args;
+
+    #[test]
+    fn counters() {
+        let mut db = Db::default();
+        assert_eq!(incr(&mut db, &args(&["n"])), Reply::Int(1));
+        assert_eq!(incrby(&mut db, &args(&["n", "41"])), Reply::Int(42));
+        assert_eq!(decrby(&mut db, &args(&["n", "50"])), Reply::Int(-8));
+        assert_eq!(db.get("n").unwrap().value, Value::Int(-8));
+
+        db.insert("s", Object::new("10"));
+        assert_eq!(decr(&mut db, &args(&["s"])), Reply::Int(9));
+
+        assert_eq!(
+            incrbyfloat(&mut db, &args(&["s", "0.5"])),
+            Reply::str("9.5")
+        );
+        assert_eq!(incrbyfloat(&mut db, &args(&["s", "0.5"])), Reply::str("10"));
+        assert_eq!(db.get("s").unwrap().value, Value::Int(10));
+    }
+
+    #[test]
+    fn set_options() {
+        let mut db = Db::default();
+        assert_eq!(set(&mut db, &args(&["k", "a", "XX"])), Reply::Nil);
+        assert_eq!(set(&mut db, &args(&["k", "a", "NX", "GET"])), Reply::Nil);
+        assert_eq!(set(&mut db, &args(&["k", "b", "NX"])), Reply::Nil);
+        assert_eq!(
+            set(&mut db, &args(&["k", "b", "XX", "GET"])),
+            Reply::str("a")
+        );
+
+        set(&mut db, &args(&["k", "c", "EX", "100"]));
+        let ttl = db.get("k").unwrap().expire_at;
+        assert!(ttl.is_some());
+        set(&mut db, &args(&["k", "d", "KEEPTTL"]));
+        assert_eq!(db.get("k").unwrap().expire_at, ttl);
+        set(&mut db, &args(&["k", "e"]));
+        assert_eq!(db.get("k").unwrap().expire_at, None);
+
+        assert_eq!(
+            set(&mut db, &args(&["k", "v", "NX", "XX"])),
+            Reply::err(SYNTAX_ERROR)
+        );
+        assert_eq!(
+            set(&mut db, &args(&["k", "v", "EX", "10", "KEEPTTL"])),
+            Reply::err(SYNTAX_ERROR)
+        );
+        assert!(matches!(
+            set(&mut db, &args(&["k", "v", "EX", "0"])),
+            Reply::Err(_)
+        ));
+    }
+
+    #[test]
+    fn expired_keys_are_gone() {
+        let mut db = Db::default();
+        set(&mut db, &args(&["k", "v", "PXAT", "1"]));
+        assert_eq!(getex(&mut db, &args(&["k"])), Reply::Nil);
+        assert_eq!(db.size(), 0);
+    }
+
+    #[test]
+    fn ranges() {
+        let mut db = Db::default();
+        assert_eq!(append(&mut db, &args(&["k", "Hello"])), Reply::Int(5));
+        assert_eq!(append(&mut db, &args(&["k", " World"])), Reply::Int(11));
+        assert_eq!(
+            getrange(&mut db, &args(&["k", "0", "4"])),
+            Reply::str("Hello")
+        );
+        assert_eq!(
+            getrange(&mut db, &args(&["k", "-5", "-1"])),
+            Reply::str("World")
+        );
+        assert_eq!(getrange(&mut db, &args(&["k", "5", "1"])), Reply::str(""));
+        assert_eq!(
+            getrange(&mut db, &args(&["k", "0", "100"])),
+            Reply::str("Hello World")
+        );
+
+        assert_eq!(
+            setrange(&mut db, &args(&["k", "6", "Redis"])),
+            Reply::Int(11)
+        );
+        assert_eq!(
+            getrange(&mut db, &args(&["k", "0", "-1"])),
+            Reply::str("Hello Redis")
+        );
+        assert_eq!(setrange(&mut db, &args(&["p", "2", "x"])), Reply::Int(3));
+        assert_eq!(getdel(&mut db, &args(&["p"])), Reply::str(b"\0\0x"));
+        assert_eq!(strlen(&mut db, &args(&["p"])), Reply::Int(0));
+
+        let too_long = Reply::err("ERR string exceeds maximum allowed size");
+        let max = u64::MAX.to_string();
+        assert_eq!(setrange(&mut db, &args(&["k", &max, "x"])), too_long);
+        assert_eq!(setrange(&mut db, &args(&["p", &max, "x"])), too_long);
+        assert_eq!(setrange(&mut db, &args(&["k", "536870912", "x"])), too_long);
+        assert_eq!(strlen(&mut db, &args(&["p"])), Reply::Int(0));
+
+        db.insert("n", Object::new(12));
+        assert_eq!(append(&mut db, &args(&["n", "3"])), Reply::Int(3));
+        assert_eq!(getset(&mut db, &args(&["n", "x"])), Reply::str("123"));
+    }
+
+    #[test]
+    fn multi_key() {
+        let mut db = Db::default();
+        assert_eq!(mset(&mut db, &args(&["a", "1", "b", "2"])), Reply::ok());
+        assert_eq!(
+            mget(&mut db, &args(&["a", "x", "b"])),
+            Reply::Arr(vec![Reply::str("1"), Reply::Nil, Reply::str("2")])
+        );
+        assert_eq!(msetnx(&mut db, &args(&["c", "3", "a", "4"])), Reply::Int(0));
+        assert_eq!(db.get("c").map(|o| o.value.clone()), None);
+        assert_eq!(msetnx(&mut db, &args(&["c", "3", "d", "4"])), Reply::Int(1));
+        assert!(matches!(mset(&mut db, &args(&["a"])), Reply::Err(_)));
+    }
+
+    #[test]
+    fn errors() {
+        let mut db = Db::default();
+        db.insert("s", Object::new("abc"));
+        db.insert("max", Object::new(i64::MAX));
+
+        assert_eq!(incr(&mut db, &args(&["s"])), Reply::err(NOT_AN_INTEGER));
+        assert_eq!(
+            incrbyfloat(&mut db, &args(&["s", "1"])),
+            Reply::err(NOT_A_FLOAT)
+        );
+        assert_eq!(
+            incrby(&mut db, &args(&["n", "x"])),
+            Reply::err(NOT_AN_INTEGER)
+        );
+        assert!(matches!(incr(&mut db, &args(&["max"])), Reply::Err(_)));
+        assert!(matches!(
+            decrby(&mut db, &args(&["n", &i64::MIN.to_string()])),
+            Reply::Err(_)
+        ));
+        assert!(matches!(incr(&mut db, &args(&[])), Reply::Err(_)));
+        assert_eq!(db.get("max").unwrap().value, Value::Int(i64::MAX));
+    }
+}
@@ -0,0 +1,165 @@
+use std::{
+    fs,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
+
+use thiserror::Error;
+
+use crate::{
+    eviction::{self, MaxmemoryPolicy},
+    storage,
+};
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("unknown config option '{0}'")]
+    UnknownOption(String),
+
+    #[error("missing value for config option '{0}'")]
+    MissingValue(String),
+
+    #[error("invalid value '{value}' for config option '{name}'")]
+    InvalidValue { name: String, value: String },
+
+    #[error("couldn't read config file '{path}': {source}")]
+    Io {
+        path: String,
+        source: std::io::Error,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Addresses of the tcp request listeners
+    pub bind: Vec<SocketAddr>,
+    /// Path of the unix socket request listener, disabled when `None`
+    pub unixsocket: Option<PathBuf>,
+    /// Permissions of the unix socket file, as octal digits
+    pub unixsocketperm: Option<u32>,
+    /// Address of the prometheus `/metrics` listener, disabled when `None`
+    pub metrics_bind: Option<SocketAddr>,
+    /// Commands slower than this many microseconds end up in the slowlog,
+    /// negative values disable it
+    pub slowlog_log_slower_than: i64,
+    /// Max number of entries kept in the slowlog
+    pub slowlog_max_len: usize,
+    /// Monitors with more pending output than this many bytes get disconnected
+    pub monitor_output_buffer_limit: usize,
+    /// Password of the default user
+    pub requirepass: Option<String>,
+    /// ACL user definitions, as `<name> <rule> <rule> ...`
+    pub users: Vec<String>,
+    /// Memory limit of the keyspace in bytes, 0 means no limit
+    pub maxmemory: usize,
+    /// What to do once `maxmemory` is reached
+    pub maxmemory_policy: MaxmemoryPolicy,
+    /// Number of keys sampled per evicted key
+    pub maxmemory_samples: usize,
+    /// Number of databases clients can `SELECT`
+    pub databases: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            bind: vec!["127.0.0.1:8080".parse().unwrap()],
+            unixsocket: None,
+            unixsocketperm: None,
+            metrics_bind: None,
+            slowlog_log_slower_than: 10_000,
+            slowlog_max_len: 128,
+            monitor_output_buffer_limit: 32 << 20,
+            requirepass: None,
+            users: Vec::new(),
+            maxmemory: 0,
+            maxmemory_policy: MaxmemoryPolicy::NoEviction,
+            maxmemory_samples: 5,
+            databases: storage::DEFAULT_DATABASES,
+        }
+    }
+}
+
+impl Config {
+    /// Builds a config from `--name value` pairs, starting from the defaults
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self, ConfigError> {
+        let mut config = Self::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let name = arg
+                .strip_prefix("--")
+                .ok_or_else(|| ConfigError::UnknownOption(arg.clone()))?;
+            let value = args
+                .next()
+                .ok_or_else(|| ConfigError::MissingValue(name.into()))?;
+            config.set(name, &value)?;
+        }
+
+        Ok(config)
+    }
+
+    /// Applies every `name value` line of the file at `path`,
+    /// skipping empty lines and `#` comments
+    pub fn load_file(&mut self, path: impl AsRef<Path>) -> Result<(), ConfigError> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path).map_err(|source| ConfigError::Io {
+            path: path.display().to_string(),
+            source,
+        })?;
+
+        for line in contents.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, value) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let value = value.trim();
+            if value.is_empty() {
+                return Err(ConfigError::MissingValue(name.into()));
+            }
+            self.set(name, value)?;
+        }
+        Ok(())
+    }
+
+    /// Sets a single option by name
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), ConfigError> {
+        let invalid = || ConfigError::InvalidValue {
+            name: name.into(),
+            value: value.into(),
+        };
+
+        match name {
+            "bind" => {
+                self.bind = value
+                    .split_whitespace()
+                    .map(|addr| addr.parse().map_err(|_| invalid()))
+                    .collect::<Result<_, _>>()?
+            }
+            "unixsocket" => self.unixsocket = Some(value.into()),
+            "unixsocketperm" => {
+                self.unixsocketperm = Some(u32::from_str_radix(value, 8).map_err(|_| invalid())?)
+            }
+            "metrics-bind" => self.metrics_bind = Some(value.parse().map_err(|_| invalid())?),
+            "slowlog-log-slower-than" => {
+                self.slowlog_log_slower_than = value.parse().map_err(|_| invalid())?
+            }
+            "slowlog-max-len" => self.slowlog_max_len = value.parse().map_err(|_| invalid())?,
+            "monitor-output-buffer-limit" => {
+                self.monitor_output_buffer_limit = value.parse().map_err(|_| invalid())?
+            }
+            "maxmemory" => self.maxmemory = eviction::parse_memory(value).ok_or_else(invalid)?,
+            "maxmemory-policy" => self.maxmemory_policy = value.parse().map_err(|_| invalid())?,
+            "maxmemory-samples" => self.maxmemory_samples = value.parse().map_err(|_| invalid())?,
+            "databases" => match value.parse() {
+                Ok(n) if n > 0 => self.databases = n,
+                _ => return Err(invalid()),
+            },
+            "requirepass" => self.requirepass = Some(value.into()),
+            "user" => self.users.push(value.into()),
+            "config" => self.load_file(value)?,
+            _ => return Err(ConfigError::UnknownOption(name.into())),
+        }
+        Ok(())
+    }
+}
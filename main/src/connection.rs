@@ -1,13 +1,10 @@
-use crate::{protocol, storage::MAP2, util::would_block, SERVER};
+use crate::{net::{Listener, Stream}, protocol::{self, Reply}, server::ServerState, session::Session, util::would_block, METRICS};
 use log::{error, info, trace};
-use mio::{
-    Interest, Token,
-    net::{TcpListener, TcpStream},
-};
+use mio::{Interest, Token};
 use std::{
     collections::HashMap,
     io::{self, Read, Write},
-    usize,
+    time::Instant,
 };
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
@@ -19,21 +16,23 @@ pub enum ConnectionState {
 
 #[derive(Debug)]
 pub struct Connection {
-    pub stream: TcpStream,
+    pub stream: Stream,
     pub token: mio::Token,
     state: ConnectionState,
     pub incoming: Vec<u8>,
     pub outgoing: Vec<u8>,
+    pub session: Session,
 }
 
 impl Connection {
-    pub fn new(stream: TcpStream, token: mio::Token) -> Self {
+    pub fn new(stream: Stream, token: mio::Token, session: Session) -> Self {
         Self {
             stream,
             token,
             state: ConnectionState::WantRead,
             incoming: Vec::new(),
             outgoing: Vec::new(),
+            session,
         }
     }
 
@@ -51,7 +50,7 @@ impl Connection {
         matches!(self.state, ConnectionState::WantClose)
     }
 
-    pub fn on_read(&mut self) -> io::Result<()> {
+    pub fn on_read(&mut self, state: &mut ServerState) -> io::Result<()> {
         assert_eq!(
             ConnectionState::WantRead,
             self.state,
@@ -81,12 +80,16 @@ impl Connection {
         }
 
         info!("read {} bytes", self.incoming.len());
+        self.process_incoming(state)
+    }
 
+    /// Handles the buffered requests, stopping early if one of them blocks
+    fn process_incoming(&mut self, state: &mut ServerState) -> io::Result<()> {
         let mut last_state;
         loop {
             // while we successfuly parse requests
             // where last_state = WantWrite == success
-            last_state = self.try_one_request();
+            last_state = self.try_one_request(state);
             if last_state != ConnectionState::WantWrite {
                 break;
             }
@@ -96,14 +99,15 @@ impl Connection {
             // we have at least one request ready to send
             // this way we skip one syscall to poll in the main loop
             self.state = ConnectionState::WantWrite;
-            return self.on_write();
+            return self.on_write(state);
         } else {
             self.state = last_state;
         }
+        self.update_client_info(state);
         Ok(())
     }
 
-    pub fn on_write(&mut self) -> io::Result<()> {
+    pub fn on_write(&mut self, state: &mut ServerState) -> io::Result<()> {
         assert_eq!(
             ConnectionState::WantWrite,
             self.state,
@@ -136,7 +140,34 @@ impl Connection {
         } else {
             self.state = ConnectionState::WantWrite;
         }
+        self.update_client_info(state);
+
+        Ok(())
+    }
+
+    fn update_client_info(&self, state: &mut ServerState) {
+        state.clients.update_buffers(self.session.id, self.incoming.len(), self.outgoing.len());
+    }
+
+    /// Sends the reply of the blocking command the client waited in,
+    /// then carries on with the requests pipelined behind it
+    pub fn unblock(&mut self, state: &mut ServerState, reply: &Reply) -> io::Result<()> {
+        self.session.blocked = false;
+        protocol::request::serialize_reply(reply, self.session.format, &mut self.outgoing);
+        if self.want_close() {
+            return Ok(());
+        }
+        self.process_incoming(state)
+    }
 
+    /// Queues already encoded frames for sending, flushing them right away
+    /// if the connection was idle
+    pub fn send(&mut self, state: &mut ServerState, frames: &[u8]) -> io::Result<()> {
+        self.outgoing.extend_from_slice(frames);
+        if self.want_read() && !self.outgoing.is_empty() {
+            self.state = ConnectionState::WantWrite;
+            return self.on_write(state);
+        }
         Ok(())
     }
 
@@ -149,11 +180,11 @@ impl Connection {
     ///
     /// -   **WantClose:** Someting seriously went wrong - likely some protocol error - and the main loop should
     ///     close down the connection
-    fn try_one_request(&mut self) -> ConnectionState {
+    fn try_one_request(&mut self, state: &mut ServerState) -> ConnectionState {
         use protocol::ParseError::*;
         
-        // dip early
-        if self.incoming.is_empty() {
+        // dip early, blocked clients keep their requests buffered
+        if self.incoming.is_empty() || self.session.blocked {
             return ConnectionState::WantRead
         }
 
@@ -168,19 +199,15 @@ impl Connection {
 
         // consume requests
         self.incoming.drain(..offset);
-        protocol::request::handle_and_encode_request(cmds, &mut self.outgoing);
+        protocol::request::handle_and_encode_request(state, cmds, &mut self.session, &mut self.outgoing);
 
-        #[allow(static_mut_refs)]
-        unsafe {
-            dbg!(&MAP2.get());
-        }
-        
         ConnectionState::WantWrite
     }
 }
 
 pub struct ConnectionManager {
     pub map: HashMap<Token, Connection>,
+    listeners: HashMap<Token, Listener>,
     token_gen: TokenGen,
 }
 
@@ -194,36 +221,158 @@ impl ConnectionManager {
     pub fn new() -> Self {
         Self {
             map: HashMap::new(),
+            listeners: HashMap::new(),
             token_gen: TokenGen::new(),
         }
     }
 
-    pub fn handle_accept(&mut self, server: &TcpListener, poll: &mut mio::Poll) -> io::Result<()> {
-        let stream = match server.accept() {
-            Ok((s, _)) => s,
-            Err(ref e) if would_block(e) => return Ok(()),
-            Err(e) => return Err(e),
+    /// Registers `listener` under a fresh token, connections accepted
+    /// through it are handled like any other
+    pub fn add_listener(&mut self, mut listener: Listener, poll: &mio::Poll) -> io::Result<Token> {
+        let token = self.token_gen.next();
+        poll.registry()
+            .register(&mut listener, token, Interest::READABLE)?;
+        self.listeners.insert(token, listener);
+        Ok(token)
+    }
+
+    pub fn is_listener(&self, token: &Token) -> bool {
+        self.listeners.contains_key(token)
+    }
+
+    pub fn listeners(&self) -> impl Iterator<Item = &Listener> {
+        self.listeners.values()
+    }
+
+    /// Accepts every pending connection on the listener registered under `token`,
+    /// since it only reports readiness again once it was drained
+    pub fn handle_accept(&mut self, state: &mut ServerState, token: Token, poll: &mut mio::Poll) -> io::Result<()> {
+        let Some(server) = self.listeners.get(&token) else {
+            return Ok(());
         };
-        trace!("new connection from {}", stream.peer_addr()?);
 
-        let token = self.token_gen.next();
-        let mut conn = Connection::new(stream, token);
+        loop {
+            let (stream, addr) = match server.accept() {
+                Ok(v) => v,
+                Err(ref e) if would_block(e) => return Ok(()),
+                Err(e) => return Err(e),
+            };
+            trace!("new connection from {}", addr);
 
-        poll.registry().register(
-            &mut conn.stream,
-            token,
-            Interest::READABLE | Interest::WRITABLE,
-        )?;
+            let token = self.token_gen.next();
+            let mut session = Session::new(state.clients.register(addr.clone()), addr);
+            state.acl.authenticate_default(&mut session);
+            let mut conn = Connection::new(stream, token, session);
 
-        self.map.insert(token, conn);
-        Ok(())
+            poll.registry().register(
+                &mut conn.stream,
+                token,
+                Interest::READABLE | Interest::WRITABLE,
+            )?;
+
+            self.map.insert(token, conn);
+            state.metrics.connection_opened();
+        }
     }
 
-    pub fn handle_close(&mut self, poll: &mio::Poll, token: mio::Token) -> io::Result<()> {
-        let mut conn = self.map.remove(&token).unwrap();
+    pub fn handle_close(&mut self, state: &mut ServerState, poll: &mio::Poll, token: mio::Token) -> io::Result<()> {
+        let Some(mut conn) = self.map.remove(&token) else {
+            return Ok(());
+        };
+        state.monitors.unsubscribe(&mut conn.session);
+        state.blocked.unblock(&mut state.dbs, &mut conn.session);
+        state.clients.unregister(conn.session.id);
+        state.metrics.connection_closed();
         poll.registry().deregister(&mut conn.stream)
     }
 
+    /// Copies the commands fed since the last call to every monitor,
+    /// closing the ones whose output buffer grew past the limit
+    pub fn flush_monitors(&mut self, state: &mut ServerState, poll: &mio::Poll) -> io::Result<()> {
+        let feed = state.monitors.drain();
+        if feed.is_empty() {
+            return Ok(());
+        }
+
+        let limit = state.monitors.output_buffer_limit();
+        let mut closed = Vec::new();
+
+        for (token, conn) in self.map.iter_mut() {
+            if !conn.session.monitor || conn.want_close() {
+                continue;
+            }
+
+            let frames = feed.frames(conn.session.format);
+            if conn.outgoing.len() + frames.len() > limit {
+                info!("closing monitor {}, output buffer limit reached", conn.session.addr);
+                conn.close();
+            } else if let Err(e) = conn.send(state, frames) {
+                info!("{e}");
+            }
+
+            if conn.want_close() {
+                closed.push(*token);
+            }
+        }
+
+        for token in closed {
+            self.handle_close(state, poll, token)?;
+        }
+        Ok(())
+    }
+
+    /// Replies to the blocked clients that got served or timed out,
+    /// until serving them doesn't make more keys ready
+    pub fn serve_blocked(&mut self, state: &mut ServerState, poll: &mio::Poll) -> io::Result<()> {
+        loop {
+            let mut replies = state.blocked.serve_ready(&mut state.dbs, &state.modules);
+            replies.extend(
+                state.blocked.take_timed_out(&mut state.dbs, Instant::now())
+                    .into_iter()
+                    .map(|id| (id, Reply::Nil)),
+            );
+            if replies.is_empty() {
+                return Ok(());
+            }
+
+            for (id, reply) in replies {
+                let Some((&token, conn)) =
+                    self.map.iter_mut().find(|(_, conn)| conn.session.id == id)
+                else {
+                    continue;
+                };
+
+                if let Err(e) = conn.unblock(state, &reply) {
+                    info!("{e}");
+                }
+                if conn.want_close() {
+                    self.handle_close(state, poll, token)?;
+                }
+            }
+        }
+    }
+
+    /// Closes every connection killed by `CLIENT KILL` since the last call
+    pub fn reap_killed(&mut self, state: &mut ServerState, poll: &mio::Poll) -> io::Result<()> {
+        for id in state.clients.take_killed() {
+            let token = self
+                .map
+                .iter()
+                .find_map(|(token, conn)| (conn.session.id == id).then_some(*token));
+
+            if let Some(token) = token {
+                trace!("killed client {id}");
+                self.handle_close(state, poll, token)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Hands out a fresh token, for registering sources outside of the manager
+    pub fn next_token(&mut self) -> mio::Token {
+        self.token_gen.next()
+    }
+
     pub fn get_connection_mut(&mut self, t: &Token) -> Option<&mut Connection> {
         self.map.get_mut(t)
     }
@@ -235,7 +384,7 @@ struct TokenGen {
 
 impl TokenGen {
     pub const fn new() -> Self {
-        Self { next: SERVER.0 + 1 }
+        Self { next: METRICS.0 + 1 }
     }
     pub fn next(&mut self) -> mio::Token {
         let t = mio::Token(self.next);
@@ -0,0 +1,309 @@
+use std::{fmt, str::FromStr};
+
+use log::trace;
+
+use collections::Entry;
+use rand::Rng;
+
+use crate::{
+    command_table::{self, CommandSpec},
+    config::Config,
+    protocol::Reply,
+    storage::{Db, Object},
+};
+
+/// Number of sampling rounds `volatile-ttl` tries before giving up,
+/// when the samples don't contain any key with an expiry
+const VOLATILE_ATTEMPTS: usize = 16;
+
+/// Write commands that can only free memory, so they are allowed while out of memory
+const SHRINKING_COMMANDS: &[&str] = &[
+    "del", "getdel", "lpop", "rpop", "ltrim", "blpop", "brpop", "hdel", "srem", "spop", "xdel",
+    "flushdb", "flushall", "swapdb", "move", "unlink", "rename", "renamenx", "xtrim",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MaxmemoryPolicy {
+    /// Reject writes once the limit is reached
+    #[default]
+    NoEviction,
+    /// Evict the least recently used keys
+    AllKeysLru,
+    /// Evict the least frequently used keys
+    AllKeysLfu,
+    /// Evict random keys
+    AllKeysRandom,
+    /// Evict the keys with an expiry that is the closest
+    VolatileTtl,
+}
+
+impl FromStr for MaxmemoryPolicy {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.to_ascii_lowercase().as_str() {
+            "noeviction" => Self::NoEviction,
+            "allkeys-lru" => Self::AllKeysLru,
+            "allkeys-lfu" => Self::AllKeysLfu,
+            "allkeys-random" => Self::AllKeysRandom,
+            "volatile-ttl" => Self::VolatileTtl,
+            _ => return Err(()),
+        })
+    }
+}
+
+impl fmt::Display for MaxmemoryPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::NoEviction => "noeviction",
+            Self::AllKeysLru => "allkeys-lru",
+            Self::AllKeysLfu => "allkeys-lfu",
+            Self::AllKeysRandom => "allkeys-random",
+            Self::VolatileTtl => "volatile-ttl",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Settings {
+    /// Memory limit in bytes, 0 means no limit
+    maxmemory: usize,
+    policy: MaxmemoryPolicy,
+    /// Number of keys sampled to pick one victim
+    samples: usize,
+}
+
+/// Keeps the databases of a server under `maxmemory`
+#[derive(Debug)]
+pub struct Eviction {
+    settings: Settings,
+    evicted_keys: u64,
+}
+
+impl Eviction {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            settings: Settings {
+                maxmemory: config.maxmemory,
+                policy: config.maxmemory_policy,
+                samples: config.maxmemory_samples.max(1),
+            },
+            evicted_keys: 0,
+        }
+    }
+
+    pub fn maxmemory(&self) -> usize {
+        self.settings.maxmemory
+    }
+
+    pub fn evicted_keys(&self) -> u64 {
+        self.evicted_keys
+    }
+
+    /// Evicts keys until the databases fit into `maxmemory` again.
+    /// Fails with the OOM reply if that's not possible and `cmd` could grow the dataset.
+    pub fn check(&mut self, dbs: &mut [Db], cmd: &CommandSpec) -> Result<(), Reply> {
+        let maxmemory = self.settings.maxmemory;
+        if maxmemory == 0 || used_memory(dbs) <= maxmemory {
+            return Ok(());
+        }
+
+        let freed = self.free_memory(dbs);
+        let denyoom = cmd.has(command_table::WRITE) && !SHRINKING_COMMANDS.contains(&cmd.name);
+
+        if !freed && denyoom {
+            return Err(Reply::err(
+                "OOM command not allowed when used memory > 'maxmemory'.",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Returns whether memory usage got under the limit
+    fn free_memory(&mut self, dbs: &mut [Db]) -> bool {
+        let settings = self.settings;
+        let mut rng = rand::rng();
+
+        while used_memory(dbs) > settings.maxmemory {
+            let victim = match settings.policy {
+                MaxmemoryPolicy::NoEviction => None,
+                MaxmemoryPolicy::AllKeysRandom => random_key(dbs, &mut rng),
+                MaxmemoryPolicy::AllKeysLru => sample(dbs, settings.samples, &mut rng)
+                    .into_iter()
+                    .max_by_key(|(_, e)| e.value().idle_time())
+                    .map(|(db, e)| (db, e.key().to_string())),
+                MaxmemoryPolicy::AllKeysLfu => sample(dbs, settings.samples, &mut rng)
+                    .into_iter()
+                    .min_by_key(|(_, e)| (e.value().lfu(), u32::MAX - e.value().idle_time()))
+                    .map(|(db, e)| (db, e.key().to_string())),
+                MaxmemoryPolicy::VolatileTtl => (0..VOLATILE_ATTEMPTS).find_map(|_| {
+                    sample(dbs, settings.samples, &mut rng)
+                        .into_iter()
+                        .filter_map(|(db, e)| Some((e.value().expire_at?, db, e.key())))
+                        .min()
+                        .map(|(_, db, key)| (db, key.to_string()))
+                }),
+            };
+
+            let Some((db, key)) = victim else {
+                return false;
+            };
+            trace!(target: "eviction", "evicting {key} of db {db}");
+            dbs[db].remove(&key);
+            self.evicted_keys += 1;
+        }
+        true
+    }
+}
+
+fn used_memory(dbs: &[Db]) -> usize {
+    dbs.iter().map(Db::used_memory).sum()
+}
+
+/// Samples `count` keys of every database, along with the index of their database
+fn sample<'a>(dbs: &'a [Db], count: usize, rng: &mut impl Rng) -> Vec<(usize, &'a Entry<Object>)> {
+    let mut samples = Vec::new();
+    for db in dbs {
+        samples.extend(
+            db.dict()
+                .sample(count, rng)
+                .into_iter()
+                .map(|e| (db.index(), e)),
+        );
+    }
+    samples
+}
+
+/// Picks a key uniformly among all databases, a database is picked
+/// by its number of keys first
+fn random_key(dbs: &[Db], rng: &mut impl Rng) -> Option<(usize, String)> {
+    let total: usize = dbs.iter().map(Db::size).sum();
+    if total == 0 {
+        return None;
+    }
+    let mut n = rng.random_range(0..total);
+    let db = dbs.iter().find(|db| {
+        let found = n < db.size();
+        n = n.saturating_sub(db.size());
+        found
+    })?;
+    let key = db.dict().random_entry(rng)?.key().to_string();
+    Some((db.index(), key))
+}
+
+/// Parses a byte count with an optional `kb`, `mb` or `gb` suffix
+pub fn parse_memory(s: &str) -> Option<usize> {
+    let s = s.to_ascii_lowercase();
+    let (digits, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => s.split_at(i),
+        None => (s.as_str(), ""),
+    };
+    let n: usize = digits.parse().ok()?;
+    let unit = match unit {
+        "" | "b" => 1,
+        "k" => 1000,
+        "kb" => 1024,
+        "m" => 1000 * 1000,
+        "mb" => 1024 * 1024,
+        "g" => 1000 * 1000 * 1000,
+        "gb" => 1024 * 1024 * 1024,
+        _ => return None,
+    };
+    n.checked_mul(unit)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn filled(n: usize) -> Db {
+        let mut db = Db::default();
+        for i in 0..n {
+            db.insert(&format!("key:{i}"), Object::new("x".repeat(100)));
+        }
+        db
+    }
+
+    #[test]
+    fn evicts_until_under_the_limit() {
+        for policy in [
+            MaxmemoryPolicy::AllKeysLru,
+            MaxmemoryPolicy::AllKeysLfu,
+            MaxmemoryPolicy::AllKeysRandom,
+        ] {
+            let mut db = filled(100);
+            let maxmemory = db.used_memory() / 2;
+            let settings = Settings {
+                maxmemory,
+                policy,
+                samples: 5,
+            };
+
+            assert!(
+                Eviction {
+                    settings,
+                    evicted_keys: 0
+                }
+                .free_memory(std::slice::from_mut(&mut db)),
+                "{policy}"
+            );
+            assert!(db.used_memory() <= maxmemory);
+            assert!(db.size() < 100);
+        }
+    }
+
+    #[test]
+    fn evicts_from_every_database() {
+        let mut dbs = [filled(50), Db::new(1)];
+        for i in 0..50 {
+            dbs[1].insert(&format!("key:{i}"), Object::new("x".repeat(100)));
+        }
+        let maxmemory = used_memory(&dbs) / 4;
+        let settings = Settings {
+            maxmemory,
+            policy: MaxmemoryPolicy::AllKeysRandom,
+            samples: 5,
+        };
+
+        assert!(
+            Eviction {
+                settings,
+                evicted_keys: 0
+            }
+            .free_memory(&mut dbs)
+        );
+        assert!(used_memory(&dbs) <= maxmemory);
+        assert!(dbs[0].size() < 50 && dbs[1].size() < 50);
+    }
+
+    #[test]
+    fn noeviction_and_volatile_without_expiry_fail() {
+        for policy in [MaxmemoryPolicy::NoEviction, MaxmemoryPolicy::VolatileTtl] {
+            let mut db = filled(10);
+            let settings = Settings {
+                maxmemory: 1,
+                policy,
+                samples: 5,
+            };
+
+            assert!(
+                !Eviction {
+                    settings,
+                    evicted_keys: 0
+                }
+                .free_memory(std::slice::from_mut(&mut db))
+            );
+            assert_eq!(db.size(), 10);
+        }
+    }
+
+    #[test]
+    fn memory_units() {
+        assert_eq!(parse_memory("100"), Some(100));
+        assert_eq!(parse_memory("1kb"), Some(1024));
+        assert_eq!(parse_memory("2MB"), Some(2 << 20));
+        assert_eq!(parse_memory("1gb"), Some(1 << 30));
+        assert_eq!(parse_memory("1tb"), None);
+        assert_eq!(parse_memory("mb"), None);
+    }
+}
@@ -0,0 +1,235 @@
+use std::{
+    collections::HashMap,
+    io::{self, Read, Write},
+    net::SocketAddr,
+};
+
+use log::{info, trace};
+use mio::{
+    Interest, Poll, Token,
+    event::Event,
+    net::{TcpListener, TcpStream},
+};
+
+use crate::{server::ServerState, util::would_block};
+
+/// Requests bigger than this are dropped without a response
+const MAX_REQUEST_SIZE: usize = 8 * 1024;
+
+/// Minimal HTTP/1.x listener serving `GET /metrics`,
+/// driven by the same poll loop as the main listener.
+///
+/// Every connection is answered once and then closed.
+pub struct MetricsExporter {
+    listener: TcpListener,
+    conns: HashMap<Token, HttpConnection>,
+}
+
+struct HttpConnection {
+    stream: TcpStream,
+    incoming: Vec<u8>,
+    outgoing: Vec<u8>,
+}
+
+impl MetricsExporter {
+    pub fn bind(addr: SocketAddr) -> io::Result<Self> {
+        Ok(Self {
+            listener: TcpListener::bind(addr)?,
+            conns: HashMap::new(),
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub fn register(&mut self, poll: &Poll, token: Token) -> io::Result<()> {
+        poll.registry()
+            .register(&mut self.listener, token, Interest::READABLE)
+    }
+
+    /// Whether `token` belongs to one of the exporter's connections
+    pub fn owns(&self, token: &Token) -> bool {
+        self.conns.contains_key(token)
+    }
+
+    /// Accepts every pending connection, using `next_token` to register them
+    pub fn handle_accept(
+        &mut self,
+        poll: &Poll,
+        mut next_token: impl FnMut() -> Token,
+    ) -> io::Result<()> {
+        loop {
+            let mut stream = match self.listener.accept() {
+                Ok((s, _)) => s,
+                Err(ref e) if would_block(e) => return Ok(()),
+                Err(e) => return Err(e),
+            };
+            trace!(target: "metrics", "new connection from {}", stream.peer_addr()?);
+
+            let token = next_token();
+            poll.registry().register(
+                &mut stream,
+                token,
+                Interest::READABLE | Interest::WRITABLE,
+            )?;
+
+            self.conns.insert(
+                token,
+                HttpConnection {
+                    stream,
+                    incoming: Vec::new(),
+                    outgoing: Vec::new(),
+                },
+            );
+        }
+    }
+
+    pub fn handle_event(
+        &mut self,
+        poll: &Poll,
+        event: &Event,
+        state: &ServerState,
+    ) -> io::Result<()> {
+        let token = event.token();
+        let Some(conn) = self.conns.get_mut(&token) else {
+            return Ok(());
+        };
+
+        let done = match conn.on_event(event, state) {
+            Ok(done) => done,
+            Err(e) => {
+                info!(target: "metrics", "{e}");
+                true
+            }
+        };
+
+        if done {
+            let mut conn = self.conns.remove(&token).unwrap();
+            poll.registry().deregister(&mut conn.stream)?;
+        }
+        Ok(())
+    }
+}
+
+impl HttpConnection {
+    /// Returns `true` once the connection should be closed
+    fn on_event(&mut self, event: &Event, state: &ServerState) -> io::Result<bool> {
+        if event.is_readable() && self.outgoing.is_empty() {
+            let mut buf = [0; 1024];
+            loop {
+                match self.stream.read(&mut buf) {
+                    Ok(0) => return Ok(true),
+                    Ok(n) => self.incoming.extend_from_slice(&buf[..n]),
+                    Err(ref e) if would_block(e) => break,
+                    Err(e) => return Err(e),
+                }
+                // don't buffer whatever a client keeps sending
+                if self.incoming.len() > MAX_REQUEST_SIZE {
+                    return Ok(true);
+                }
+            }
+
+            let Some(end) = self.incoming.windows(4).position(|w| w == b"\r\n\r\n") else {
+                return Ok(false);
+            };
+            self.outgoing = respond(&self.incoming[..end], state);
+        }
+
+        while !self.outgoing.is_empty() {
+            match self.stream.write(&self.outgoing) {
+                Ok(0) => return Ok(true),
+                Ok(n) => {
+                    self.outgoing.drain(..n);
+                }
+                Err(ref e) if would_block(e) => return Ok(false),
+                Err(e) => return Err(e),
+            }
+        }
+
+        // everything is flushed once a response was built
+        Ok(!self.incoming.is_empty())
+    }
+}
+
+fn respond(head: &[u8], state: &ServerState) -> Vec<u8> {
+    let head = String::from_utf8_lossy(head);
+    let mut request_line = head.lines().next().unwrap_or_default().split(' ');
+    let method = request_line.next().unwrap_or_default();
+    let path = request_line.next().unwrap_or_default();
+
+    let (status, body) = match (method, path) {
+        ("GET", "/metrics") => ("200 OK", state.metrics.render(&state.dbs, &state.eviction)),
+        ("GET", _) => ("404 Not Found", String::from("not found\n")),
+        _ => (
+            "405 Method Not Allowed",
+            String::from("method not allowed\n"),
+        ),
+    };
+
+    format!(
+        "HTTP/1.1 {status}\r\n\
+         Content-Type: text/plain; version=0.0.4\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\r\n{body}",
+        body.len()
+    )
+    .into_bytes()
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::TcpStream;
+
+    use super::*;
+    use crate::{config::Config, server::Server};
+
+    #[test]
+    fn responds_to_metrics_requests() {
+        let state = ServerState::new(&Config::default()).unwrap();
+        let response = String::from_utf8(respond(b"GET /metrics HTTP/1.1", &state)).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(head.contains(&format!("Content-Length: {}\r\n", body.len())));
+        assert!(body.contains("# TYPE tcpserver_keyspace_keys gauge\n"));
+
+        let status = |head: &[u8]| {
+            let response = respond(head, &state);
+            String::from_utf8(response)
+                .unwrap()
+                .lines()
+                .next()
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(status(b"GET / HTTP/1.1"), "HTTP/1.1 404 Not Found");
+        assert_eq!(
+            status(b"POST /metrics HTTP/1.1"),
+            "HTTP/1.1 405 Method Not Allowed"
+        );
+    }
+
+    #[test]
+    fn serves_metrics_over_http() {
+        let config = Config {
+            metrics_bind: Some("127.0.0.1:0".parse().unwrap()),
+            ..Config::default()
+        };
+        let server = Server::builder()
+            .config(config)
+            .bind("127.0.0.1:0".parse().unwrap())
+            .run()
+            .unwrap();
+
+        let mut stream = TcpStream::connect(server.metrics_addr().unwrap()).unwrap();
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("tcpserver_connected_clients 0\n"));
+
+        server.stop().unwrap();
+    }
+}
@@ -1,15 +1,31 @@
-#![feature(once_cell_get_mut)]
-
+pub mod acl;
+pub mod blocking;
+pub mod cli;
+pub mod client;
+pub mod clients;
+pub mod command_table;
+pub mod commands;
+pub mod config;
 pub mod connection;
+pub mod eviction;
+pub mod exporter;
+pub mod metrics;
+pub mod module;
+pub mod monitor;
+pub mod net;
 pub mod util;
 pub mod protocol;
+pub mod server;
+pub mod session;
+pub mod slowlog;
 pub mod storage;
 
 use core::panic;
-use std::{u32, usize};
 
 use mio::Token;
-pub const SERVER: Token = Token(0);
+/// Wakes the event loop up when the server gets stopped
+pub const WAKER: Token = Token(0);
+pub const METRICS: Token = Token(1);
 
 pub trait Protocol {
     type Frame;
@@ -0,0 +1,288 @@
+use std::{collections::BTreeMap, fmt::Write, time::Duration};
+
+use crate::{eviction::Eviction, storage::Db};
+
+/// Upper bounds (in seconds) of the latency histogram buckets
+pub const LATENCY_BUCKETS: [f64; 14] = [
+    0.00001, 0.000025, 0.00005, 0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05,
+    0.1, 1.0,
+];
+
+#[derive(Debug, Default, Clone)]
+pub struct Histogram {
+    /// Non-cumulative counts, one per `LATENCY_BUCKETS` entry plus `+Inf`
+    buckets: [u64; LATENCY_BUCKETS.len() + 1],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    pub fn observe(&mut self, secs: f64) {
+        let i = LATENCY_BUCKETS
+            .iter()
+            .position(|&le| secs <= le)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        self.buckets[i] += 1;
+        self.sum += secs;
+        self.count += 1;
+    }
+}
+
+#[derive(Debug)]
+pub struct Metrics {
+    commands: BTreeMap<&'static str, Histogram>,
+    connections_received: u64,
+    connected_clients: u64,
+}
+
+impl Metrics {
+    pub const fn new() -> Self {
+        Self {
+            commands: BTreeMap::new(),
+            connections_received: 0,
+            connected_clients: 0,
+        }
+    }
+
+    pub fn record_command(&mut self, name: &'static str, elapsed: Duration) {
+        self.commands
+            .entry(name)
+            .or_default()
+            .observe(elapsed.as_secs_f64());
+    }
+
+    pub fn connection_opened(&mut self) {
+        self.connections_received += 1;
+        self.connected_clients += 1;
+    }
+
+    pub fn connection_closed(&mut self) {
+        self.connected_clients = self.connected_clients.saturating_sub(1);
+    }
+
+    /// Renders every metric in the prometheus text exposition format,
+    /// along with the state of the databases
+    pub fn render(&self, dbs: &[Db], eviction: &Eviction) -> String {
+        let mut out = String::new();
+
+        header(
+            &mut out,
+            "tcpserver_commands_total",
+            "counter",
+            "Total number of processed commands",
+        );
+        for (name, h) in &self.commands {
+            let _ = writeln!(
+                out,
+                "tcpserver_commands_total{{cmd=\"{name}\"}} {}",
+                h.count
+            );
+        }
+
+        header(
+            &mut out,
+            "tcpserver_command_duration_seconds",
+            "histogram",
+            "Command dispatch latency",
+        );
+        for (name, h) in &self.commands {
+            let mut cumulative = 0;
+            for (le, n) in LATENCY_BUCKETS.iter().zip(h.buckets) {
+                cumulative += n;
+                let _ = writeln!(
+                    out,
+                    "tcpserver_command_duration_seconds_bucket{{cmd=\"{name}\",le=\"{le}\"}} {cumulative}"
+                );
+            }
+            let _ = writeln!(
+                out,
+                "tcpserver_command_duration_seconds_bucket{{cmd=\"{name}\",le=\"+Inf\"}} {}",
+                h.count
+            );
+            let _ = writeln!(
+                out,
+                "tcpserver_command_duration_seconds_sum{{cmd=\"{name}\"}} {}",
+                h.sum
+            );
+            let _ = writeln!(
+                out,
+                "tcpserver_command_duration_seconds_count{{cmd=\"{name}\"}} {}",
+                h.count
+            );
+        }
+
+        header(
+            &mut out,
+            "tcpserver_connections_received_total",
+            "counter",
+            "Total number of accepted connections",
+        );
+        let _ = writeln!(
+            out,
+            "tcpserver_connections_received_total {}",
+            self.connections_received
+        );
+
+        header(
+            &mut out,
+            "tcpserver_connected_clients",
+            "gauge",
+            "Number of open client connections",
+        );
+        let _ = writeln!(
+            out,
+            "tcpserver_connected_clients {}",
+            self.connected_clients
+        );
+
+        let keys: usize = dbs.iter().map(Db::size).sum();
+        let used_memory: usize = dbs.iter().map(Db::used_memory).sum();
+
+        header(
+            &mut out,
+            "tcpserver_keyspace_keys",
+            "gauge",
+            "Number of keys in the keyspace",
+        );
+        let _ = writeln!(out, "tcpserver_keyspace_keys {keys}");
+
+        header(
+            &mut out,
+            "tcpserver_dict_buckets",
+            "gauge",
+            "Number of buckets per hash table",
+        );
+        for db in dbs.iter() {
+            let (primary, secondary) = db.dict().bucket_counts();
+            let index = db.index();
+            let _ = writeln!(
+                out,
+                "tcpserver_dict_buckets{{db=\"{index}\",table=\"primary\"}} {primary}"
+            );
+            let _ = writeln!(
+                out,
+                "tcpserver_dict_buckets{{db=\"{index}\",table=\"secondary\"}} {secondary}"
+            );
+        }
+
+        header(
+            &mut out,
+            "tcpserver_dict_load_factor",
+            "gauge",
+            "Items per bucket of the primary hash table",
+        );
+        for db in dbs.iter() {
+            let _ = writeln!(
+                out,
+                "tcpserver_dict_load_factor{{db=\"{}\"}} {}",
+                db.index(),
+                db.dict().load_factor()
+            );
+        }
+
+        header(
+            &mut out,
+            "tcpserver_dict_rehashing",
+            "gauge",
+            "Whether an incremental rehash is in progress",
+        );
+        for db in dbs.iter() {
+            let _ = writeln!(
+                out,
+                "tcpserver_dict_rehashing{{db=\"{}\"}} {}",
+                db.index(),
+                db.dict().is_rehashing() as u8
+            );
+        }
+
+        header(
+            &mut out,
+            "tcpserver_used_memory_bytes",
+            "gauge",
+            "Approximate memory used by the keyspace",
+        );
+        let _ = writeln!(out, "tcpserver_used_memory_bytes {used_memory}");
+
+        header(
+            &mut out,
+            "tcpserver_maxmemory_bytes",
+            "gauge",
+            "Configured memory limit, 0 if unlimited",
+        );
+        let _ = writeln!(out, "tcpserver_maxmemory_bytes {}", eviction.maxmemory());
+
+        header(
+            &mut out,
+            "tcpserver_evicted_keys_total",
+            "counter",
+            "Number of keys evicted because of maxmemory",
+        );
+        let _ = writeln!(
+            out,
+            "tcpserver_evicted_keys_total {}",
+            eviction.evicted_keys()
+        );
+
+        out
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{config::Config, storage};
+
+    #[test]
+    fn renders_prometheus_text() {
+        let mut metrics = Metrics::new();
+        metrics.record_command("get", Duration::from_micros(20));
+        metrics.record_command("get", Duration::from_millis(2));
+        metrics.connection_opened();
+        metrics.connection_opened();
+        metrics.connection_closed();
+
+        let dbs = storage::new_dbs(2);
+        let out = metrics.render(&dbs, &Eviction::new(&Config::default()));
+        let lines: Vec<&str> = out.lines().collect();
+        for line in [
+            "# HELP tcpserver_commands_total Total number of processed commands",
+            "# TYPE tcpserver_commands_total counter",
+            "tcpserver_commands_total{cmd=\"get\"} 2",
+            "# TYPE tcpserver_command_duration_seconds histogram",
+            "tcpserver_command_duration_seconds_bucket{cmd=\"get\",le=\"0.00001\"} 0",
+            "tcpserver_command_duration_seconds_bucket{cmd=\"get\",le=\"0.000025\"} 1",
+            "tcpserver_command_duration_seconds_bucket{cmd=\"get\",le=\"0.001\"} 1",
+            "tcpserver_command_duration_seconds_bucket{cmd=\"get\",le=\"0.0025\"} 2",
+            "tcpserver_command_duration_seconds_bucket{cmd=\"get\",le=\"+Inf\"} 2",
+            "tcpserver_command_duration_seconds_count{cmd=\"get\"} 2",
+            "tcpserver_connections_received_total 2",
+            "tcpserver_connected_clients 1",
+            "tcpserver_keyspace_keys 0",
+            "tcpserver_dict_rehashing{db=\"1\"} 0",
+            "tcpserver_maxmemory_bytes 0",
+        ] {
+            assert!(lines.contains(&line), "missing {line:?} in\n{out}");
+        }
+
+        // every sample follows the HELP and TYPE lines of its family
+        let mut family = "";
+        for line in lines {
+            if let Some(rest) = line.strip_prefix("# TYPE ") {
+                family = rest.split(' ').next().unwrap();
+            } else if !line.starts_with('#') {
+                assert!(line.starts_with(family), "{line} outside of {family}");
+            }
+        }
+    }
+}
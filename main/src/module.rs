@@ -0,0 +1,337 @@
+//! Extension API, so crates can add commands, data types and keyspace
+//! callbacks without forking the server. Modules are linked in with
+//! [`ServerBuilder::module`](crate::server::ServerBuilder::module) and
+//! loaded once before any client connects.
+
+use std::{any::Any, collections::HashMap, fmt::Debug};
+
+use thiserror::Error;
+
+use crate::{
+    command_table::CommandSpec, protocol::Reply, server::ServerState, session::Session, storage::Db,
+};
+
+type KeyspaceCallback = Box<dyn Fn(&KeyspaceEvent) + Send + Sync>;
+
+#[derive(Error, Debug)]
+pub enum ModuleError {
+    #[error("command '{0}' already exists")]
+    DuplicateCommand(String),
+
+    #[error("data type '{0}' already exists")]
+    DuplicateType(String),
+
+    #[error("{0}")]
+    Custom(String),
+}
+
+/// Entry point of an extension
+pub trait Module {
+    /// Name used when reporting errors
+    fn name(&self) -> &str;
+
+    /// Registers the commands, types and callbacks of the module
+    fn load(&self, registry: &mut Registry) -> Result<(), ModuleError>;
+}
+
+/// What a module command gets to work with
+pub struct Context<'a> {
+    /// The database selected by the client
+    pub db: &'a mut Db,
+    pub session: &'a Session,
+}
+
+/// A command implemented by a module, closures taking a `Context` and
+/// the arguments after the command name work too
+pub trait Command: Send + Sync {
+    fn call(&self, ctx: &mut Context<'_>, args: &[String]) -> Reply;
+}
+
+impl<F> Command for F
+where
+    F: Fn(&mut Context<'_>, &[String]) -> Reply + Send + Sync,
+{
+    fn call(&self, ctx: &mut Context<'_>, args: &[String]) -> Reply {
+        self(ctx, args)
+    }
+}
+
+/// A value of a type defined by a module, stored as [`Value::Module`](crate::storage::Value::Module)
+pub trait ModuleValue: Any + Debug + Send + Sync {
+    /// Name of the registered [`ModuleType`], also reported by `TYPE`
+    fn type_name(&self) -> &'static str;
+
+    /// Whether `other` holds the same value, backs `PartialEq` for [`Value`](crate::storage::Value)
+    fn eq_value(&self, other: &dyn ModuleValue) -> bool;
+
+    /// Approximate number of heap bytes used by the value, counted
+    /// against `maxmemory`
+    fn mem_usage(&self) -> usize;
+
+    fn clone_value(&self) -> Box<dyn ModuleValue>;
+}
+
+/// Describes a data type defined by a module
+#[derive(Clone, Copy)]
+pub struct ModuleType {
+    pub name: &'static str,
+}
+
+/// Boxed module value, so `Value` can stay `Clone` and `PartialEq`
+#[derive(Debug)]
+pub struct CustomValue(Box<dyn ModuleValue>);
+
+impl CustomValue {
+    pub fn new(value: impl ModuleValue) -> Self {
+        Self(Box::new(value))
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        self.0.type_name()
+    }
+
+    pub fn mem_usage(&self) -> usize {
+        self.0.mem_usage()
+    }
+
+    pub fn downcast_ref<T: ModuleValue>(&self) -> Option<&T> {
+        (self.0.as_ref() as &dyn Any).downcast_ref()
+    }
+
+    pub fn downcast_mut<T: ModuleValue>(&mut self) -> Option<&mut T> {
+        (self.0.as_mut() as &mut dyn Any).downcast_mut()
+    }
+}
+
+impl Clone for CustomValue {
+    fn clone(&self) -> Self {
+        Self(self.0.clone_value())
+    }
+}
+
+impl PartialEq for CustomValue {
+    fn eq(&self, other: &Self) -> bool {
+        self.type_name() == other.type_name() && self.0.eq_value(other.0.as_ref())
+    }
+}
+
+/// A key modified by a write command
+#[derive(Debug)]
+pub struct KeyspaceEvent<'a> {
+    pub db: usize,
+    /// Name of the command, like `set` or `lpush`
+    pub event: &'a str,
+    pub key: &'a str,
+}
+
+/// The data types and keyspace callbacks the modules of a server registered
+#[derive(Default)]
+pub struct Modules {
+    types: HashMap<&'static str, ModuleType>,
+    keyspace_callbacks: Vec<KeyspaceCallback>,
+}
+
+impl Modules {
+    /// Runs the keyspace callbacks for every key modified since the last
+    /// call, `event` being the command that modified them
+    pub fn notify_keyspace_events(&self, dbs: &mut [Db], event: &str) {
+        if self.keyspace_callbacks.is_empty() {
+            return;
+        }
+        for (index, db) in dbs.iter_mut().enumerate() {
+            for key in db.take_modified() {
+                let event = KeyspaceEvent {
+                    db: index,
+                    event,
+                    key: &key,
+                };
+                for callback in &self.keyspace_callbacks {
+                    callback(&event);
+                }
+            }
+        }
+    }
+
+    /// Forgets the keys modified since the last call, like the ones
+    /// evicted or written by a command that failed
+    pub fn discard_keyspace_events(&self, dbs: &mut [Db]) {
+        if self.keyspace_callbacks.is_empty() {
+            return;
+        }
+        for db in dbs {
+            db.take_modified();
+        }
+    }
+}
+
+/// Handed to [`Module::load`] to register what the module provides
+pub struct Registry<'a> {
+    state: &'a mut ServerState,
+}
+
+impl<'a> Registry<'a> {
+    pub(crate) fn new(state: &'a mut ServerState) -> Self {
+        Self { state }
+    }
+
+    /// Adds a command, see [`CommandSpec::module`]
+    pub fn command(&mut self, spec: CommandSpec) -> Result<(), ModuleError> {
+        let name = spec.name;
+        if !self.state.commands.register(spec) {
+            return Err(ModuleError::DuplicateCommand(name.into()));
+        }
+        Ok(())
+    }
+
+    pub fn data_type(&mut self, ty: ModuleType) -> Result<(), ModuleError> {
+        let types = &mut self.state.modules.types;
+        if types.contains_key(ty.name) {
+            return Err(ModuleError::DuplicateType(ty.name.into()));
+        }
+        types.insert(ty.name, ty);
+        Ok(())
+    }
+
+    /// Calls `callback` for every key a write command modified, after
+    /// the command ran without an error
+    pub fn on_keyspace_event(&mut self, callback: impl Fn(&KeyspaceEvent) + Send + Sync + 'static) {
+        for db in &mut self.state.dbs {
+            db.track_modified();
+        }
+        self.state
+            .modules
+            .keyspace_callbacks
+            .push(Box::new(callback));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::{
+        command_table::{Keys, WRITE},
+        commands::args,
+        config::Config,
+        net::PeerAddr,
+        protocol::request::handle_and_encode_request,
+        storage::{Object, Value},
+    };
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct Counter(u64);
+
+    impl ModuleValue for Counter {
+        fn type_name(&self) -> &'static str {
+            "counter"
+        }
+
+        fn eq_value(&self, other: &dyn ModuleValue) -> bool {
+            (other as &dyn Any).downcast_ref() == Some(self)
+        }
+
+        fn mem_usage(&self) -> usize {
+            0
+        }
+
+        fn clone_value(&self) -> Box<dyn ModuleValue> {
+            Box::new(self.clone())
+        }
+    }
+
+    struct CounterModule;
+
+    impl Module for CounterModule {
+        fn name(&self) -> &str {
+            "counter"
+        }
+
+        fn load(&self, registry: &mut Registry) -> Result<(), ModuleError> {
+            registry.data_type(ModuleType { name: "counter" })?;
+            registry.command(CommandSpec::module(
+                "counter.incr",
+                2,
+                WRITE,
+                Keys::Range {
+                    first: 1,
+                    last: 1,
+                    step: 1,
+                },
+                |ctx: &mut Context<'_>, args: &[String]| {
+                    let key = &args[0];
+                    if let Some(mut obj) = ctx.db.get_mut(key) {
+                        let Value::Module(v) = &mut obj.value else {
+                            return Reply::err(crate::commands::WRONGTYPE);
+                        };
+                        let Some(counter) = v.downcast_mut::<Counter>() else {
+                            return Reply::err(crate::commands::WRONGTYPE);
+                        };
+                        counter.0 += 1;
+                        return Reply::Int(counter.0 as i64);
+                    }
+                    let value = Value::Module(CustomValue::new(Counter(1)));
+                    ctx.db.insert(key, Object::new(value));
+                    Reply::Int(1)
+                },
+            ))
+        }
+    }
+
+    #[test]
+    fn commands_types_and_keyspace_events() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut state = ServerState::new(&Config::default()).unwrap();
+        let mut registry = Registry::new(&mut state);
+        CounterModule.load(&mut registry).unwrap();
+        let seen = events.clone();
+        registry.on_keyspace_event(move |e| {
+            seen.lock()
+                .unwrap()
+                .push(format!("{} {} {}", e.db, e.event, e.key));
+        });
+        assert!(matches!(
+            CounterModule.load(&mut registry),
+            Err(ModuleError::DuplicateType(_))
+        ));
+
+        let mut session = Session::new(0, PeerAddr::Unix(Default::default()));
+        state.acl.authenticate_default(&mut session);
+        let mut run = |cmd: &[&str]| {
+            let mut buf = Vec::new();
+            handle_and_encode_request(&mut state, args(cmd), &mut session, &mut buf);
+        };
+        run(&["COUNTER.INCR", "c"]);
+        run(&["COUNTER.INCR", "c"]);
+        // no-ops, failures and read-only keys aren't reported
+        run(&["del", "missing"]);
+        run(&["sadd", "s1", "a"]);
+        run(&["sadd", "s2", "a"]);
+        run(&["renamenx", "s1", "s2"]);
+        run(&["sinterstore", "dst", "s1", "s2"]);
+        run(&["lpush", "s1", "x"]);
+        run(&["blpop", "l", "0"]);
+        run(&["move", "c", "1"]);
+        run(&["rpush", "l", "a"]);
+        state.blocked.serve_ready(&mut state.dbs, &state.modules);
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                "0 counter.incr c",
+                "0 counter.incr c",
+                "0 sadd s1",
+                "0 sadd s2",
+                "0 sinterstore dst",
+                "0 move c",
+                "1 move c",
+                "0 rpush l",
+                "0 blpop l",
+            ]
+        );
+
+        let obj = state.dbs[1].get("c").unwrap();
+        assert_eq!(obj.value.type_name(), "counter");
+        assert_eq!(obj.value, Value::Module(CustomValue::new(Counter(2))));
+        assert_ne!(obj.value, Value::Module(CustomValue::new(Counter(1))));
+    }
+}
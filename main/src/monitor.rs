@@ -0,0 +1,196 @@
+use std::{
+    fmt::Write,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    config::Config,
+    protocol::{Reply, ReplyFormat, request::serialize_reply},
+    session::Session,
+};
+
+/// The connections in monitor mode of a server
+#[derive(Debug)]
+pub struct Monitors {
+    /// Encoded frames waiting to be copied into every monitor's outgoing buffer
+    feed: Feed,
+    /// Number of connections in monitor mode, so we can skip
+    /// formatting commands when nobody is watching
+    count: usize,
+    /// Max number of bytes a monitor may have pending before it gets disconnected
+    output_buffer_limit: usize,
+}
+
+impl Monitors {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            feed: Feed::default(),
+            count: 0,
+            output_buffer_limit: config.monitor_output_buffer_limit,
+        }
+    }
+
+    pub fn output_buffer_limit(&self) -> usize {
+        self.output_buffer_limit
+    }
+
+    /// Turns `session` into a monitor sink
+    pub fn subscribe(&mut self, session: &mut Session) {
+        if !session.monitor {
+            session.monitor = true;
+            self.count += 1;
+        }
+    }
+
+    pub fn unsubscribe(&mut self, session: &mut Session) {
+        if session.monitor {
+            session.monitor = false;
+            self.count -= 1;
+        }
+    }
+
+    /// Queues `cmd` for every monitor, formatted as
+    /// `<unix secs>.<micros> [<client addr>] "arg" "arg" ...`
+    pub fn feed(&mut self, cmd: &[String], session: &Session) {
+        if self.count == 0 {
+            return;
+        }
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+
+        let mut line = format!(
+            "{}.{:06} [{}]",
+            now.as_secs(),
+            now.subsec_micros(),
+            session.addr
+        );
+        for arg in cmd {
+            line.push(' ');
+            repr(arg, &mut line);
+        }
+
+        let line = Reply::Str(line.into_bytes());
+        serialize_reply(&line, ReplyFormat::Plain, &mut self.feed.plain);
+        serialize_reply(&line, ReplyFormat::Typed, &mut self.feed.typed);
+    }
+
+    /// Takes every frame queued since the last call
+    pub fn drain(&mut self) -> Feed {
+        std::mem::take(&mut self.feed)
+    }
+}
+
+/// Monitor frames encoded in both reply formats, since monitors
+/// may not all use the same
+#[derive(Debug, Default)]
+pub struct Feed {
+    plain: Vec<u8>,
+    typed: Vec<u8>,
+}
+
+impl Feed {
+    pub fn is_empty(&self) -> bool {
+        self.plain.is_empty()
+    }
+
+    pub fn frames(&self, format: ReplyFormat) -> &[u8] {
+        match format {
+            ReplyFormat::Plain => &self.plain,
+            ReplyFormat::Typed => &self.typed,
+        }
+    }
+}
+
+/// Writes `s` as a quoted string, escaping non printable characters
+fn repr(s: &str, out: &mut String) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\x{:02x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        client::{Client, ClientError},
+        net::PeerAddr,
+        server::Server,
+    };
+
+    #[test]
+    fn repr_escapes_unprintable_characters() {
+        let mut out = String::new();
+        repr("a \"b\"\\\n\r\t\x01\x7fé", &mut out);
+        assert_eq!(out, r#""a \"b\"\\\n\r\t\x01\x7fé""#);
+    }
+
+    #[test]
+    fn feeds_only_while_someone_watches() {
+        let mut monitors = Monitors::new(&Config::default());
+        let mut session = Session::new(1, PeerAddr::Tcp(([127, 0, 0, 1], 6000).into()));
+        let cmd = ["set".to_string(), "k".into(), "a b".into()];
+
+        monitors.feed(&cmd, &session);
+        assert!(monitors.drain().is_empty());
+
+        monitors.subscribe(&mut session);
+        monitors.feed(&cmd, &session);
+        let feed = monitors.drain();
+        assert!(monitors.drain().is_empty());
+
+        let plain = feed.frames(ReplyFormat::Plain);
+        let line = std::str::from_utf8(&plain[8..]).unwrap();
+        assert!(
+            line.ends_with(r#" [127.0.0.1:6000] "set" "k" "a b""#),
+            "{line}"
+        );
+        let (typed, _) = Reply::decode(&feed.frames(ReplyFormat::Typed)[8..]).unwrap();
+        assert_eq!(typed, Reply::str(line));
+
+        monitors.unsubscribe(&mut session);
+        monitors.feed(&cmd, &session);
+        assert!(monitors.drain().is_empty());
+    }
+
+    #[test]
+    fn disconnects_monitors_over_the_output_buffer_limit() {
+        let config = Config {
+            monitor_output_buffer_limit: 256,
+            ..Config::default()
+        };
+        let server = Server::builder()
+            .config(config)
+            .bind("127.0.0.1:0".parse().unwrap())
+            .run()
+            .unwrap();
+        let addr = server.addr().unwrap();
+
+        let mut monitor = Client::connect(addr).unwrap();
+        assert_eq!(monitor.query(&["monitor"]).unwrap(), Reply::ok());
+        let mut client = Client::connect(addr).unwrap();
+
+        client.set("k", "v").unwrap();
+        let Reply::Str(line) = monitor.read_reply().unwrap() else {
+            panic!("monitors receive strings");
+        };
+        assert!(line.ends_with(br#" "set" "k" "v""#));
+
+        client.set("k", &"v".repeat(256)).unwrap();
+        assert!(matches!(monitor.read_reply(), Err(ClientError::Io(_))));
+        assert!(monitor.is_broken());
+    }
+}
@@ -0,0 +1,306 @@
+use std::{
+    ffi::OsString,
+    fmt, fs,
+    io::{self, Read, Write},
+    net::SocketAddr,
+    os::unix::{
+        fs::{DirBuilderExt, FileTypeExt, PermissionsExt},
+        net as std_unix,
+    },
+    path::{Path, PathBuf},
+    process,
+};
+
+use mio::{
+    Interest, Registry, Token,
+    event::Source,
+    net::{TcpListener, TcpStream, UnixListener, UnixStream},
+};
+use socket2::{Domain, Socket, Type};
+
+/// Backlog of pending connections per listener
+const BACKLOG: i32 = 1024;
+
+/// Address of a connected peer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PeerAddr {
+    Tcp(SocketAddr),
+    /// Path of the unix socket the peer connected through
+    Unix(PathBuf),
+}
+
+impl fmt::Display for PeerAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PeerAddr::Tcp(addr) => write!(f, "{addr}"),
+            PeerAddr::Unix(path) => write!(f, "{}:0", path.display()),
+        }
+    }
+}
+
+impl From<SocketAddr> for PeerAddr {
+    fn from(addr: SocketAddr) -> Self {
+        PeerAddr::Tcp(addr)
+    }
+}
+
+/// A listening socket, unix socket files are removed when dropped
+#[derive(Debug)]
+pub enum Listener {
+    Tcp(TcpListener),
+    Unix(UnixListener, PathBuf),
+}
+
+impl Listener {
+    /// Binds a tcp listener, ipv6 listeners only accept ipv6 connections
+    /// so they can share a port with an ipv4 one
+    pub fn bind_tcp(addr: SocketAddr) -> io::Result<Self> {
+        let socket = Socket::new(Domain::for_address(addr), Type::STREAM, None)?;
+        if addr.is_ipv6() {
+            socket.set_only_v6(true)?;
+        }
+        socket.set_reuse_address(true)?;
+        socket.set_nonblocking(true)?;
+        socket.bind(&addr.into())?;
+        socket.listen(BACKLOG)?;
+
+        Ok(Listener::Tcp(TcpListener::from_std(socket.into())))
+    }
+
+    /// Binds a unix socket at `path`, replacing a stale socket file,
+    /// and sets its permissions to `perm` if given
+    pub fn bind_unix(path: impl AsRef<Path>, perm: Option<u32>) -> io::Result<Self> {
+        let path = path.as_ref();
+        remove_stale_socket(path)?;
+
+        let listener = match perm {
+            Some(perm) => bind_unix_private(path, perm)?,
+            None => UnixListener::bind(path)?,
+        };
+        Ok(Listener::Unix(listener, path.to_path_buf()))
+    }
+
+    pub fn accept(&self) -> io::Result<(Stream, PeerAddr)> {
+        match self {
+            Listener::Tcp(l) => l.accept().map(|(s, addr)| (Stream::Tcp(s), addr.into())),
+            Listener::Unix(l, path) => l
+                .accept()
+                .map(|(s, _)| (Stream::Unix(s), PeerAddr::Unix(path.clone()))),
+        }
+    }
+
+    pub fn local_addr(&self) -> io::Result<PeerAddr> {
+        match self {
+            Listener::Tcp(l) => l.local_addr().map(PeerAddr::Tcp),
+            Listener::Unix(_, path) => Ok(PeerAddr::Unix(path.clone())),
+        }
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        if let Listener::Unix(_, path) = self {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// Removes the socket file a server left behind at `path`, refusing to
+/// touch anything that isn't a socket or that a server still listens on
+fn remove_stale_socket(path: &Path) -> io::Result<()> {
+    let meta = match fs::symlink_metadata(path) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        meta => meta?,
+    };
+    if !meta.file_type().is_socket() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} exists and is not a socket", path.display()),
+        ));
+    }
+    if std_unix::UnixStream::connect(path).is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            format!("{} is in use by another server", path.display()),
+        ));
+    }
+    fs::remove_file(path)
+}
+
+/// Binds in a directory only we can enter and moves the socket to `path`
+/// once it has its permissions, so nobody can connect before
+fn bind_unix_private(path: &Path, perm: u32) -> io::Result<UnixListener> {
+    let Some(name) = path.file_name() else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is not a valid socket path", path.display()),
+        ));
+    };
+    let mut dir_name = OsString::from(".");
+    dir_name.push(name);
+    dir_name.push(format!(".{}", process::id()));
+    let dir = path.with_file_name(dir_name);
+    fs::DirBuilder::new().mode(0o700).create(&dir)?;
+
+    let tmp = dir.join("socket");
+    let listener = UnixListener::bind(&tmp).and_then(|listener| {
+        fs::set_permissions(&tmp, fs::Permissions::from_mode(perm))?;
+        fs::rename(&tmp, path)?;
+        Ok(listener)
+    });
+    let _ = fs::remove_file(&tmp);
+    let _ = fs::remove_dir(&dir);
+    listener
+}
+
+impl Source for Listener {
+    fn register(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        match self {
+            Listener::Tcp(l) => l.register(registry, token, interests),
+            Listener::Unix(l, _) => l.register(registry, token, interests),
+        }
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        match self {
+            Listener::Tcp(l) => l.reregister(registry, token, interests),
+            Listener::Unix(l, _) => l.reregister(registry, token, interests),
+        }
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        match self {
+            Listener::Tcp(l) => l.deregister(registry),
+            Listener::Unix(l, _) => l.deregister(registry),
+        }
+    }
+}
+
+/// A client connection, accepted by any kind of `Listener`
+#[derive(Debug)]
+pub enum Stream {
+    Tcp(TcpStream),
+    Unix(UnixStream),
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(s) => s.read(buf),
+            Stream::Unix(s) => s.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(s) => s.write(buf),
+            Stream::Unix(s) => s.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Tcp(s) => s.flush(),
+            Stream::Unix(s) => s.flush(),
+        }
+    }
+}
+
+impl Source for Stream {
+    fn register(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        match self {
+            Stream::Tcp(s) => s.register(registry, token, interests),
+            Stream::Unix(s) => s.register(registry, token, interests),
+        }
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        match self {
+            Stream::Tcp(s) => s.reregister(registry, token, interests),
+            Stream::Unix(s) => s.reregister(registry, token, interests),
+        }
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        match self {
+            Stream::Tcp(s) => s.deregister(registry),
+            Stream::Unix(s) => s.deregister(registry),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{env, fs::File};
+
+    use super::*;
+
+    /// A fresh directory for the sockets of one test
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("tcpserver-{name}-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn unix_socket_lifecycle() {
+        let dir = temp_dir("lifecycle");
+        let path = dir.join("server.sock");
+
+        let listener = Listener::bind_unix(&path, Some(0o600)).unwrap();
+        let meta = fs::symlink_metadata(&path).unwrap();
+        assert!(meta.file_type().is_socket());
+        assert_eq!(meta.permissions().mode() & 0o777, 0o600);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        std_unix::UnixStream::connect(&path).unwrap();
+        let err = Listener::bind_unix(&path, None).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+
+        drop(listener);
+        assert!(!path.exists());
+        fs::remove_dir(&dir).unwrap();
+    }
+
+    #[test]
+    fn replaces_only_stale_sockets() {
+        let dir = temp_dir("stale");
+        let path = dir.join("server.sock");
+
+        // a socket nobody listens on anymore
+        drop(std_unix::UnixListener::bind(&path).unwrap());
+        assert!(path.exists());
+        let listener = Listener::bind_unix(&path, None).unwrap();
+        std_unix::UnixStream::connect(&path).unwrap();
+        drop(listener);
+
+        File::create(&path).unwrap();
+        let err = Listener::bind_unix(&path, None).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert!(path.is_file());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -13,7 +13,197 @@ pub enum ParseError {
     ProtocolError,
 }
 
+/// How a connection wants replies encoded after the status code,
+/// switched with `HELLO`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReplyFormat {
+    /// The original framing, see [`Reply::encode_plain`]
+    #[default]
+    Plain,
+    /// Tagged payloads, see [`Reply::encode`]. Asked for with `HELLO 2`
+    Typed,
+}
+
+/// Payload of a response, encoded after the status code
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reply {
+    Nil,
+    Err(String),
+    Str(Vec<u8>),
+    Int(i64),
+    Dbl(f64),
+    Arr(Vec<Reply>),
+}
+
+impl Reply {
+    pub const TAG_NIL: u8 = 0;
+    pub const TAG_ERR: u8 = 1;
+    pub const TAG_STR: u8 = 2;
+    pub const TAG_INT: u8 = 3;
+    pub const TAG_DBL: u8 = 4;
+    pub const TAG_ARR: u8 = 5;
+
+    /// Length of nil elements in plain arrays, telling them apart from empty strings
+    pub const PLAIN_NIL_LEN: u32 = u32::MAX;
+
+    pub fn ok() -> Self {
+        Reply::Str(b"OK".to_vec())
+    }
+
+    pub fn err(msg: impl Into<String>) -> Self {
+        Reply::Err(msg.into())
+    }
+
+    pub fn str(s: impl AsRef<[u8]>) -> Self {
+        Reply::Str(s.as_ref().to_vec())
+    }
+
+    /// Status code sent in the response header
+    pub fn status(&self) -> i32 {
+        match self {
+            Reply::Nil => request::RES_NX,
+            Reply::Err(_) => request::RES_ERR,
+            _ => request::RES_OK,
+        }
+    }
+
+    /// Encodes the payload in `format`
+    pub fn encode_as(&self, format: ReplyFormat, buf: &mut Vec<u8>) {
+        match format {
+            ReplyFormat::Plain => self.encode_plain(buf),
+            ReplyFormat::Typed => self.encode(buf),
+        }
+    }
+
+    /// Encodes the payload without type information: the bare bytes of a
+    /// string or error message, numbers as decimal text and nothing for nil,
+    /// which the status code already tells apart. Arrays are framed like
+    /// requests, a u32 count followed by every element prefixed with its
+    /// u32 length. Nil elements have no status code of their own, so their
+    /// length is `PLAIN_NIL_LEN` instead
+    pub fn encode_plain(&self, buf: &mut Vec<u8>) {
+        match self {
+            Reply::Nil => {}
+            Reply::Err(msg) => buf.extend_from_slice(msg.as_bytes()),
+            Reply::Str(s) => buf.extend_from_slice(s),
+            Reply::Int(n) => buf.extend_from_slice(n.to_string().as_bytes()),
+            Reply::Dbl(n) => buf.extend_from_slice(n.to_string().as_bytes()),
+            Reply::Arr(items) => {
+                buf.extend_from_slice(&(items.len() as u32).to_be_bytes());
+                for item in items {
+                    if *item == Reply::Nil {
+                        buf.extend_from_slice(&Self::PLAIN_NIL_LEN.to_be_bytes());
+                        continue;
+                    }
+                    let start = buf.len();
+                    buf.extend_from_slice(&[0; 4]);
+                    item.encode_plain(buf);
+                    let len = (buf.len() - start - 4) as u32;
+                    buf[start..start + 4].copy_from_slice(&len.to_be_bytes());
+                }
+            }
+        }
+    }
+
+    /// Encodes the payload with a tag byte in front of every value
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            Reply::Nil => buf.push(Self::TAG_NIL),
+            Reply::Err(msg) => {
+                buf.push(Self::TAG_ERR);
+                buf.extend_from_slice(&(msg.len() as u32).to_be_bytes());
+                buf.extend_from_slice(msg.as_bytes());
+            }
+            Reply::Str(s) => {
+                buf.push(Self::TAG_STR);
+                buf.extend_from_slice(&(s.len() as u32).to_be_bytes());
+                buf.extend_from_slice(s);
+            }
+            Reply::Int(n) => {
+                buf.push(Self::TAG_INT);
+                buf.extend_from_slice(&n.to_be_bytes());
+            }
+            Reply::Dbl(n) => {
+                buf.push(Self::TAG_DBL);
+                buf.extend_from_slice(&n.to_be_bytes());
+            }
+            Reply::Arr(items) => {
+                buf.push(Self::TAG_ARR);
+                buf.extend_from_slice(&(items.len() as u32).to_be_bytes());
+                for item in items {
+                    item.encode(buf);
+                }
+            }
+        }
+    }
+
+    /// Decodes one reply from the start of `src`, returning it
+    /// with the number of bytes consumed
+    pub fn decode(src: &[u8]) -> Result<(Reply, usize), ParseError> {
+        let Some(&tag) = src.first() else {
+            return Err(ParseError::NotEnoughBytes { want: 1, got: 0 });
+        };
+        let mut cursor = 1;
+
+        let reply = match tag {
+            Self::TAG_NIL => Reply::Nil,
+            Self::TAG_ERR | Self::TAG_STR => {
+                let len = get_u32(src, cursor)? as usize;
+                cursor += 4;
+                if src.len() < cursor + len {
+                    return Err(ParseError::NotEnoughBytes {
+                        want: cursor + len,
+                        got: src.len(),
+                    });
+                }
+                let bytes = src[cursor..cursor + len].to_vec();
+                cursor += len;
+                if tag == Self::TAG_ERR {
+                    Reply::Err(String::from_utf8(bytes).map_err(|_| ParseError::ProtocolError)?)
+                } else {
+                    Reply::Str(bytes)
+                }
+            }
+            Self::TAG_INT | Self::TAG_DBL => {
+                if src.len() < cursor + 8 {
+                    return Err(ParseError::NotEnoughBytes {
+                        want: cursor + 8,
+                        got: src.len(),
+                    });
+                }
+                let n: [u8; 8] = src[cursor..cursor + 8].try_into().unwrap();
+                cursor += 8;
+                if tag == Self::TAG_INT {
+                    Reply::Int(i64::from_be_bytes(n))
+                } else {
+                    Reply::Dbl(f64::from_be_bytes(n))
+                }
+            }
+            Self::TAG_ARR => {
+                let n = get_u32(src, cursor)? as usize;
+                if n > MAX_ARGS {
+                    return Err(ParseError::ProtocolError);
+                }
+                cursor += 4;
+                let mut items = Vec::with_capacity(n);
+                for _ in 0..n {
+                    let (item, used) = Self::decode(&src[cursor..])?;
+                    items.push(item);
+                    cursor += used;
+                }
+                Reply::Arr(items)
+            }
+            _ => return Err(ParseError::ProtocolError),
+        };
+
+        Ok((reply, cursor))
+    }
+}
+
 pub mod request {
+    use super::{Reply, ReplyFormat};
+    use crate::{command_table, server::ServerState, session::Session};
+
     pub const RES_NX: i32 = 1;
     pub const RES_OK: i32 = 0;
     pub const RES_ERR: i32 = -1;
@@ -30,36 +220,52 @@ pub mod request {
         }
     }
 
-    #[allow(static_mut_refs)]
-    pub fn handle_and_encode_request(cmd: Vec<String>, buf: &mut Vec<u8>) {
-        let map = unsafe {
-            crate::storage::MAP2.get_mut_or_init(|| collections::Dict::default())
+    /// Encodes `reply` with its status code as one response frame
+    pub fn serialize_reply(reply: &Reply, format: ReplyFormat, buf: &mut Vec<u8>) {
+        let mut data = Vec::new();
+        reply.encode_as(format, &mut data);
+        serialize(reply.status(), &data, buf)
+    }
+
+    pub fn handle_and_encode_request(
+        state: &mut ServerState,
+        cmd: Vec<String>,
+        session: &mut Session,
+        buf: &mut Vec<u8>,
+    ) {
+        let spec = match state.commands.find(&cmd).and_then(|spec| {
+            state.acl.check(session, &spec, &cmd)?;
+            state.eviction.check(&mut state.dbs, &spec)?;
+            Ok(spec)
+        }) {
+            Ok(spec) => spec,
+            Err(reply) => return serialize_reply(&reply, session.format, buf),
         };
 
-        match cmd.len() {
-            2 if cmd[0] == "get" => {
-                if let Some(v) = map.get(&cmd[1]) {
-                    serialize(RES_OK, v.value().as_bytes(), buf)
-                } else {
-                    serialize(RES_NX, &[], buf)
-                }
-            }
-            2 if cmd[0] == "del" => {
-                if let Some(s) = map.remove(cmd[1].as_str()) {
-                    serialize(RES_OK, s.value().as_bytes(), buf)
-                } else {
-                    serialize(RES_NX, &[], buf)
-                };
-            }
-            3 if cmd[0] == "set" => {
-                let old = map.insert(&cmd[1], &cmd[2]);
-                let res: &[u8] = match old {
-                    Some(ref s) => s.as_bytes(),
-                    None => cmd[2].as_str().as_bytes()
-                };
-                serialize(RES_OK, res, buf)
-            }
-            _ => serialize(RES_OK, &[], buf),
+        // keys evicted to make room aren't the command's doing
+        state.modules.discard_keyspace_events(&mut state.dbs);
+
+        let start = std::time::Instant::now();
+        let reply = spec.call(state, session, &cmd[1..]);
+        let elapsed = start.elapsed();
+        let name = spec.name;
+
+        if spec.has(command_table::WRITE) && !matches!(reply, Reply::Err(_)) {
+            state.modules.notify_keyspace_events(&mut state.dbs, name);
+        }
+
+        state.metrics.record_command(name, elapsed);
+        state.clients.record_command(session.id, name);
+        // keep credentials out of the slowlog and monitors
+        if !matches!(name, "auth" | "hello" | "acl") {
+            state.slowlog.record(&cmd, elapsed, session.addr.clone());
+        }
+        if !matches!(name, "auth" | "hello" | "acl" | "monitor") {
+            state.monitors.feed(&cmd, session);
+        }
+        // blocked clients get their reply once they are served or time out
+        if !session.blocked {
+            serialize_reply(&reply, session.format, buf);
         }
     }
 }
@@ -122,3 +328,33 @@ fn get_u32(src: &[u8], start: usize) -> Result<u32, ParseError> {
 fn to_u32(n: &[u8]) -> u32 {
     u32::from_be_bytes([n[0], n[1], n[2], n[3]])
 }
+
+#[cfg(test)]
+mod test {
+    use super::{request::*, *};
+    use crate::{
+        commands::args, config::Config, net::PeerAddr, server::ServerState, session::Session,
+    };
+
+    #[test]
+    fn plain_arrays_tell_nil_from_empty_strings() {
+        let mut state = ServerState::new(&Config::default()).unwrap();
+        let mut session = Session::new(0, PeerAddr::Unix(Default::default()));
+        state.acl.authenticate_default(&mut session);
+
+        let mut run = |cmd: &[&str]| {
+            let mut buf = Vec::new();
+            handle_and_encode_request(&mut state, args(cmd), &mut session, &mut buf);
+            buf
+        };
+        run(&["set", "empty", ""]);
+        let response = run(&["mget", "empty", "missing"]);
+
+        let mut payload = 2u32.to_be_bytes().to_vec();
+        payload.extend_from_slice(&0u32.to_be_bytes());
+        payload.extend_from_slice(&Reply::PLAIN_NIL_LEN.to_be_bytes());
+        let mut expected = Vec::new();
+        serialize(RES_OK, &payload, &mut expected);
+        assert_eq!(response, expected);
+    }
+}
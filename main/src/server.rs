@@ -0,0 +1,444 @@
+use std::{
+    io,
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::{self, JoinHandle},
+    time::Instant,
+};
+
+use log::{info, trace};
+use mio::{Events, Poll, Waker};
+
+use crate::{
+    METRICS, WAKER,
+    acl::{Acl, AclError},
+    blocking::Blocked,
+    clients::Clients,
+    command_table::CommandTable,
+    config::Config,
+    connection::ConnectionManager,
+    eviction::{Eviction, MaxmemoryPolicy},
+    exporter::MetricsExporter,
+    metrics::Metrics,
+    module::{Module, Modules, Registry},
+    monitor::Monitors,
+    net::{Listener, PeerAddr},
+    slowlog::Slowlog,
+    storage::{self, Db},
+    util::interrupted,
+};
+
+/// Everything a server instance owns, so several servers can run in one
+/// process without seeing each other
+pub struct ServerState {
+    pub dbs: Vec<Db>,
+    pub commands: CommandTable,
+    pub modules: Modules,
+    pub acl: Acl,
+    pub clients: Clients,
+    pub monitors: Monitors,
+    pub slowlog: Slowlog,
+    pub metrics: Metrics,
+    pub eviction: Eviction,
+    pub blocked: Blocked,
+}
+
+impl ServerState {
+    pub fn new(config: &Config) -> Result<Self, AclError> {
+        Ok(Self {
+            dbs: storage::new_dbs(config.databases),
+            commands: CommandTable::new(),
+            modules: Modules::default(),
+            acl: Acl::new(config)?,
+            clients: Clients::new(),
+            monitors: Monitors::new(config),
+            slowlog: Slowlog::from_config(config),
+            metrics: Metrics::new(),
+            eviction: Eviction::new(config),
+            blocked: Blocked::default(),
+        })
+    }
+}
+
+pub struct Server;
+
+impl Server {
+    pub fn builder() -> ServerBuilder {
+        ServerBuilder {
+            config: Config::default(),
+            bind: Vec::new(),
+            modules: Vec::new(),
+        }
+    }
+}
+
+/// Configures the server and links in extension modules
+pub struct ServerBuilder {
+    config: Config,
+    bind: Vec<SocketAddr>,
+    modules: Vec<Box<dyn Module>>,
+}
+
+impl ServerBuilder {
+    /// Replaces the whole configuration, so it should come before the
+    /// other options
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Listens on `addr` instead of the configured addresses, can be
+    /// called more than once. Port 0 picks a free port, see
+    /// [`ServerHandle::addr`]
+    pub fn bind(mut self, addr: SocketAddr) -> Self {
+        self.bind.push(addr);
+        self
+    }
+
+    /// Also listens on a unix socket at `path`
+    pub fn unixsocket(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config.unixsocket = Some(path.into());
+        self
+    }
+
+    /// Number of databases clients can `SELECT`, at least one
+    pub fn databases(mut self, count: usize) -> Self {
+        self.config.databases = count;
+        self
+    }
+
+    /// Limits the memory used by the keyspace to `bytes`, 0 meaning no limit
+    pub fn maxmemory(mut self, bytes: usize, policy: MaxmemoryPolicy) -> Self {
+        self.config.maxmemory = bytes;
+        self.config.maxmemory_policy = policy;
+        self
+    }
+
+    /// Adds a module, loaded in the order they were added
+    pub fn module(mut self, module: impl Module + 'static) -> Self {
+        self.modules.push(Box::new(module));
+        self
+    }
+
+    /// Loads the modules and binds the listeners, then serves clients on a
+    /// thread of its own until the returned handle stops it
+    pub fn run(mut self) -> io::Result<ServerHandle> {
+        if !self.bind.is_empty() {
+            self.config.bind = self.bind;
+        }
+        let config = self.config;
+        if config.databases == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "a server needs at least one database",
+            ));
+        }
+        let mut state = ServerState::new(&config)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+        let mut registry = Registry::new(&mut state);
+        for module in &self.modules {
+            module.load(&mut registry).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("module {}: {e}", module.name()),
+                )
+            })?;
+            info!("loaded module {}", module.name());
+        }
+
+        let poll = Poll::new()?;
+        let mut connection_manager = ConnectionManager::new();
+
+        for addr in &config.bind {
+            connection_manager.add_listener(Listener::bind_tcp(*addr)?, &poll)?;
+        }
+        if let Some(path) = &config.unixsocket {
+            connection_manager
+                .add_listener(Listener::bind_unix(path, config.unixsocketperm)?, &poll)?;
+        }
+        let mut addrs = Vec::new();
+        for listener in connection_manager.listeners() {
+            let addr = listener.local_addr()?;
+            info!("listening on {addr}");
+            addrs.push(addr);
+        }
+
+        let exporter = match config.metrics_bind {
+            Some(addr) => {
+                let mut exporter = MetricsExporter::bind(addr)?;
+                exporter.register(&poll, METRICS)?;
+                info!(
+                    "serving metrics on http://{}/metrics",
+                    exporter.local_addr()?
+                );
+                Some(exporter)
+            }
+            None => None,
+        };
+        let metrics_addr = exporter.as_ref().map(|e| e.local_addr()).transpose()?;
+
+        let waker = Arc::new(Waker::new(poll.registry(), WAKER)?);
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let event_loop = EventLoop {
+            poll,
+            state,
+            connection_manager,
+            exporter,
+            shutdown: shutdown.clone(),
+        };
+        let thread = thread::Builder::new()
+            .name("tcpserver".into())
+            .spawn(move || event_loop.run())?;
+
+        Ok(ServerHandle {
+            addrs,
+            metrics_addr,
+            shutdown,
+            waker,
+            thread: Some(thread),
+        })
+    }
+}
+
+/// A running server, stopped when the handle is dropped
+pub struct ServerHandle {
+    addrs: Vec<PeerAddr>,
+    metrics_addr: Option<SocketAddr>,
+    shutdown: Arc<AtomicBool>,
+    waker: Arc<Waker>,
+    thread: Option<JoinHandle<io::Result<()>>>,
+}
+
+impl ServerHandle {
+    /// Address of the first TCP listener, with the port picked by the OS
+    /// when bound to port 0
+    pub fn addr(&self) -> Option<SocketAddr> {
+        self.addrs.iter().find_map(|addr| match addr {
+            PeerAddr::Tcp(addr) => Some(*addr),
+            _ => None,
+        })
+    }
+
+    /// Addresses of every listener
+    pub fn addrs(&self) -> &[PeerAddr] {
+        &self.addrs
+    }
+
+    pub fn metrics_addr(&self) -> Option<SocketAddr> {
+        self.metrics_addr
+    }
+
+    /// Stops accepting requests, closes every connection and waits for the
+    /// event loop to exit
+    pub fn stop(mut self) -> io::Result<()> {
+        self.shutdown()
+    }
+
+    /// Waits until the server exits because of an I/O error
+    pub fn join(mut self) -> io::Result<()> {
+        match self.thread.take() {
+            Some(thread) => join(thread),
+            None => Ok(()),
+        }
+    }
+
+    fn shutdown(&mut self) -> io::Result<()> {
+        let Some(thread) = self.thread.take() else {
+            return Ok(());
+        };
+        self.shutdown.store(true, Ordering::Release);
+        self.waker.wake()?;
+        join(thread)
+    }
+}
+
+impl Drop for ServerHandle {
+    fn drop(&mut self) {
+        let _ = self.shutdown();
+    }
+}
+
+fn join(thread: JoinHandle<io::Result<()>>) -> io::Result<()> {
+    thread
+        .join()
+        .unwrap_or_else(|_| Err(io::Error::other("server thread panicked")))
+}
+
+struct EventLoop {
+    poll: Poll,
+    state: ServerState,
+    connection_manager: ConnectionManager,
+    exporter: Option<MetricsExporter>,
+    shutdown: Arc<AtomicBool>,
+}
+
+impl EventLoop {
+    fn run(mut self) -> io::Result<()> {
+        let mut events = Events::with_capacity(128);
+        let state = &mut self.state;
+        let connection_manager = &mut self.connection_manager;
+        let exporter = &mut self.exporter;
+        let poll = &mut self.poll;
+
+        loop {
+            // wake up in time for the closest blocking timeout
+            let timeout = state.blocked.next_timeout(Instant::now());
+            if let Err(e) = poll.poll(&mut events, timeout) {
+                if interrupted(&e) {
+                    continue;
+                } else {
+                    return Err(e);
+                }
+            }
+            if self.shutdown.load(Ordering::Acquire) {
+                info!("shutting down");
+                return Ok(());
+            }
+
+            for event in events.iter() {
+                match event.token() {
+                    WAKER => {}
+                    METRICS => {
+                        if let Some(exporter) = exporter.as_mut() {
+                            exporter.handle_accept(poll, || connection_manager.next_token())?;
+                        }
+                    }
+                    token if connection_manager.is_listener(&token) => {
+                        connection_manager.handle_accept(state, token, poll)?;
+                    }
+                    token if exporter.as_ref().is_some_and(|e| e.owns(&token)) => {
+                        exporter
+                            .as_mut()
+                            .unwrap()
+                            .handle_event(poll, event, state)?;
+                    }
+                    token => {
+                        let Some(conn) = connection_manager.get_connection_mut(&token) else {
+                            continue;
+                        };
+                        // connections close themselves on I/O errors,
+                        // which must not take the whole server down
+                        if event.is_readable()
+                            && conn.want_read()
+                            && let Err(e) = conn.on_read(state)
+                        {
+                            info!("{e}");
+                        }
+
+                        if event.is_writable()
+                            && conn.want_write()
+                            && let Err(e) = conn.on_write(state)
+                        {
+                            info!("{e}");
+                        }
+
+                        if conn.want_close() {
+                            connection_manager.handle_close(state, poll, token)?;
+                            trace!(target:"handle_close", "did close connection");
+                        }
+                    }
+                }
+            }
+
+            connection_manager.serve_blocked(state, poll)?;
+            connection_manager.flush_monitors(state, poll)?;
+            connection_manager.reap_killed(state, poll)?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::{Read, Write},
+        net::TcpStream,
+    };
+
+    use super::*;
+    use crate::{
+        LengthPrefixed, Protocol,
+        protocol::request::{RES_NX, RES_OK},
+    };
+
+    fn encode(args: &[&str]) -> Vec<u8> {
+        let mut buf = (args.len() as u32).to_be_bytes().to_vec();
+        for arg in args {
+            buf.extend_from_slice(&LengthPrefixed::encode(&crate::LPFrame(arg.to_string())));
+        }
+        buf
+    }
+
+    /// Sends one request and reads back the status code and payload
+    fn request(stream: &mut TcpStream, args: &[&str]) -> (i32, Vec<u8>) {
+        stream.write_all(&encode(args)).unwrap();
+
+        let mut head = [0; 8];
+        stream.read_exact(&mut head).unwrap();
+        let len = u32::from_be_bytes(head[..4].try_into().unwrap()) as usize;
+        let status = i32::from_be_bytes(head[4..].try_into().unwrap());
+        let mut data = vec![0; len - 8];
+        stream.read_exact(&mut data).unwrap();
+        (status, data)
+    }
+
+    #[test]
+    fn instances_are_isolated() {
+        let start = || {
+            Server::builder()
+                .bind("127.0.0.1:0".parse().unwrap())
+                .databases(2)
+                .run()
+                .unwrap()
+        };
+        let (first, second) = (start(), start());
+        assert_ne!(first.addr(), second.addr());
+
+        let mut a = TcpStream::connect(first.addr().unwrap()).unwrap();
+        let mut b = TcpStream::connect(second.addr().unwrap()).unwrap();
+        assert_eq!(request(&mut a, &["set", "k", "v"]).0, RES_OK);
+        assert_eq!(request(&mut b, &["get", "k"]), (RES_NX, vec![]));
+        assert_eq!(request(&mut a, &["get", "k"]), (RES_OK, b"v".to_vec()));
+
+        second.stop().unwrap();
+        assert_eq!(request(&mut a, &["dbsize"]).0, RES_OK);
+        first.stop().unwrap();
+        assert!(TcpStream::connect(a.peer_addr().unwrap()).is_err());
+    }
+
+    #[test]
+    fn needs_a_database() {
+        let result = Server::builder()
+            .bind("127.0.0.1:0".parse().unwrap())
+            .databases(0)
+            .run();
+        assert_eq!(result.err().unwrap().kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn survives_connections_reset_by_the_peer() {
+        let server = Server::builder()
+            .bind("127.0.0.1:0".parse().unwrap())
+            .run()
+            .unwrap();
+        let addr = server.addr().unwrap();
+
+        // closing with replies left unread resets the connection,
+        // failing the server's next read or write on it
+        for _ in 0..10 {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream
+                .write_all(&encode(&["set", "k", "v"]).repeat(1000))
+                .unwrap();
+        }
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        assert_eq!(request(&mut stream, &["set", "k", "w"]).0, RES_OK);
+        assert_eq!(request(&mut stream, &["get", "k"]), (RES_OK, b"w".to_vec()));
+        server.stop().unwrap();
+    }
+}
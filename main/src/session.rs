@@ -0,0 +1,35 @@
+use crate::{net::PeerAddr, protocol::ReplyFormat};
+
+/// Per-connection state visible to command handlers
+#[derive(Debug, Clone)]
+pub struct Session {
+    /// Unique id of the client, as shown by `CLIENT LIST`
+    pub id: u64,
+    /// Address of the peer
+    pub addr: PeerAddr,
+    /// Whether this connection receives every processed command
+    pub monitor: bool,
+    /// Name of the authenticated user, `None` until `AUTH` succeeds
+    pub user: Option<String>,
+    /// Set while waiting in a blocking command, pipelined requests
+    /// are only processed once it is served or times out
+    pub blocked: bool,
+    /// Index of the database selected with `SELECT`
+    pub db: usize,
+    /// Encoding of the replies, changed with `HELLO`
+    pub format: ReplyFormat,
+}
+
+impl Session {
+    pub fn new(id: u64, addr: PeerAddr) -> Self {
+        Self {
+            id,
+            addr,
+            monitor: false,
+            user: None,
+            blocked: false,
+            db: 0,
+            format: ReplyFormat::Plain,
+        }
+    }
+}
@@ -0,0 +1,196 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{config::Config, net::PeerAddr, protocol::Reply};
+
+/// Max number of arguments kept per entry
+pub const MAX_ARGC: usize = 32;
+/// Max number of bytes kept per argument
+pub const MAX_ARGLEN: usize = 128;
+
+#[derive(Debug, Clone)]
+pub struct SlowlogEntry {
+    pub id: u64,
+    /// Unix time (in seconds) the command was processed at
+    pub timestamp: u64,
+    pub duration: Duration,
+    /// Arguments, truncated to `MAX_ARGC` items of `MAX_ARGLEN` bytes
+    pub args: Vec<String>,
+    pub client: PeerAddr,
+}
+
+/// Bounded ring buffer of commands that took longer than a threshold,
+/// newest first
+#[derive(Debug)]
+pub struct Slowlog {
+    entries: VecDeque<SlowlogEntry>,
+    next_id: u64,
+    /// Threshold in microseconds, negative values disable the log
+    slower_than: i64,
+    max_len: usize,
+}
+
+impl Slowlog {
+    pub fn from_config(config: &Config) -> Self {
+        Self::new(config.slowlog_log_slower_than, config.slowlog_max_len)
+    }
+
+    pub const fn new(slower_than: i64, max_len: usize) -> Self {
+        Self {
+            entries: VecDeque::new(),
+            next_id: 0,
+            slower_than,
+            max_len,
+        }
+    }
+
+    pub fn record(&mut self, cmd: &[String], duration: Duration, client: PeerAddr) {
+        if self.slower_than < 0 || duration.as_micros() < self.slower_than as u128 {
+            return;
+        }
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        self.entries.push_front(SlowlogEntry {
+            id: self.next_id,
+            timestamp,
+            duration,
+            args: truncate_args(cmd),
+            client,
+        });
+        self.next_id += 1;
+        self.entries.truncate(self.max_len);
+    }
+
+    /// Returns at most `count` entries, newest first
+    pub fn get(&self, count: usize) -> impl Iterator<Item = &SlowlogEntry> {
+        self.entries.iter().take(count)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn reset(&mut self) {
+        self.entries.clear();
+    }
+}
+
+fn truncate_args(cmd: &[String]) -> Vec<String> {
+    let mut args = Vec::with_capacity(cmd.len().min(MAX_ARGC));
+
+    for (i, arg) in cmd.iter().enumerate() {
+        if i == MAX_ARGC - 1 && cmd.len() > MAX_ARGC {
+            args.push(format!("... ({} more arguments)", cmd.len() - i));
+            break;
+        }
+
+        if arg.len() > MAX_ARGLEN {
+            let mut end = MAX_ARGLEN;
+            while !arg.is_char_boundary(end) {
+                end -= 1;
+            }
+            args.push(format!(
+                "{}... ({} more bytes)",
+                &arg[..end],
+                arg.len() - end
+            ));
+        } else {
+            args.push(arg.clone());
+        }
+    }
+
+    args
+}
+
+/// Handles `SLOWLOG GET [count] | LEN | RESET`
+pub fn command(slowlog: &mut Slowlog, args: &[String]) -> Reply {
+    match args {
+        [sub] if sub.eq_ignore_ascii_case("len") => Reply::Int(slowlog.len() as i64),
+        [sub] if sub.eq_ignore_ascii_case("reset") => {
+            slowlog.reset();
+            Reply::ok()
+        }
+        [sub, rest @ ..] if sub.eq_ignore_ascii_case("get") && rest.len() <= 1 => {
+            let count = match rest.first().map(|c| c.parse::<i64>()) {
+                None => 10,
+                Some(Ok(n)) if n < 0 => usize::MAX,
+                Some(Ok(n)) => n as usize,
+                Some(Err(_)) => return Reply::err("ERR value is not an integer or out of range"),
+            };
+
+            let entries = slowlog
+                .get(count)
+                .map(|e| {
+                    Reply::Arr(vec![
+                        Reply::Int(e.id as i64),
+                        Reply::Int(e.timestamp as i64),
+                        Reply::Int(e.duration.as_micros() as i64),
+                        Reply::Arr(e.args.iter().map(Reply::str).collect()),
+                        Reply::str(e.client.to_string()),
+                    ])
+                })
+                .collect();
+            Reply::Arr(entries)
+        }
+        _ => Reply::err("ERR unknown subcommand or wrong number of arguments for 'slowlog'"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn args(n: usize) -> Vec<String> {
+        (0..n).map(|i| format!("{i}")).collect()
+    }
+
+    #[test]
+    fn ring_buffer_is_bounded() {
+        let addr = PeerAddr::Tcp("127.0.0.1:1".parse().unwrap());
+        let mut log = Slowlog::new(0, 3);
+
+        for i in 0..5 {
+            log.record(&args(i + 1), Duration::from_millis(1), addr.clone());
+        }
+
+        assert_eq!(log.len(), 3);
+        let ids: Vec<u64> = log.get(usize::MAX).map(|e| e.id).collect();
+        assert_eq!(ids, [4, 3, 2]);
+    }
+
+    #[test]
+    fn threshold() {
+        let addr = PeerAddr::Tcp("127.0.0.1:1".parse().unwrap());
+        let mut log = Slowlog::new(100, 3);
+
+        log.record(&args(1), Duration::from_micros(99), addr.clone());
+        assert!(log.is_empty());
+        log.record(&args(1), Duration::from_micros(100), addr.clone());
+        assert_eq!(log.len(), 1);
+
+        let mut disabled = Slowlog::new(-1, 3);
+        disabled.record(&args(1), Duration::from_secs(1), addr);
+        assert!(disabled.is_empty());
+    }
+
+    #[test]
+    fn truncates_args() {
+        let truncated = truncate_args(&args(40));
+        assert_eq!(truncated.len(), MAX_ARGC);
+        assert_eq!(truncated[MAX_ARGC - 1], "... (9 more arguments)");
+
+        let long = "x".repeat(MAX_ARGLEN + 10);
+        let truncated = truncate_args(&[long]);
+        assert!(truncated[0].ends_with("... (10 more bytes)"));
+    }
+}
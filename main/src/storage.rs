@@ -1,11 +1,528 @@
-use std::{
-    collections::HashMap, hash::{BuildHasherDefault, DefaultHasher}, sync::Mutex
-};
+use collections::{Dict, Entry, FieldMap, HyperLogLog, QuickList, Set, SortedSet, Stream};
+use std::borrow::Cow;
+use std::ops::{Deref, DerefMut};
+use std::sync::LazyLock;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
-use collections::Dict;
-use std::sync::OnceLock;
+use crate::{blocking::WaitQueues, module::CustomValue};
 
-pub static MAP: Mutex<HashMap<String, String, BuildHasherDefault<DefaultHasher>>> =
-    Mutex::new(HashMap::with_hasher(BuildHasherDefault::new()));
+/// Number of databases unless configured otherwise
+pub const DEFAULT_DATABASES: usize = 16;
 
-pub static mut MAP2: OnceLock<Dict> = OnceLock::new();
+pub fn new_dbs(count: usize) -> Vec<Db> {
+    (0..count).map(Db::new).collect()
+}
+
+/// Rough cost of an entry besides its key and value:
+/// the entry itself plus the linked list node pointers
+const ENTRY_OVERHEAD: usize = size_of::<Entry<Object>>() + 2 * size_of::<usize>();
+
+/// Starting value of the LFU counter, so new keys aren't evicted right away
+const LFU_INIT_VAL: u8 = 5;
+/// How hard it is to increment the LFU counter, higher is harder
+const LFU_LOG_FACTOR: f64 = 10.0;
+/// Minutes without access it takes for the LFU counter to decay by one
+const LFU_DECAY_TIME: u16 = 1;
+
+static START: LazyLock<Instant> = LazyLock::new(Instant::now);
+
+/// Seconds since the server started, used as the LRU clock
+fn lru_clock() -> u32 {
+    START.elapsed().as_secs() as u32
+}
+
+/// Unix time in milliseconds, the clock key expiries use
+pub fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+/// Unix time in minutes, truncated to 16 bits, used as the LFU decay clock
+fn lfu_clock() -> u16 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    (now.as_secs() / 60) as u16
+}
+
+/// Longest string that can be the canonical form of an `i64`
+const MAX_INT_LEN: usize = 20;
+
+/// A value stored in the keyspace, strings have two encodings
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    /// Strings that are the canonical form of an integer, stored without an allocation
+    Int(i64),
+    Str(Vec<u8>),
+    List(QuickList),
+    Hash(FieldMap),
+    Set(Set),
+    SortedSet(SortedSet),
+    HyperLogLog(HyperLogLog),
+    Stream(Stream),
+    /// A type defined by a module
+    Module(CustomValue),
+}
+
+impl Value {
+    /// Name of the type as reported to clients
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Int(_) | Value::Str(_) => "string",
+            Value::List(_) => "list",
+            Value::Hash(_) => "hash",
+            Value::Set(_) => "set",
+            Value::SortedSet(_) => "zset",
+            Value::HyperLogLog(_) => "hyperloglog",
+            Value::Stream(_) => "stream",
+            Value::Module(v) => v.type_name(),
+        }
+    }
+
+    pub fn is_string(&self) -> bool {
+        matches!(self, Value::Int(_) | Value::Str(_))
+    }
+
+    /// Approximate number of heap bytes used by the value
+    pub fn mem_usage(&self) -> usize {
+        match self {
+            Value::Int(_) => 0,
+            Value::Str(s) => s.capacity(),
+            Value::List(l) => l.mem_usage(),
+            Value::Hash(h) => h.mem_usage(),
+            Value::Set(s) => s.mem_usage(),
+            Value::SortedSet(z) => z.mem_usage(),
+            Value::HyperLogLog(h) => h.mem_usage(),
+            Value::Stream(s) => s.mem_usage(),
+            Value::Module(v) => v.mem_usage(),
+        }
+    }
+
+    /// Borrows the bytes of a string value, only integers need formatting
+    pub fn as_bytes(&self) -> Option<Cow<'_, [u8]>> {
+        match self {
+            Value::Int(n) => Some(Cow::Owned(n.to_string().into_bytes())),
+            Value::Str(s) => Some(Cow::Borrowed(s)),
+            _ => None,
+        }
+    }
+
+    /// Returns the bytes of a string value
+    pub fn to_bytes(&self) -> Option<Vec<u8>> {
+        match self {
+            Value::Int(n) => Some(n.to_string().into_bytes()),
+            Value::Str(s) => Some(s.clone()),
+            _ => None,
+        }
+    }
+
+    /// Length of a string value
+    pub fn str_len(&self) -> Option<usize> {
+        match self {
+            Value::Int(n) => Some(n.to_string().len()),
+            Value::Str(s) => Some(s.len()),
+            _ => None,
+        }
+    }
+
+    /// Returns a string value as an integer, if it is one
+    pub fn as_int(&self) -> Option<i64> {
+        match self {
+            Value::Int(n) => Some(*n),
+            Value::Str(s) => std::str::from_utf8(s).ok()?.parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// Switches a string value to the raw encoding, so the bytes can be modified in place
+    pub fn make_raw(&mut self) -> Option<&mut Vec<u8>> {
+        if let Value::Int(n) = self {
+            *self = Value::Str(n.to_string().into_bytes());
+        }
+        match self {
+            Value::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&QuickList> {
+        match self {
+            Value::List(l) => Some(l),
+            _ => None,
+        }
+    }
+
+    pub fn as_list_mut(&mut self) -> Option<&mut QuickList> {
+        match self {
+            Value::List(l) => Some(l),
+            _ => None,
+        }
+    }
+
+    pub fn as_hash(&self) -> Option<&FieldMap> {
+        match self {
+            Value::Hash(h) => Some(h),
+            _ => None,
+        }
+    }
+
+    pub fn as_hash_mut(&mut self) -> Option<&mut FieldMap> {
+        match self {
+            Value::Hash(h) => Some(h),
+            _ => None,
+        }
+    }
+
+    pub fn as_set(&self) -> Option<&Set> {
+        match self {
+            Value::Set(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_set_mut(&mut self) -> Option<&mut Set> {
+        match self {
+            Value::Set(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_sorted_set(&self) -> Option<&SortedSet> {
+        match self {
+            Value::SortedSet(z) => Some(z),
+            _ => None,
+        }
+    }
+
+    pub fn as_sorted_set_mut(&mut self) -> Option<&mut SortedSet> {
+        match self {
+            Value::SortedSet(z) => Some(z),
+            _ => None,
+        }
+    }
+
+    pub fn as_hyperloglog(&self) -> Option<&HyperLogLog> {
+        match self {
+            Value::HyperLogLog(h) => Some(h),
+            _ => None,
+        }
+    }
+
+    pub fn as_hyperloglog_mut(&mut self) -> Option<&mut HyperLogLog> {
+        match self {
+            Value::HyperLogLog(h) => Some(h),
+            _ => None,
+        }
+    }
+
+    pub fn as_stream(&self) -> Option<&Stream> {
+        match self {
+            Value::Stream(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_stream_mut(&mut self) -> Option<&mut Stream> {
+        match self {
+            Value::Stream(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+impl From<i64> for Value {
+    fn from(n: i64) -> Self {
+        Value::Int(n)
+    }
+}
+
+impl From<Vec<u8>> for Value {
+    /// Picks the integer encoding if `s` round-trips through an `i64`
+    fn from(s: Vec<u8>) -> Self {
+        if s.len() <= MAX_INT_LEN
+            && let Ok(n) = std::str::from_utf8(&s).unwrap_or_default().parse::<i64>()
+            && n.to_string().as_bytes() == s
+        {
+            return Value::Int(n);
+        }
+        Value::Str(s)
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        s.into_bytes().into()
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        s.as_bytes().to_vec().into()
+    }
+}
+
+/// A value stored in the keyspace, with the metadata needed for eviction
+#[derive(Debug)]
+pub struct Object {
+    pub value: Value,
+    /// `lru_clock` at the last access
+    lru: u32,
+    /// Logarithmic access counter
+    lfu_counter: u8,
+    /// `lfu_clock` at the last access
+    lfu_time: u16,
+    /// Unix time in milliseconds the key expires at
+    pub expire_at: Option<u64>,
+}
+
+impl Object {
+    pub fn new(value: impl Into<Value>) -> Self {
+        Self {
+            value: value.into(),
+            lru: lru_clock(),
+            lfu_counter: LFU_INIT_VAL,
+            lfu_time: lfu_clock(),
+            expire_at: None,
+        }
+    }
+
+    /// Approximate number of heap bytes used by the value
+    pub fn mem_usage(&self) -> usize {
+        self.value.mem_usage()
+    }
+
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expire_at.is_some_and(|at| at <= now)
+    }
+
+    /// Seconds since the last access
+    pub fn idle_time(&self) -> u32 {
+        lru_clock().saturating_sub(self.lru)
+    }
+
+    /// The LFU counter, decayed by the time passed since the last access
+    pub fn lfu(&self) -> u8 {
+        let elapsed = lfu_clock().wrapping_sub(self.lfu_time);
+        let periods = elapsed / LFU_DECAY_TIME;
+        self.lfu_counter
+            .saturating_sub(periods.min(u8::MAX as u16) as u8)
+    }
+
+    /// Records an access for the LRU and LFU policies
+    fn touch(&mut self) {
+        let mut counter = self.lfu();
+        if counter < u8::MAX {
+            let base = counter.saturating_sub(LFU_INIT_VAL) as f64;
+            if rand::random::<f64>() < 1.0 / (base * LFU_LOG_FACTOR + 1.0) {
+                counter += 1;
+            }
+        }
+
+        self.lfu_counter = counter;
+        self.lfu_time = lfu_clock();
+        self.lru = lru_clock();
+    }
+}
+
+fn entry_size(key: &str, obj: &Object) -> usize {
+    key.len() + obj.mem_usage() + ENTRY_OVERHEAD
+}
+
+/// A numbered keyspace, keeping track of how much memory its entries use
+#[derive(Debug, Default)]
+pub struct Db {
+    dict: Dict<Object>,
+    used_memory: usize,
+    /// Number of the database, as given to `SELECT`
+    index: usize,
+    /// Clients blocked on keys of this database, they stay
+    /// when the keys get swapped or flushed
+    pub(crate) waiting: WaitQueues,
+    /// Keys written to since the last `take_modified`, `None` unless
+    /// somebody asked for them with `track_modified`
+    modified: Option<Vec<String>>,
+}
+
+impl Db {
+    pub fn new(index: usize) -> Self {
+        Self {
+            index,
+            ..Self::default()
+        }
+    }
+
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn dict(&self) -> &Dict<Object> {
+        &self.dict
+    }
+
+    pub fn size(&self) -> usize {
+        self.dict.size()
+    }
+
+    /// Approximate number of bytes used by the entries
+    pub fn used_memory(&self) -> usize {
+        self.used_memory
+    }
+
+    /// Looks up `key`, counting it as an access
+    pub fn get(&mut self, key: &str) -> Option<&Object> {
+        self.expire_if_needed(key);
+        let obj = self.dict.get_mut(key)?.value_mut();
+        obj.touch();
+        Some(obj)
+    }
+
+    /// Looks up `key` without counting it as an access
+    pub fn peek(&mut self, key: &str) -> Option<&Object> {
+        self.expire_if_needed(key);
+        self.dict.peek(key).map(Entry::value)
+    }
+
+    /// Looks up `key` for modification, counting it as an access.
+    /// The memory accounting is updated when the returned guard is dropped
+    pub fn get_mut(&mut self, key: &str) -> Option<ObjectMut<'_>> {
+        self.expire_if_needed(key);
+        let obj = self.dict.get_mut(key)?.value_mut();
+        obj.touch();
+        Some(ObjectMut {
+            before: obj.mem_usage(),
+            obj,
+            used_memory: &mut self.used_memory,
+            modified: self.modified.as_mut().map(|keys| (keys, key.to_string())),
+            written: false,
+        })
+    }
+
+    pub fn insert(&mut self, key: &str, obj: Object) -> Option<Object> {
+        self.expire_if_needed(key);
+        self.record_modified(key);
+        self.used_memory += entry_size(key, &obj);
+        let old = self.dict.insert(key, obj);
+        if let Some(old) = &old {
+            self.used_memory -= entry_size(key, old);
+        }
+        old
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<Object> {
+        let obj = self
+            .remove_entry(key)
+            .filter(|obj| !obj.is_expired(now_ms()))?;
+        self.record_modified(key);
+        Some(obj)
+    }
+
+    /// Starts recording the keys that get written to, see `take_modified`
+    pub fn track_modified(&mut self) {
+        self.modified.get_or_insert_default();
+    }
+
+    /// Takes the keys inserted, removed or changed through `get_mut`
+    /// since the last call, in the order they were written
+    pub fn take_modified(&mut self) -> Vec<String> {
+        self.modified
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    fn record_modified(&mut self, key: &str) {
+        if let Some(keys) = &mut self.modified {
+            record(keys, key);
+        }
+    }
+
+    fn remove_entry(&mut self, key: &str) -> Option<Object> {
+        let entry = self.dict.remove(key)?;
+        self.used_memory -= entry_size(entry.key(), entry.value());
+        Some(entry.into_value())
+    }
+
+    /// Picks a key uniformly at random, deleting the expired keys it comes across
+    pub fn random_key(&mut self) -> Option<String> {
+        let mut rng = rand::rng();
+        loop {
+            let entry = self.dict.random_entry(&mut rng)?;
+            if !entry.value().is_expired(now_ms()) {
+                return Some(entry.key().to_string());
+            }
+            let key = entry.key().to_string();
+            self.remove_entry(&key);
+        }
+    }
+
+    /// Deletes every key
+    pub fn clear(&mut self) {
+        self.dict = Dict::default();
+        self.used_memory = 0;
+    }
+
+    /// Swaps the keys of two databases, each keeping its own index
+    pub fn swap_keys(&mut self, other: &mut Db) {
+        std::mem::swap(&mut self.dict, &mut other.dict);
+        std::mem::swap(&mut self.used_memory, &mut other.used_memory);
+    }
+
+    /// Deletes `key` if it expired, keys are only expired lazily when accessed
+    fn expire_if_needed(&mut self, key: &str) {
+        let expired = self
+            .dict
+            .get(key)
+            .is_some_and(|e| e.value().is_expired(now_ms()));
+        if expired {
+            self.remove_entry(key);
+        }
+    }
+}
+
+/// Adds `key` to the modified keys, unless it was the last one
+fn record(keys: &mut Vec<String>, key: &str) {
+    if keys.last().is_none_or(|last| last != key) {
+        keys.push(key.to_string());
+    }
+}
+
+/// Mutable access to an `Object`, keeping `Db::used_memory` in sync
+/// and recording the key as modified once it is written through
+#[derive(Debug)]
+pub struct ObjectMut<'a> {
+    obj: &'a mut Object,
+    used_memory: &'a mut usize,
+    /// `mem_usage` of the object when it was borrowed
+    before: usize,
+    /// `Db::modified` and the key, when modified keys are tracked
+    modified: Option<(&'a mut Vec<String>, String)>,
+    written: bool,
+}
+
+impl Deref for ObjectMut<'_> {
+    type Target = Object;
+
+    fn deref(&self) -> &Self::Target {
+        self.obj
+    }
+}
+
+impl DerefMut for ObjectMut<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.written = true;
+        self.obj
+    }
+}
+
+impl Drop for ObjectMut<'_> {
+    fn drop(&mut self) {
+        *self.used_memory = *self.used_memory - self.before + self.obj.mem_usage();
+        if let Some((keys, key)) = &mut self.modified
+            && self.written
+        {
+            record(keys, key);
+        }
+    }
+}
@@ -6,3 +6,98 @@ pub fn would_block(e: &std::io::Error) -> bool {
 pub fn interrupted(e: &std::io::Error) -> bool {
     e.kind() == std::io::ErrorKind::Interrupted
 }
+
+/// Matches `s` against a glob style `pattern`, supporting
+/// `*`, `?`, `[abc]`, `[^abc]`, `[a-z]` and `\` escapes
+pub fn glob_match(pattern: &str, s: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let s: Vec<char> = s.chars().collect();
+    glob_match_chars(&p, &s)
+}
+
+fn glob_match_chars(p: &[char], s: &[char]) -> bool {
+    let (mut pi, mut si) = (0, 0);
+    // position of the last `*` in the pattern, and where in `s` it started matching
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while si < s.len() {
+        let matched = match p.get(pi) {
+            Some('*') => {
+                backtrack = Some((pi, si));
+                pi += 1;
+                continue;
+            }
+            Some('?') => Some(pi + 1),
+            Some('[') => match_class(p, pi, s[si]),
+            Some('\\') if pi + 1 < p.len() => (p[pi + 1] == s[si]).then_some(pi + 2),
+            Some(&c) => (c == s[si]).then_some(pi + 1),
+            None => None,
+        };
+
+        match (matched, backtrack) {
+            (Some(next), _) => {
+                pi = next;
+                si += 1;
+            }
+            (None, Some((star, start))) => {
+                pi = star + 1;
+                si = start + 1;
+                backtrack = Some((star, start + 1));
+            }
+            (None, None) => return false,
+        }
+    }
+
+    p[pi..].iter().all(|&c| c == '*')
+}
+
+/// Matches `c` against the `[...]` class starting at `p[start]`,
+/// returning the index right after the class on success
+fn match_class(p: &[char], start: usize, c: char) -> Option<usize> {
+    let mut i = start + 1;
+    let negate = p.get(i) == Some(&'^');
+    if negate {
+        i += 1;
+    }
+
+    let mut found = false;
+    while i < p.len() && p[i] != ']' {
+        if p[i] == '\\' && i + 1 < p.len() {
+            found |= p[i + 1] == c;
+            i += 2;
+        } else if i + 2 < p.len() && p[i + 1] == '-' && p[i + 2] != ']' {
+            let (lo, hi) = if p[i] <= p[i + 2] {
+                (p[i], p[i + 2])
+            } else {
+                (p[i + 2], p[i])
+            };
+            found |= (lo..=hi).contains(&c);
+            i += 3;
+        } else {
+            found |= p[i] == c;
+            i += 1;
+        }
+    }
+
+    // unterminated classes never match
+    (i < p.len() && found != negate).then_some(i + 1)
+}
+
+#[cfg(test)]
+mod test {
+    use super::glob_match;
+
+    #[test]
+    fn glob() {
+        assert!(glob_match("*", ""));
+        assert!(glob_match("user:*", "user:42"));
+        assert!(!glob_match("user:*", "session:42"));
+        assert!(glob_match("h?llo", "hello"));
+        assert!(glob_match("h[ae]llo", "hallo"));
+        assert!(!glob_match("h[^e]llo", "hello"));
+        assert!(glob_match("h[a-c]llo", "hbllo"));
+        assert!(glob_match("*:*:end", "a:b:c:end"));
+        assert!(glob_match("\\*", "*"));
+        assert!(!glob_match("\\*", "x"));
+    }
+}